@@ -1,7 +1,97 @@
 use heck::{
     ToKebabCase, ToLowerCamelCase, ToPascalCase, ToShoutySnakeCase, ToSnakeCase, ToTitleCase,
 };
-use unicode_ident::is_xid_continue;
+use unicode_ident::{is_xid_continue, is_xid_start};
+
+/// Reserved words that cannot be used as TypeScript identifiers
+/// (including those only reserved in strict mode, e.g. in ES modules)
+const RESERVED_WORDS: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+/// Turns `ident` into a legal TypeScript identifier
+///
+/// Characters that cannot appear in identifiers (e.g. punctuation, emoji or
+/// other non-ASCII symbols) are dropped, a leading digit is prefixed with `_`
+/// and reserved words get a trailing `_`. Legal identifiers are returned as is.
+pub fn legalize_identifier(ident: &str) -> String {
+    let mut legal: String = ident
+        .chars()
+        .filter(|c| *c == '$' || is_xid_continue(*c))
+        .collect();
+
+    match legal.chars().next() {
+        None => return "_".into(),
+        Some(first) if !(first == '_' || first == '$' || is_xid_start(first)) => {
+            legal.insert(0, '_');
+        }
+        Some(_) => {}
+    }
+    if RESERVED_WORDS.contains(&legal.as_str()) {
+        legal.push('_');
+    }
+
+    legal
+}
+
+/// Whether `ident` can be used as a TypeScript identifier
+pub fn is_valid_identifier(ident: &str) -> bool {
+    let mut chars = ident.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    (first == '_' || first == '$' || is_xid_start(first))
+        && chars.all(|c| c == '$' || is_xid_continue(c))
+        && !RESERVED_WORDS.contains(&ident)
+}
 
 #[derive(Debug)]
 pub enum Case {
@@ -47,7 +137,7 @@ impl Case {
 
 #[cfg(test)]
 mod test {
-    use super::Case;
+    use super::{Case, is_valid_identifier, legalize_identifier};
 
     #[test]
     fn test_trailing_underscore() {
@@ -55,6 +145,35 @@ mod test {
         assert_eq!(Case::Camel.sanitize(input), "ident_");
     }
 
+    #[test]
+    fn test_is_valid_identifier() {
+        assert!(is_valid_identifier("MyApi"));
+        assert!(is_valid_identifier("_private$"));
+        assert!(is_valid_identifier("café"));
+        assert!(!is_valid_identifier(""));
+        assert!(!is_valid_identifier("123abc"));
+        assert!(!is_valid_identifier("my-api"));
+        assert!(!is_valid_identifier("delete"));
+    }
+
+    #[test]
+    fn test_legalize_identifier() {
+        assert_eq!(legalize_identifier("getItem"), "getItem");
+        assert_eq!(legalize_identifier("delete"), "delete_");
+        assert_eq!(legalize_identifier("default"), "default_");
+        assert_eq!(legalize_identifier("eval"), "eval_");
+        assert_eq!(legalize_identifier("arguments"), "arguments_");
+        assert_eq!(legalize_identifier("123abc"), "_123abc");
+        assert_eq!(legalize_identifier("get.item™"), "getitem");
+        assert_eq!(legalize_identifier("🚀launch"), "launch");
+        assert_eq!(legalize_identifier("éclair"), "éclair");
+        assert_eq!(legalize_identifier("🚀"), "_");
+
+        for ident in ["delete", "123abc", "a-b", "🚀", ""] {
+            assert!(is_valid_identifier(&legalize_identifier(ident)));
+        }
+    }
+
     #[test]
     fn test_leading_underscore() {
         let input = "_ident";
@@ -1,5 +1,6 @@
 pub mod case;
 pub mod format;
+pub mod schema_diff;
 pub mod schema_type;
 pub mod typegen;
 pub mod utils;
@@ -0,0 +1,451 @@
+//! Classification of the changes between two versions of a JSON schema
+//!
+//! Changes are classified by whether they narrow the set of values a schema
+//! allows (e.g. a new required property or a removed enum value) or widen it.
+//! Narrowing an input schema breaks callers sending values that used to be
+//! valid, while widening an output schema breaks callers relying on what used
+//! to be guaranteed.
+
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+/// Validation keywords compared between schema versions, paired with whether
+/// a larger value narrows the schema
+static BOUND_KEYWORDS: &[(&str, bool)] = &[
+    ("minimum", true),
+    ("exclusiveMinimum", true),
+    ("minLength", true),
+    ("minItems", true),
+    ("minProperties", true),
+    ("maximum", false),
+    ("exclusiveMaximum", false),
+    ("maxLength", false),
+    ("maxItems", false),
+    ("maxProperties", false),
+];
+
+/// Validation keywords whose changes can't be ordered, treated as narrowing
+static OPAQUE_KEYWORDS: &[&str] = &["format", "pattern", "multipleOf", "uniqueItems"];
+
+/// Keywords combining sub-schemas, compared as a whole
+static COMPOSITION_KEYWORDS: &[&str] = &["anyOf", "oneOf", "allOf", "not"];
+
+/// Side of a tool call a schema describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaRole {
+    /// Arguments sent by callers, narrowing it breaks them
+    Input,
+    /// Results received by callers, widening it breaks them
+    Output,
+}
+
+/// Single difference between two versions of a schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaChange {
+    /// Location of the change, e.g. `input.filters.state`
+    pub path: String,
+    pub message: String,
+    /// Whether callers written against the old schema may fail with the new one
+    pub breaking: bool,
+}
+
+/// Lists the changes from `old` to `new`, with `role` deciding which of them
+/// break callers. Paths are rooted at `input` or `output` according to `role`.
+pub fn diff_schemas(old: &Value, new: &Value, role: SchemaRole) -> Vec<SchemaChange> {
+    let mut differ = SchemaDiffer {
+        old_root: old,
+        new_root: new,
+        same_definitions: ["$defs", "definitions"]
+            .iter()
+            .all(|k| old.get(k) == new.get(k)),
+        role,
+        visited: HashSet::new(),
+        changes: vec![],
+    };
+    let path = match role {
+        SchemaRole::Input => "input",
+        SchemaRole::Output => "output",
+    };
+    differ.compare(old, new, path);
+    differ.changes
+}
+
+struct SchemaDiffer<'a> {
+    old_root: &'a Value,
+    new_root: &'a Value,
+    /// Whether references resolve to the same schemas in both versions
+    same_definitions: bool,
+    role: SchemaRole,
+    /// Pairs of followed references, so recursive schemas terminate
+    visited: HashSet<(String, String)>,
+    changes: Vec<SchemaChange>,
+}
+
+impl<'a> SchemaDiffer<'a> {
+    fn compare(&mut self, old: &'a Value, new: &'a Value, path: &str) {
+        let (old, old_ref) = resolve(self.old_root, old);
+        let (new, new_ref) = resolve(self.new_root, new);
+        if (old_ref.is_some() || new_ref.is_some())
+            && !self
+                .visited
+                .insert((old_ref.unwrap_or_default(), new_ref.unwrap_or_default()))
+        {
+            return;
+        }
+        if old == new && self.same_definitions {
+            return;
+        }
+
+        self.compare_types(old, new, path);
+        self.compare_enums(old, new, path);
+        self.compare_bounds(old, new, path);
+        self.compare_properties(old, new, path);
+
+        if let (Some(old_items), Some(new_items)) = (old.get("items"), new.get("items")) {
+            self.compare(old_items, new_items, &format!("{path}[]"));
+        }
+        if let (Some(old_values @ Value::Object(_)), Some(new_values @ Value::Object(_))) = (
+            old.get("additionalProperties"),
+            new.get("additionalProperties"),
+        ) {
+            self.compare(old_values, new_values, &format!("{path}.*"));
+        }
+
+        for keyword in COMPOSITION_KEYWORDS {
+            if old.get(keyword) != new.get(keyword) {
+                // sub-schema matching is ambiguous, assume the worst
+                self.push_breaking(path, format!("`{keyword}` schemas changed"));
+            }
+        }
+    }
+
+    fn compare_types(&mut self, old: &Value, new: &Value, path: &str) {
+        match (types(old), types(new)) {
+            (Some(old_types), Some(new_types)) => {
+                for t in sorted(old_types.difference(&new_types)) {
+                    self.push(path, format!("no longer allows `{t}`"), true);
+                }
+                for t in sorted(new_types.difference(&old_types)) {
+                    self.push(path, format!("now allows `{t}`"), false);
+                }
+            }
+            (Some(_), None) => self.push(path, "is no longer restricted to a type".into(), false),
+            (None, Some(new_types)) => self.push(
+                path,
+                format!(
+                    "is now restricted to {}",
+                    sorted(new_types.iter())
+                        .iter()
+                        .map(|t| format!("`{t}`"))
+                        .collect::<Vec<_>>()
+                        .join(" | ")
+                ),
+                true,
+            ),
+            (None, None) => {}
+        }
+    }
+
+    fn compare_enums(&mut self, old: &Value, new: &Value, path: &str) {
+        match (enum_values(old), enum_values(new)) {
+            (Some(old_values), Some(new_values)) => {
+                for v in old_values.iter().filter(|v| !new_values.contains(v)) {
+                    self.push(path, format!("value {v} removed from enum"), true);
+                }
+                for v in new_values.iter().filter(|v| !old_values.contains(v)) {
+                    self.push(path, format!("value {v} added to enum"), false);
+                }
+            }
+            (Some(_), None) => self.push(path, "is no longer an enum".into(), false),
+            (None, Some(_)) => self.push(path, "is now an enum".into(), true),
+            (None, None) => {}
+        }
+    }
+
+    fn compare_bounds(&mut self, old: &Value, new: &Value, path: &str) {
+        for (keyword, larger_narrows) in BOUND_KEYWORDS {
+            match (
+                old.get(keyword).and_then(Value::as_f64),
+                new.get(keyword).and_then(Value::as_f64),
+            ) {
+                (Some(o), Some(n)) if (o - n).abs() > f64::EPSILON => self.push(
+                    path,
+                    format!("`{keyword}` changed from {o} to {n}"),
+                    (n > o) == *larger_narrows,
+                ),
+                (Some(o), None) => self.push(path, format!("`{keyword}` {o} removed"), false),
+                (None, Some(n)) => self.push(path, format!("`{keyword}` {n} added"), true),
+                _ => {}
+            }
+        }
+        for keyword in OPAQUE_KEYWORDS {
+            match (old.get(keyword), new.get(keyword)) {
+                (Some(o), Some(n)) if o != n => {
+                    self.push(path, format!("`{keyword}` changed from {o} to {n}"), true);
+                }
+                (Some(o), None) => self.push(path, format!("`{keyword}` {o} removed"), false),
+                (None, Some(n)) => self.push(path, format!("`{keyword}` {n} added"), true),
+                _ => {}
+            }
+        }
+    }
+
+    fn compare_properties(&mut self, old: &'a Value, new: &'a Value, path: &str) {
+        let old_props = properties(old);
+        let new_props = properties(new);
+        let old_required = required(old);
+        let new_required = required(new);
+
+        for (name, old_prop) in old_props.into_iter().flatten() {
+            let prop_path = format!("{path}.{name}");
+            let Some(new_prop) = new_props.and_then(|p| p.get(name)) else {
+                // callers either send it or read it, both break
+                self.push_breaking(&prop_path, "property removed".into());
+                continue;
+            };
+            self.compare(old_prop, new_prop, &prop_path);
+
+            match (old_required.contains(name), new_required.contains(name)) {
+                (false, true) => self.push(&prop_path, "is now required".into(), true),
+                (true, false) => self.push(&prop_path, "is now optional".into(), false),
+                _ => {}
+            }
+        }
+
+        for name in new_props
+            .into_iter()
+            .flat_map(Map::keys)
+            .filter(|n| !old_props.is_some_and(|p| p.contains_key(*n)))
+        {
+            let prop_path = format!("{path}.{name}");
+            if new_required.contains(name) {
+                self.push(&prop_path, "new required property".into(), true);
+            } else {
+                // extra properties were never ruled out, so nothing changes for callers
+                self.record(&prop_path, "new optional property".into(), false);
+            }
+        }
+    }
+
+    /// Records a change that narrows (or widens) the allowed values
+    fn push(&mut self, path: &str, message: String, narrows: bool) {
+        let breaking = match self.role {
+            SchemaRole::Input => narrows,
+            SchemaRole::Output => !narrows,
+        };
+        self.record(path, message, breaking);
+    }
+
+    fn push_breaking(&mut self, path: &str, message: String) {
+        self.record(path, message, true);
+    }
+
+    fn record(&mut self, path: &str, message: String, breaking: bool) {
+        self.changes.push(SchemaChange {
+            path: path.into(),
+            message,
+            breaking,
+        });
+    }
+}
+
+/// Follows a local `$ref` (e.g. `#/$defs/Item`), returning the target and reference
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> (&'a Value, Option<String>) {
+    match schema.get("$ref").and_then(Value::as_str) {
+        Some(reference) => match reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+        {
+            Some(target) => (target, Some(reference.to_string())),
+            None => (schema, Some(reference.to_string())),
+        },
+        None => (schema, None),
+    }
+}
+
+/// Types allowed by `schema`, `None` if it doesn't restrict them
+fn types(schema: &Value) -> Option<HashSet<String>> {
+    let mut types: HashSet<String> = match schema.get("type")? {
+        Value::String(t) => HashSet::from([t.clone()]),
+        Value::Array(ts) => ts
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        _ => return None,
+    };
+    if schema.get("nullable").and_then(Value::as_bool) == Some(true) {
+        types.insert("null".into());
+    }
+    Some(types)
+}
+
+/// Values allowed by an `enum` or `const` schema
+fn enum_values(schema: &Value) -> Option<Vec<Value>> {
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        return Some(values.clone());
+    }
+    schema.get("const").map(|c| vec![c.clone()])
+}
+
+fn properties(schema: &Value) -> Option<&Map<String, Value>> {
+    schema.get("properties").and_then(Value::as_object)
+}
+
+fn required(schema: &Value) -> HashSet<String> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|r| {
+            r.iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn sorted<'s>(values: impl Iterator<Item = &'s String>) -> Vec<&'s String> {
+    let mut values: Vec<&String> = values.collect();
+    values.sort();
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Sorted `path: message` of the breaking (or compatible) changes
+    fn listed(changes: &[SchemaChange], breaking: bool) -> Vec<String> {
+        let mut listed: Vec<String> = changes
+            .iter()
+            .filter(|c| c.breaking == breaking)
+            .map(|c| format!("{}: {}", c.path, c.message))
+            .collect();
+        listed.sort();
+        listed
+    }
+
+    #[test]
+    fn test_unchanged_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "id": { "type": "string" } },
+            "required": ["id"]
+        });
+        assert!(diff_schemas(&schema, &schema, SchemaRole::Input).is_empty());
+    }
+
+    #[test]
+    fn test_input_changes() {
+        let old = json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "state": { "type": "string", "enum": ["open", "closed", "all"] },
+                "legacy": { "type": "boolean" },
+                "page": { "type": "integer" }
+            },
+            "required": ["id", "page"]
+        });
+        let new = json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "state": { "type": "string", "enum": ["open", "closed"] },
+                "page": { "type": "integer" },
+                "owner": { "type": "string" },
+                "per_page": { "type": "integer", "maximum": 100 }
+            },
+            "required": ["id", "owner"]
+        });
+
+        let changes = diff_schemas(&old, &new, SchemaRole::Input);
+        assert_eq!(
+            listed(&changes, true),
+            vec![
+                "input.legacy: property removed",
+                "input.owner: new required property",
+                "input.state: value \"all\" removed from enum",
+            ]
+        );
+        assert_eq!(
+            listed(&changes, false),
+            vec![
+                "input.page: is now optional",
+                "input.per_page: new optional property",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_changes() {
+        let old = json!({
+            "type": "object",
+            "properties": {
+                "count": { "type": "integer" },
+                "items": { "type": "array", "items": { "$ref": "#/$defs/Item" } }
+            },
+            "required": ["count", "items"],
+            "$defs": {
+                "Item": {
+                    "type": "object",
+                    "properties": { "name": { "type": "string" } }
+                }
+            }
+        });
+        let new = json!({
+            "type": "object",
+            "properties": {
+                "count": { "type": ["integer", "null"] },
+                "items": { "type": "array", "items": { "$ref": "#/$defs/Item" } },
+                "next": { "type": "string" }
+            },
+            "required": ["items"],
+            "$defs": {
+                "Item": {
+                    "type": "object",
+                    "properties": { "title": { "type": "string" } }
+                }
+            }
+        });
+
+        let changes = diff_schemas(&old, &new, SchemaRole::Output);
+        assert_eq!(
+            listed(&changes, true),
+            vec![
+                "output.count: is now optional",
+                "output.count: now allows `null`",
+                "output.items[].name: property removed",
+            ]
+        );
+        assert_eq!(
+            listed(&changes, false),
+            vec![
+                "output.items[].title: new optional property",
+                "output.next: new optional property",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recursive_schema_terminates() {
+        let schema = |desc: &str| {
+            json!({
+                "$ref": "#/$defs/Node",
+                "$defs": {
+                    "Node": {
+                        "type": "object",
+                        "description": desc,
+                        "properties": {
+                            "children": { "type": "array", "items": { "$ref": "#/$defs/Node" } }
+                        }
+                    }
+                }
+            })
+        };
+        let changes = diff_schemas(&schema("old"), &schema("new"), SchemaRole::Input);
+        assert!(changes.is_empty(), "{changes:?}");
+    }
+}
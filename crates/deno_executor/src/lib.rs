@@ -3,16 +3,32 @@ use deno_runtime::deno_core::JsRuntime;
 use deno_runtime::deno_core::ModuleCodeString;
 use deno_runtime::deno_core::RuntimeOptions;
 use deno_runtime::deno_core::error::AnyError;
+pub use pctx_code_execution_runtime::{
+    MCPConnectionManager, NetworkConfig, ServerConfig, ToolProgress,
+};
 pub use pctx_type_check_runtime::{CheckResult, Diagnostic, is_relevant_error, type_check};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 use thiserror::Error;
+use tokio::sync::{Semaphore, mpsc, oneshot};
 
 pub type Result<T> = std::result::Result<T, DenoExecutorError>;
 
+/// Diagnostic codes only ignored in sessions
+const SESSION_IGNORED_CODES: &[u32] = &[
+    7017, // Element implicitly has an 'any' type - values stored on globalThis by earlier executions
+];
+
 /// Filter diagnostics to only include errors relevant to runtime execution
-fn filter_relevant_diagnostics(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
-    diagnostics.into_iter().filter(is_relevant_error).collect()
+fn filter_relevant_diagnostics(diagnostics: Vec<Diagnostic>, session: bool) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .filter(is_relevant_error)
+        .filter(|d| !(session && d.code.is_some_and(|c| SESSION_IGNORED_CODES.contains(&c))))
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +51,23 @@ pub struct ExecuteResult {
     pub stderr: String,
 }
 
+/// Options for a single execution
+#[derive(Clone, Default)]
+pub struct ExecuteOptions {
+    /// Hosts that network requests are allowed to access.
+    /// Format: "hostname:port" or just "hostname" (e.g., "localhost:3000", "api.example.com").
+    /// If None or empty, all network access is denied.
+    pub allowed_hosts: Option<Vec<String>>,
+
+    /// Shared upstream MCP connection pool. If None, connections are pooled for
+    /// the duration of this execution only.
+    pub connections: Option<MCPConnectionManager>,
+
+    /// Proxy and TLS settings of network requests. If None, requests connect
+    /// directly and only trust the system's root certificates.
+    pub network: Option<NetworkConfig>,
+}
+
 #[derive(Debug, Error)]
 pub enum DenoExecutorError {
     #[error("Internal check error: {0}")]
@@ -67,9 +100,208 @@ pub enum DenoExecutorError {
 /// * Returns error only if internal tooling fails (not for type errors or runtime errors)
 ///
 pub async fn execute(code: &str, allowed_hosts: Option<Vec<String>>) -> Result<ExecuteResult> {
+    execute_with_options(
+        code,
+        ExecuteOptions {
+            allowed_hosts,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Execute TypeScript code with type checking and runtime execution using
+/// the provided [`ExecuteOptions`]
+///
+/// See [`execute`] for details.
+///
+/// # Errors
+/// * Returns error only if internal tooling fails (not for type errors or runtime errors)
+pub async fn execute_with_options(code: &str, options: ExecuteOptions) -> Result<ExecuteResult> {
+    execute_in(code, options, &mut None, false).await
+}
+
+/// Deno runtime kept alive across executions, so the top-level declarations
+/// of one execution (and values it stores on `globalThis`) are visible to the next
+///
+/// Each execution is still evaluated as its own module: its top-level
+/// variables, functions and classes are defined as accessors of `globalThis`,
+/// which later executions reach unless they declare the same names again.
+///
+/// The runtime lives on a dedicated thread, started by the first execution.
+/// Once no execution ran for `idle_timeout` (or every clone of the session is
+/// dropped) the runtime is discarded and the thread exits, the next execution
+/// then starts from a fresh runtime on a new thread.
+#[derive(Clone)]
+pub struct Session {
+    jobs: Arc<Mutex<Option<mpsc::UnboundedSender<SessionJob>>>>,
+    idle_timeout: Duration,
+    limit: Option<SessionLimit>,
+}
+
+struct SessionJob {
+    code: String,
+    options: ExecuteOptions,
+    reply: oneshot::Sender<Result<ExecuteResult>>,
+}
+
+/// Maximum number of sessions running a runtime at the same time, shared by
+/// the sessions it limits (see [`Session::limited_by`])
+#[derive(Clone)]
+pub struct SessionLimit {
+    threads: Arc<Semaphore>,
+    max: usize,
+}
+
+impl SessionLimit {
+    pub fn new(max: usize) -> Self {
+        Self {
+            threads: Arc::new(Semaphore::new(max)),
+            max,
+        }
+    }
+
+    /// Number of limited sessions whose thread is running
+    pub fn active(&self) -> usize {
+        self.max - self.threads.available_permits()
+    }
+}
+
+impl Session {
+    /// Session whose runtime is discarded after `idle_timeout` without executions
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            jobs: Arc::default(),
+            idle_timeout,
+            limit: None,
+        }
+    }
+
+    /// Counts the session against `limit` while its thread runs, executions
+    /// needing a new thread fail while the limit is reached
+    #[must_use]
+    pub fn limited_by(mut self, limit: &SessionLimit) -> Self {
+        self.limit = Some(limit.clone());
+        self
+    }
+
+    /// Execute TypeScript code in the session's runtime, like [`execute_with_options`]
+    ///
+    /// # Errors
+    /// * Returns error if internal tooling fails, the session's thread cannot
+    ///   be started or its [`SessionLimit`] is reached
+    pub async fn execute(&self, code: &str, options: ExecuteOptions) -> Result<ExecuteResult> {
+        let (reply, result) = oneshot::channel();
+        self.send(SessionJob {
+            code: code.into(),
+            options,
+            reply,
+        })?;
+        result
+            .await
+            .map_err(|_| DenoExecutorError::InternalError("Session was closed".into()))?
+    }
+
+    /// Queues `job` on the session's thread, starting a new thread if the
+    /// previous one exited
+    fn send(&self, job: SessionJob) -> Result<()> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        let job = match jobs.as_ref() {
+            Some(queue) => match queue.send(job) {
+                Ok(()) => return Ok(()),
+                // the thread stopped taking jobs after idling
+                Err(mpsc::error::SendError(job)) => job,
+            },
+            None => job,
+        };
+
+        let queue = self.spawn()?;
+        queue
+            .send(job)
+            .map_err(|_| DenoExecutorError::InternalError("Session was closed".into()))?;
+        *jobs = Some(queue);
+        Ok(())
+    }
+
+    /// Starts the session's thread, running queued jobs until it idles for
+    /// `idle_timeout` or every clone of the session is dropped
+    fn spawn(&self) -> Result<mpsc::UnboundedSender<SessionJob>> {
+        let permit = match &self.limit {
+            Some(limit) => Some(limit.threads.clone().try_acquire_owned().map_err(|_| {
+                DenoExecutorError::InternalError(format!(
+                    "Too many active sessions (at most {}), try again once another session is idle",
+                    limit.max
+                ))
+            })?),
+            None => None,
+        };
+        let (jobs, mut queue) = mpsc::unbounded_channel::<SessionJob>();
+        let idle_timeout = self.idle_timeout;
+
+        std::thread::Builder::new()
+            .name("pctx-session".into())
+            .spawn(move || {
+                // released once the thread exits
+                let _permit = permit;
+
+                // Deno ops rely on a current-thread runtime
+                let rt = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(rt) => rt,
+                    Err(e) => {
+                        queue.close();
+                        while let Ok(job) = queue.try_recv() {
+                            let _ = job.reply.send(Err(DenoExecutorError::InternalError(format!(
+                                "Failed to create session runtime: {e}"
+                            ))));
+                        }
+                        return;
+                    }
+                };
+
+                rt.block_on(async move {
+                    let mut isolate = None;
+                    loop {
+                        let job = match tokio::time::timeout(idle_timeout, queue.recv()).await {
+                            Ok(Some(job)) => job,
+                            // every clone of the session is gone, or the jobs sent
+                            // before it idled ran
+                            Ok(None) => break,
+                            Err(_) => {
+                                // idle for too long, run the jobs sent meanwhile and
+                                // exit, later executions start a new thread
+                                queue.close();
+                                continue;
+                            }
+                        };
+
+                        let result = execute_in(&job.code, job.options, &mut isolate, true).await;
+                        let _ = job.reply.send(result);
+                    }
+                });
+            })
+            .map_err(|e| {
+                DenoExecutorError::InternalError(format!("Failed to spawn session thread: {e}"))
+            })?;
+
+        Ok(jobs)
+    }
+}
+
+/// Type check and execute `code`, reusing `isolate` if there is one or
+/// leaving the runtime created for this execution in it, `session` keeps its
+/// top-level declarations for the next executions
+async fn execute_in(
+    code: &str,
+    options: ExecuteOptions,
+    isolate: &mut Option<Isolate>,
+    session: bool,
+) -> Result<ExecuteResult> {
     let check_result = type_check(code).await?;
 
-    let relevant_diagnostics = filter_relevant_diagnostics(check_result.diagnostics);
+    let relevant_diagnostics = filter_relevant_diagnostics(check_result.diagnostics, session);
 
     if !relevant_diagnostics.is_empty() {
         // Format diagnostics as stderr output
@@ -89,7 +321,7 @@ pub async fn execute(code: &str, allowed_hosts: Option<Vec<String>>) -> Result<E
         });
     }
 
-    let exec_result = execute_code(code, allowed_hosts)
+    let exec_result = execute_code(code, options, isolate, session)
         .await
         .map_err(|e| DenoExecutorError::InternalError(e.to_string()))?;
 
@@ -129,6 +361,75 @@ struct InternalExecuteResult {
     pub stderr: String,
 }
 
+/// Runtime executions run in, with the number of modules it evaluated
+struct Isolate {
+    runtime: JsRuntime,
+    executions: usize,
+}
+
+impl Isolate {
+    /// Create a `JsRuntime` from the `pctx_runtime` snapshot and extension
+    fn new(options: ExecuteOptions) -> Self {
+        let (registry, connections, allowed_hosts, network) = runtime_state(options);
+
+        // The snapshot contains the ESM code pre-compiled, and init() registers both ops and ESM
+        // Deno handles the deduplication when loading from snapshot
+        let runtime = JsRuntime::new(RuntimeOptions {
+            module_loader: Some(Rc::new(deno_core::FsModuleLoader)),
+            startup_snapshot: Some(pctx_code_execution_runtime::RUNTIME_SNAPSHOT),
+            extensions: vec![pctx_code_execution_runtime::pctx_runtime_snapshot::init(
+                registry,
+                connections,
+                allowed_hosts,
+                network,
+            )],
+            ..Default::default()
+        });
+
+        Self {
+            runtime,
+            executions: 0,
+        }
+    }
+
+    /// Prepare a reused runtime for the next execution: servers registered by
+    /// the previous one are forgotten, the options replaced and console output cleared
+    fn reset(&mut self, options: ExecuteOptions) -> std::result::Result<(), AnyError> {
+        let (registry, connections, allowed_hosts, network) = runtime_state(options);
+        {
+            let state = self.runtime.op_state();
+            let mut state = state.borrow_mut();
+            state.put(registry);
+            state.put(connections);
+            state.put(allowed_hosts);
+            state.put(network);
+        }
+
+        self.runtime.execute_script(
+            "<reset_output>",
+            "globalThis.__stdout = []; globalThis.__stderr = [];",
+        )?;
+        Ok(())
+    }
+}
+
+/// Extension state of an execution with `options`
+fn runtime_state(
+    options: ExecuteOptions,
+) -> (
+    pctx_code_execution_runtime::MCPRegistry,
+    MCPConnectionManager,
+    pctx_code_execution_runtime::AllowedHosts,
+    NetworkConfig,
+) {
+    (
+        pctx_code_execution_runtime::MCPRegistry::new(),
+        options.connections.unwrap_or_default(),
+        pctx_code_execution_runtime::AllowedHosts::new(options.allowed_hosts),
+        options.network.unwrap_or_default(),
+    )
+}
+
 /// Execute TypeScript/JavaScript code with `pctx_runtime`
 ///
 /// This function executes code in an isolated Deno runtime with MCP client functionality built-in.
@@ -136,7 +437,9 @@ struct InternalExecuteResult {
 ///
 /// # Arguments
 /// * `code` - The TypeScript/JavaScript code to execute
-/// * `allowed_hosts` - Optional list of hosts that network requests are allowed to access
+/// * `options` - Allowed hosts and the upstream MCP connection pool to use
+/// * `isolate` - Runtime to execute in, created if there is none
+/// * `session` - Whether to define the top-level declarations on `globalThis`
 ///
 /// # Returns
 /// * `Ok(ExecuteResult)` - Contains execution result or error information
@@ -145,10 +448,17 @@ struct InternalExecuteResult {
 /// * Returns error only if internal Deno runtime initialization fails
 async fn execute_code(
     code: &str,
-    allowed_hosts: Option<Vec<String>>,
+    options: ExecuteOptions,
+    isolate: &mut Option<Isolate>,
+    session: bool,
 ) -> std::result::Result<InternalExecuteResult, AnyError> {
     // Transpile TypeScript to JavaScript
     let js_code = match deno_transpiler::transpile(code, None) {
+        Ok(js) if session => match deno_transpiler::top_level_bindings(&js) {
+            // on the first line, so reported line numbers stay the same
+            Ok(bindings) => format!("{}{js}", session_globals(&bindings)),
+            Err(_) => js,
+        },
         Ok(js) => js,
         Err(e) => {
             return Ok(InternalExecuteResult {
@@ -164,25 +474,22 @@ async fn execute_code(
         }
     };
 
-    // Create MCP registry and allowed hosts for this execution
-    let mcp_registry = pctx_code_execution_runtime::MCPRegistry::new();
-    let allowed_hosts = pctx_code_execution_runtime::AllowedHosts::new(allowed_hosts);
-
-    // Create JsRuntime from `pctx_runtime` snapshot and extension
-    // The snapshot contains the ESM code pre-compiled, and init() registers both ops and ESM
-    // Deno handles the deduplication when loading from snapshot
-    let mut js_runtime = JsRuntime::new(RuntimeOptions {
-        module_loader: Some(Rc::new(deno_core::FsModuleLoader)),
-        startup_snapshot: Some(pctx_code_execution_runtime::RUNTIME_SNAPSHOT),
-        extensions: vec![pctx_code_execution_runtime::pctx_runtime_snapshot::init(
-            mcp_registry,
-            allowed_hosts,
-        )],
-        ..Default::default()
-    });
+    let isolate = match isolate {
+        Some(isolate) => {
+            isolate.reset(options)?;
+            isolate
+        }
+        None => isolate.insert(Isolate::new(options)),
+    };
 
-    // Create the main module specifier
-    let main_module = deno_core::resolve_url("file:///execute.js")?;
+    // every module evaluated by a runtime needs its own specifier
+    let main_module = if isolate.executions == 0 {
+        deno_core::resolve_url("file:///execute.js")?
+    } else {
+        deno_core::resolve_url(&format!("file:///execute-{}.js", isolate.executions))?
+    };
+    isolate.executions += 1;
+    let js_runtime = &mut isolate.runtime;
 
     // Load and evaluate the transpiled code as a module
     let mod_id = match js_runtime
@@ -250,7 +557,7 @@ async fn execute_code(
 
     // Extract console output and module exports using scope
     let (stdout, stderr, output) = {
-        deno_core::scope!(scope, &mut js_runtime);
+        deno_core::scope!(scope, js_runtime);
 
         let console_output = console_global.and_then(|global| {
             let local = deno_core::v8::Local::new(scope, global);
@@ -318,6 +625,31 @@ async fn execute_code(
     })
 }
 
+/// JavaScript defining each of `bindings` as an accessor of `globalThis`,
+/// so later executions of a session reach the module's top-level declarations
+///
+/// Accessors read (and, unless `const`, assign) the module's own bindings, so
+/// they stay live. Globals that can't be redefined are skipped.
+fn session_globals(bindings: &[deno_transpiler::TopLevelBinding]) -> String {
+    let accessors: Vec<String> = bindings
+        .iter()
+        .map(|b| {
+            let (key, name) = (json!(b.name), &b.name);
+            if b.constant {
+                format!("[{key}, () => {name}]")
+            } else {
+                format!("[{key}, () => {name}, (v) => {{ {name} = v; }}]")
+            }
+        })
+        .collect();
+
+    format!(
+        "try {{ for (const __pctx of [{}]) {{ try {{ Object.defineProperty(globalThis, __pctx[0], \
+        {{ get: __pctx[1], set: __pctx[2], configurable: true, enumerable: true }}); }} catch {{}} }} }} catch {{}} ",
+        accessors.join(", ")
+    )
+}
+
 pub fn version() -> &'static str {
     env!("CARGO_PKG_VERSION")
 }
@@ -1,5 +1,5 @@
 use super::serial;
-use crate::execute;
+use crate::{ExecuteOptions, MCPConnectionManager, ServerConfig, execute, execute_with_options};
 use serde_json::json;
 
 #[serial]
@@ -223,6 +223,75 @@ export default { hasBefore, hasAfter };
     );
 }
 
+#[serial]
+#[tokio::test]
+async fn test_execute_with_mcp_client_register_unsandboxed_rejected() {
+    let code = r#"
+
+const errors = [];
+try {
+    registerMCP({ name: "shell", command: "sh", args: ["-c", "echo escaped"] } as any);
+} catch (e) {
+    errors.push("stdio");
+}
+try {
+    registerMCP({ name: "proxied", url: "http://localhost:3000", network: { proxy: "http://localhost:8080" } } as any);
+} catch (e) {
+    errors.push("network");
+}
+
+export default { errors, registered: REGISTRY.has("shell") || REGISTRY.has("proxied") };
+"#;
+
+    let result = execute(code, None).await.expect("execution should succeed");
+    assert!(result.success, "Execution should succeed: {result:?}");
+    assert_eq!(
+        result.output,
+        Some(json!({ "errors": ["stdio", "network"], "registered": false })),
+        "Stdio servers and network overrides should not be registrable from the sandbox"
+    );
+}
+
+#[serial]
+#[tokio::test]
+async fn test_execute_with_mcp_client_configured_server_is_protected() {
+    let code = r#"
+
+const errors = [];
+try {
+    REGISTRY.delete("configured-server");
+} catch (e) {
+    errors.push("delete");
+}
+try {
+    registerMCP({ name: "configured-server", url: "http://localhost:3001" });
+} catch (e) {
+    errors.push("register");
+}
+
+export default errors;
+"#;
+
+    let server = ServerConfig::new(
+        "configured-server".into(),
+        "http://localhost:3000".parse().unwrap(),
+    );
+    let options = ExecuteOptions {
+        connections: Some(MCPConnectionManager::new().configured(vec![server])),
+        ..Default::default()
+    };
+    let result = execute_with_options(code, options)
+        .await
+        .expect("execution should succeed");
+    assert!(result.success, "Execution should succeed: {result:?}");
+
+    assert_eq!(
+        result.output,
+        Some(json!(["delete", "register"])),
+        "Configured servers should be neither deletable nor re-registrable"
+    );
+}
+
 #[serial]
 #[tokio::test]
 async fn test_execute_with_mcp_client_delete_nonexistent() {
@@ -289,3 +358,50 @@ export default await test();
         "Error message should mention nonexistent server, got: {message}"
     );
 }
+
+#[serial]
+#[tokio::test]
+async fn test_execute_with_mcp_client_call_tool_error_is_catchable() {
+    let code = r#"
+
+registerMCP({ name: "broken", url: "http://127.0.0.1:1/mcp" });
+
+async function test() {
+    try {
+        await callMCPTool({ name: "broken", tool: "some-tool" });
+        return { caught: false };
+    } catch (e) {
+        if (!(e instanceof MCPToolError)) {
+            throw e;
+        }
+        return {
+            caught: true,
+            server: e.server,
+            tool: e.tool,
+            isTransportError: e.isTransportError,
+            content: e.content,
+        };
+    }
+}
+
+export default await test();
+"#;
+
+    let result = execute(code, None).await.expect("execution should succeed");
+    assert!(
+        result.runtime_error.is_none(),
+        "Should have no runtime errors (error was caught), got: {:?}",
+        result.runtime_error
+    );
+    assert_eq!(
+        result.output,
+        Some(json!({
+            "caught": true,
+            "server": "broken",
+            "tool": "some-tool",
+            "isTransportError": true,
+            "content": []
+        })),
+        "Should catch an MCPToolError describing the failed call"
+    );
+}
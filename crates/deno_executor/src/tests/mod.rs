@@ -20,4 +20,5 @@ mod mcp_client_usage;
 mod output_capture;
 mod permissions;
 mod runtime_execution;
+mod sessions;
 mod type_checking;
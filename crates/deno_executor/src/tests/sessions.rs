@@ -0,0 +1,254 @@
+use super::serial;
+use crate::{ExecuteOptions, Session, SessionLimit};
+use serde_json::json;
+use std::time::Duration;
+
+#[serial]
+#[tokio::test]
+async fn test_session_keeps_globals() {
+    let session = Session::new(Duration::from_secs(60));
+
+    let first = session
+        .execute(
+            r"
+globalThis.rows = [1, 2, 3];
+console.log('stored');
+export default rows.length;
+",
+            ExecuteOptions::default(),
+        )
+        .await
+        .expect("execution should succeed");
+    assert!(first.success, "First execution should succeed: {first:?}");
+    assert_eq!(first.output, Some(json!(3)));
+    assert_eq!(first.stdout, "stored");
+
+    let second = session
+        .execute(
+            r"
+console.log('read');
+export default rows.map((r) => r * 2);
+",
+            ExecuteOptions::default(),
+        )
+        .await
+        .expect("execution should succeed");
+    assert!(
+        second.success,
+        "Second execution should succeed: {second:?}"
+    );
+    assert_eq!(
+        second.output,
+        Some(json!([2, 4, 6])),
+        "Globals of the first execution should be visible"
+    );
+    assert_eq!(
+        second.stdout, "read",
+        "Console output of previous executions should not be repeated"
+    );
+}
+
+#[serial]
+#[tokio::test]
+async fn test_session_discards_globals_when_idle() {
+    let session = Session::new(Duration::from_millis(50));
+
+    let first = session
+        .execute(
+            "globalThis.token = 'abc';\nexport default token;",
+            ExecuteOptions::default(),
+        )
+        .await
+        .expect("execution should succeed");
+    assert_eq!(first.output, Some(json!("abc")));
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let second = session
+        .execute(
+            "export default typeof globalThis.token;",
+            ExecuteOptions::default(),
+        )
+        .await
+        .expect("execution should succeed");
+    assert_eq!(
+        second.output,
+        Some(json!("undefined")),
+        "Idle session should start from a fresh runtime"
+    );
+}
+
+#[serial]
+#[tokio::test]
+async fn test_session_registrations_do_not_leak() {
+    let session = Session::new(Duration::from_secs(60));
+    let code = r#"
+registerMCP({ name: "server", url: "http://localhost:3000" });
+export default REGISTRY.has("server");
+"#;
+
+    for _ in 0..2 {
+        let result = session
+            .execute(code, ExecuteOptions::default())
+            .await
+            .expect("execution should succeed");
+        assert!(
+            result.success,
+            "Registering the same server in every execution should succeed: {result:?}"
+        );
+        assert_eq!(result.output, Some(json!(true)));
+    }
+}
+
+#[serial]
+#[tokio::test]
+async fn test_session_keeps_top_level_declarations() {
+    let session = Session::new(Duration::from_secs(60));
+
+    let first = session
+        .execute(
+            r"
+const rows = [1, 2, 3];
+let total = 0;
+function double(n: number) {
+    return n * 2;
+}
+export default rows.length;
+",
+            ExecuteOptions::default(),
+        )
+        .await
+        .expect("execution should succeed");
+    assert!(first.success, "First execution should succeed: {first:?}");
+
+    let second = session
+        .execute(
+            r"
+total = rows.map(double).reduce((a, b) => a + b, 0);
+export default total;
+",
+            ExecuteOptions::default(),
+        )
+        .await
+        .expect("execution should succeed");
+    assert!(
+        second.success,
+        "Second execution should succeed: {second:?}"
+    );
+    assert_eq!(
+        second.output,
+        Some(json!(12)),
+        "Top-level declarations of the first execution should be visible"
+    );
+}
+
+#[serial]
+#[tokio::test]
+async fn test_global_this_access_only_allowed_in_sessions() {
+    let code = "globalThis.token = 'abc';\nexport default token;";
+
+    let one_shot = crate::execute(code, None)
+        .await
+        .expect("execution should succeed");
+    assert!(
+        !one_shot.success,
+        "Untyped globalThis access should fail type checking outside of sessions"
+    );
+
+    let session = Session::new(Duration::from_secs(60));
+    let result = session
+        .execute(code, ExecuteOptions::default())
+        .await
+        .expect("execution should succeed");
+    assert!(
+        result.success,
+        "Session execution should succeed: {result:?}"
+    );
+}
+
+#[serial]
+#[tokio::test]
+async fn test_session_limit() {
+    let limit = SessionLimit::new(1);
+    let first = Session::new(Duration::from_millis(200)).limited_by(&limit);
+    let second = Session::new(Duration::from_millis(200)).limited_by(&limit);
+    assert_eq!(
+        limit.active(),
+        0,
+        "Sessions should only start a thread when executing"
+    );
+
+    let result = first
+        .execute("export default 1;", ExecuteOptions::default())
+        .await
+        .expect("execution should succeed");
+    assert!(result.success, "First session should execute: {result:?}");
+    assert_eq!(limit.active(), 1);
+
+    let err = second
+        .execute("export default 2;", ExecuteOptions::default())
+        .await
+        .expect_err("execution should exceed the session limit");
+    assert!(
+        err.to_string().contains("Too many active sessions"),
+        "Unexpected error: {err}"
+    );
+
+    // still running, the thread of the session keeps its runtime
+    let result = first
+        .execute("export default 3;", ExecuteOptions::default())
+        .await
+        .expect("execution should succeed");
+    assert_eq!(result.output, Some(json!(3)));
+}
+
+#[serial]
+#[tokio::test]
+async fn test_session_thread_exits_when_idle() {
+    let limit = SessionLimit::new(1);
+    let first = Session::new(Duration::from_millis(50)).limited_by(&limit);
+    let second = Session::new(Duration::from_millis(50)).limited_by(&limit);
+
+    first
+        .execute("export default 1;", ExecuteOptions::default())
+        .await
+        .expect("execution should succeed");
+    assert_eq!(limit.active(), 1);
+
+    // the idle session's thread exits, releasing its place
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while limit.active() > 0 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "Idle session's thread should exit"
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let result = second
+        .execute("export default 2;", ExecuteOptions::default())
+        .await
+        .expect("another session should start once the idle one exited");
+    assert_eq!(result.output, Some(json!(2)));
+    assert_eq!(limit.active(), 1);
+    drop(second);
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while limit.active() > 0 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "Dropped session's thread should exit"
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // the evicted session starts over on a new thread
+    let result = first
+        .execute(
+            "export default typeof globalThis.missing;",
+            ExecuteOptions::default(),
+        )
+        .await
+        .expect("evicted session should start a new thread");
+    assert_eq!(result.output, Some(json!("undefined")));
+}
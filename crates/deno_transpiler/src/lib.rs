@@ -1,5 +1,11 @@
 use deno_ast::{
-    EmitOptions, MediaType, ModuleSpecifier, ParseParams, TranspileModuleOptions, TranspileOptions,
+    EmitOptions, MediaType, ModuleSpecifier, ParseParams, ProgramRef, TranspileModuleOptions,
+    TranspileOptions,
+    swc::{
+        ast::{Decl, ModuleDecl, ModuleItem, Stmt, VarDeclKind},
+        atoms::Atom,
+        utils::find_pat_ids,
+    },
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -75,6 +81,76 @@ pub fn transpile(code: &str, specifier: Option<&str>) -> Result<String> {
     Ok(transpiled.into_source().text)
 }
 
+/// Binding declared at the top level of a module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopLevelBinding {
+    pub name: String,
+    /// Whether the binding was declared with `const` and can't be reassigned
+    pub constant: bool,
+}
+
+/// Variables, functions and classes declared at the top level of the
+/// JavaScript module `code`, including exported ones and the names bound by
+/// destructuring patterns
+///
+/// # Errors
+/// Returns `TranspileError::ParseError` if the code cannot be parsed
+///
+/// # Examples
+/// ```
+/// use deno_transpiler::top_level_bindings;
+///
+/// let bindings = top_level_bindings("const { a, b: [c] } = f(); function g() { let d; }").unwrap();
+/// let names: Vec<&str> = bindings.iter().map(|b| b.name.as_str()).collect();
+/// assert_eq!(names, ["a", "c", "g"]);
+/// ```
+pub fn top_level_bindings(code: &str) -> Result<Vec<TopLevelBinding>> {
+    let parsed = deno_ast::parse_module(ParseParams {
+        specifier: ModuleSpecifier::parse("file:///execute.js")
+            .map_err(|e| TranspileError::InvalidSpecifier(e.to_string()))?,
+        text: code.into(),
+        media_type: MediaType::JavaScript,
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+    })
+    .map_err(|e| TranspileError::ParseError(e.to_string()))?;
+
+    let ProgramRef::Module(module) = parsed.program_ref() else {
+        return Ok(vec![]);
+    };
+
+    let mut bindings = vec![];
+    for item in &module.body {
+        let decl = match item {
+            ModuleItem::Stmt(Stmt::Decl(decl)) => decl,
+            ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => &export.decl,
+            _ => continue,
+        };
+        match decl {
+            Decl::Var(var) => {
+                let constant = var.kind == VarDeclKind::Const;
+                bindings.extend(find_pat_ids::<_, Atom>(&**var).into_iter().map(|name| {
+                    TopLevelBinding {
+                        name: name.to_string(),
+                        constant,
+                    }
+                }));
+            }
+            Decl::Fn(f) => bindings.push(TopLevelBinding {
+                name: f.ident.sym.to_string(),
+                constant: false,
+            }),
+            Decl::Class(c) => bindings.push(TopLevelBinding {
+                name: c.ident.sym.to_string(),
+                constant: false,
+            }),
+            _ => {}
+        }
+    }
+    Ok(bindings)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -140,4 +216,39 @@ mod tests {
         assert!(result.contains(r#"from "zod""#));
         assert!(!result.contains(": z.ZodType"));
     }
+
+    #[test]
+    fn test_top_level_bindings() {
+        let code = r"
+            import { z } from 'zod';
+            const rows = [1, 2, 3];
+            let { total, page: [first] } = await fetchPage();
+            var legacy;
+            export const exported = 1;
+            async function run() {
+                const local = rows.length;
+                return local;
+            }
+            class Cache {}
+            for (const i of rows) {}
+            export default await run();
+        ";
+        let bindings = top_level_bindings(code).unwrap();
+        let names: Vec<(&str, bool)> = bindings
+            .iter()
+            .map(|b| (b.name.as_str(), b.constant))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("rows", true),
+                ("total", false),
+                ("first", false),
+                ("legacy", false),
+                ("exported", true),
+                ("run", false),
+                ("Cache", false),
+            ]
+        );
+    }
 }
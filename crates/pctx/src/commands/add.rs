@@ -1,11 +1,12 @@
 use std::str::FromStr;
 
 use anyhow::Result;
+use camino::Utf8PathBuf;
 use clap::Parser;
 use log::info;
 
 use crate::{
-    commands::USER_CANCELLED,
+    commands::{USER_CANCELLED, auth::LoginCmd},
     utils::{
         prompts,
         spinner::Spinner,
@@ -15,7 +16,7 @@ use crate::{
 use pctx_config::{
     Config,
     auth::{AuthConfig, SecretString},
-    server::{McpConnectionError, ServerConfig},
+    server::{HttpTransport, McpConnectionError, ServerConfig, ServerConnection},
 };
 
 #[derive(Debug, Clone, Parser)]
@@ -24,13 +25,26 @@ pub struct AddCmd {
     pub name: String,
 
     /// HTTP(S) URL of the MCP server endpoint
-    pub url: url::Url,
+    #[arg(required_unless_present = "command", conflicts_with = "command")]
+    pub url: Option<url::Url>,
+
+    /// Command (and its arguments) that launches a stdio MCP server,
+    /// provided after `--`.
+    ///
+    /// e.g. `pctx add fs -- npx -y @modelcontextprotocol/server-filesystem .`
+    #[arg(last = true, num_args = 1.., value_name = "COMMAND")]
+    pub command: Vec<String>,
+
+    /// HTTP transport of the MCP server (`streamable-http` or `sse`),
+    /// detected automatically when omitted
+    #[arg(long, short, conflicts_with = "command")]
+    pub transport: Option<HttpTransport>,
 
     /// use bearer authentication to connect to MCP server
     /// using PCTX's secret string syntax.
     ///
     /// e.g. `--bearer '${env:BEARER_TOKEN}'`
-    #[arg(long, short, conflicts_with = "header")]
+    #[arg(long, short, conflicts_with_all = ["header", "command"])]
     pub bearer: Option<SecretString>,
 
     /// use custom headers to connect to MCP server
@@ -38,9 +52,26 @@ pub struct AddCmd {
     /// be defined.
     ///
     /// e.g. `--headers 'x-api-key: ${keychain:API_KEY}'`
-    #[arg(long, short = 'H')]
+    #[arg(long, short = 'H', conflicts_with = "command")]
     pub header: Option<Vec<ClapHeader>>,
 
+    /// environment variables passed to a stdio MCP server
+    /// using PCTX's secret string syntax. Many variables can
+    /// be defined.
+    ///
+    /// e.g. `--env 'API_KEY=${keychain:API_KEY}'`
+    #[arg(long, short, requires = "command")]
+    pub env: Option<Vec<ClapEnvVar>>,
+
+    /// working directory of a stdio MCP server
+    #[arg(long, requires = "command")]
+    pub cwd: Option<Utf8PathBuf>,
+
+    /// TypeScript namespace of the server's functions, derived
+    /// from the name by default
+    #[arg(long)]
+    pub namespace: Option<String>,
+
     /// Overrides any existing server under the same name &
     /// skips testing connection to the MCP server
     #[arg(long, short)]
@@ -49,7 +80,15 @@ pub struct AddCmd {
 
 impl AddCmd {
     pub(crate) async fn handle(&self, mut cfg: Config, save: bool) -> Result<Config> {
-        let mut server = ServerConfig::new(self.name.clone(), self.url.clone());
+        let mut server = if let Some(url) = &self.url {
+            ServerConfig::new(self.name.clone(), url.clone())
+        } else {
+            let Some((command, args)) = self.command.split_first() else {
+                anyhow::bail!("Either an MCP server URL or a command to launch one is required")
+            };
+            ServerConfig::new_stdio(self.name.clone(), command.clone(), args.to_vec())
+        };
+        server.namespace.clone_from(&self.namespace);
 
         // check for name clash
         if cfg.servers.iter().any(|s| s.name == server.name) {
@@ -66,45 +105,86 @@ impl AddCmd {
             }
         }
 
-        // apply authentication (clap ensures bearer & header are mutually exclusive)
-        server.auth = if let Some(bearer) = &self.bearer {
-            Some(AuthConfig::Bearer {
-                token: bearer.clone(),
-            })
-        } else if let Some(headers) = &self.header {
-            Some(AuthConfig::Custom {
-                headers: headers
-                    .iter()
-                    .map(|h| (h.name.clone(), h.value.clone()))
-                    .collect(),
-            })
-        } else {
-            let add_auth =
-                inquire::Confirm::new("Do you want to add authentication interactively?")
+        // check for namespace clash before testing the connection
+        let mut updated = cfg.clone();
+        updated.add_server(server.clone());
+        if let Err(e) = updated.validate() {
+            anyhow::bail!(
+                "{e}\nChoose another namespace with `pctx add {} --namespace <NAMESPACE> ...`",
+                self.name
+            );
+        }
+
+        match &mut server.connection {
+            ServerConnection::Http(http) => {
+                if let Some(transport) = self.transport {
+                    http.transport = transport;
+                }
+
+                // apply authentication (clap ensures bearer & header are mutually exclusive)
+                http.auth = if let Some(bearer) = &self.bearer {
+                    Some(AuthConfig::Bearer {
+                        token: bearer.clone(),
+                    })
+                } else if let Some(headers) = &self.header {
+                    Some(AuthConfig::Custom {
+                        headers: headers
+                            .iter()
+                            .map(|h| (h.name.clone(), h.value.clone()))
+                            .collect(),
+                    })
+                } else {
+                    let add_auth = inquire::Confirm::new(
+                        "Do you want to add authentication interactively?",
+                    )
                     .with_default(false)
                     .with_help_message(
                         "you can also manually update the auth configuration later in the config",
                     );
-            if !self.force && add_auth.prompt()? {
-                Some(prompts::prompt_auth(&server.name)?)
-            } else {
-                None
+                    if !self.force && add_auth.prompt()? {
+                        Some(prompts::prompt_auth(&server.name)?)
+                    } else {
+                        None
+                    }
+                };
             }
-        };
+            ServerConnection::Stdio(stdio) => {
+                // environment variables & working directory of the spawned server
+                stdio.env = self
+                    .env
+                    .iter()
+                    .flatten()
+                    .map(|e| (e.name.clone(), e.value.clone()))
+                    .collect();
+                stdio.cwd.clone_from(&self.cwd);
+            }
+        }
 
         // try connection
+        let mut requires_login = false;
         if !self.force {
             let mut sp = Spinner::new("Testing MCP connection...");
-            let connected = match server.connect().await {
+            // connect with the global network settings applied, keeping the detected transport
+            let mut probe = cfg.resolve_server(&server);
+            let res = if self.transport.is_some() {
+                probe.connect().await
+            } else {
+                probe.connect_detect_transport().await
+            };
+            server.connection = probe.connection;
+            let connected = match res {
                 Ok(client) => {
-                    sp.stop_success("Successfully connected");
+                    sp.stop_success(match server.transport() {
+                        Some(HttpTransport::Sse) => "Successfully connected (SSE transport)",
+                        _ => "Successfully connected",
+                    });
                     client.cancel().await?;
                     true
                 }
                 Err(McpConnectionError::RequiresAuth) => {
                     sp.stop_and_persist(
                         "🔒",
-                        if server.auth.is_none() {
+                        if server.auth().is_none() {
                             "MCP requires authentication"
                         } else {
                             "Invalid authentication"
@@ -112,6 +192,15 @@ impl AddCmd {
                     );
                     false
                 }
+                Err(McpConnectionError::RequiresOAuth) => {
+                    sp.stop_and_persist("🔒", "MCP requires OAuth login");
+                    if let ServerConnection::Http(http) = &mut server.connection {
+                        http.auth
+                            .get_or_insert(AuthConfig::OAuth { scopes: vec![] });
+                    }
+                    requires_login = true;
+                    true
+                }
                 Err(McpConnectionError::Failed(msg)) => {
                     sp.stop_error(msg);
                     false
@@ -144,6 +233,26 @@ impl AddCmd {
             );
         }
 
+        if requires_login {
+            let login_now = save
+                && inquire::Confirm::new("Do you want to log in now?")
+                    .with_default(true)
+                    .prompt()?;
+            if login_now {
+                let login = LoginCmd {
+                    name: self.name.clone(),
+                    scope: None,
+                    port: 0,
+                };
+                cfg = login.handle(cfg).await?;
+            } else {
+                info!(
+                    "Log in later with {}",
+                    fmt_bold(&format!("pctx auth login {}", self.name))
+                );
+            }
+        }
+
         Ok(cfg)
     }
 }
@@ -190,3 +299,32 @@ impl FromStr for ClapHeader {
         })
     }
 }
+
+/// An environment variable in the format "NAME=value" where value is a `SecretString`
+#[derive(Debug, Clone)]
+pub struct ClapEnvVar {
+    pub name: String,
+    pub value: SecretString,
+}
+
+impl FromStr for ClapEnvVar {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!("Environment variable must be in format '<NAME>=<SECRETS STRING>'")
+        })?;
+
+        let name = name.trim();
+        if name.is_empty() {
+            anyhow::bail!(
+                "Environment variable name cannot be empty in format '<NAME>=<SECRETS STRING>'"
+            );
+        }
+
+        Ok(ClapEnvVar {
+            name: name.to_string(),
+            value: SecretString::parse(value)?,
+        })
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use log::{info, warn};
+use pctx_config::{
+    Config,
+    auth::AuthConfig,
+    oauth::{AuthorizationCodeLogin, OAuthCredentials},
+    server::ServerConnection,
+};
+
+use crate::utils::{
+    spinner::Spinner,
+    styles::{fmt_bold, fmt_dimmed, fmt_success},
+};
+
+#[derive(Debug, Clone, Parser)]
+pub struct AuthCmd {
+    #[command(subcommand)]
+    pub command: AuthCommands,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum AuthCommands {
+    /// Log into an OAuth protected MCP server
+    #[command(
+        long_about = "Log into an OAuth protected MCP server using the OAuth 2.1 authorization code flow. \
+The resulting tokens are stored in the system keychain and refreshed automatically."
+    )]
+    Login(LoginCmd),
+
+    /// Remove the stored OAuth credentials of an MCP server
+    Logout(LogoutCmd),
+}
+
+impl AuthCmd {
+    pub(crate) async fn handle(&self, cfg: Config) -> Result<Config> {
+        match &self.command {
+            AuthCommands::Login(cmd) => cmd.handle(cfg).await,
+            AuthCommands::Logout(cmd) => cmd.handle(cfg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct LoginCmd {
+    /// Name of the server to log into
+    pub name: String,
+
+    /// OAuth scopes to request, overrides the scopes in the
+    /// server's auth configuration. Many scopes can be defined.
+    #[arg(long, short)]
+    pub scope: Option<Vec<String>>,
+
+    /// Port of the local listener the authorization server
+    /// redirects to, a free port is picked by default
+    #[arg(long, short, default_value = "0")]
+    pub port: u16,
+}
+
+impl LoginCmd {
+    pub(crate) async fn handle(&self, mut cfg: Config) -> Result<Config> {
+        let network = cfg
+            .get_server(&self.name)
+            .and_then(|server| cfg.resolve_server(server).network)
+            .unwrap_or_default();
+        let server = cfg
+            .get_server_mut(&self.name)
+            .context(format!("Server '{}' not found", self.name))?;
+        let ServerConnection::Http(http) = &mut server.connection else {
+            anyhow::bail!(
+                "{} is a stdio server, OAuth login only applies to HTTP servers",
+                fmt_bold(&self.name)
+            );
+        };
+
+        let scopes = match (&self.scope, &http.auth) {
+            (Some(scopes), _) => scopes.clone(),
+            (None, Some(AuthConfig::OAuth { scopes })) => scopes.clone(),
+            (None, _) => vec![],
+        };
+
+        let login = AuthorizationCodeLogin::start(&http.url, &scopes, self.port, &network).await?;
+        info!(
+            "Log into {} in your browser:\n{}",
+            fmt_bold(&self.name),
+            fmt_dimmed(login.authorization_url())
+        );
+        if let Err(e) = open::that(login.authorization_url()) {
+            warn!("Failed opening browser ({e}), open the URL above manually");
+        }
+
+        let mut sp = Spinner::new("Waiting for login to complete...");
+        let creds = match login.finish().await {
+            Ok(creds) => creds,
+            Err(e) => {
+                sp.stop_error("Login failed");
+                return Err(e);
+            }
+        };
+        creds.store(&self.name)?;
+        sp.stop_success(format!("Logged into {}", fmt_bold(&self.name)));
+
+        http.auth = Some(AuthConfig::OAuth { scopes });
+        cfg.save()?;
+
+        Ok(cfg)
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct LogoutCmd {
+    /// Name of the server to log out of
+    pub name: String,
+}
+
+impl LogoutCmd {
+    pub(crate) fn handle(&self, cfg: Config) -> Result<Config> {
+        if cfg.get_server(&self.name).is_none() {
+            anyhow::bail!("Server '{}' not found", self.name);
+        }
+
+        OAuthCredentials::remove(&self.name)?;
+
+        info!(
+            "{}",
+            fmt_success(&format!(
+                "OAuth credentials of {} removed",
+                fmt_bold(&self.name)
+            ))
+        );
+
+        Ok(cfg)
+    }
+}
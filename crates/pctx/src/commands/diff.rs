@@ -0,0 +1,460 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use clap::Parser;
+use codegen::schema_diff::{SchemaRole, diff_schemas};
+use log::info;
+use pctx_config::Config;
+
+use crate::{
+    mcp::{
+        lock::{self, Lockfile},
+        upstream::{UpstreamMcp, UpstreamTool},
+    },
+    utils::{
+        spinner::Spinner,
+        styles::{fmt_bold, fmt_cyan, fmt_dimmed, fmt_error, fmt_green, fmt_red, fmt_yellow},
+    },
+};
+
+#[derive(Debug, Clone, Parser)]
+pub struct DiffCmd {
+    /// Record the live interfaces in the lockfile, accepting the reported changes
+    #[arg(long)]
+    pub update: bool,
+}
+
+impl DiffCmd {
+    pub(crate) async fn handle(&self, cfg: Config) -> Result<Config> {
+        let lock_path = Lockfile::path(&cfg);
+        if cfg.servers.is_empty() && !lock_path.exists() {
+            info!("No upstream MCP servers configured");
+            return Ok(cfg);
+        }
+        if !lock_path.exists() && !self.update {
+            anyhow::bail!(
+                "No snapshot recorded at {lock_path}, record one with `pctx diff --update` or `pctx start`"
+            );
+        }
+        let lock = Lockfile::load(&cfg)?;
+
+        let num_servers = cfg.servers.len();
+        let mut sp = Spinner::new(format!("Fetching upstream tools... 0/{num_servers}"));
+        let mut diffs = vec![];
+        let mut live = vec![];
+        for (i, server) in cfg.resolved_servers().iter().enumerate() {
+            sp.update_text(format!(
+                "Fetching upstream tools... {}/{num_servers}",
+                i + 1
+            ));
+            let diff = match UpstreamMcp::discover(server).await {
+                Ok(upstream) => {
+                    let diff = ServerDiff::new(&server.name, lock.get(&server.name), &upstream);
+                    live.push(upstream);
+                    diff
+                }
+                Err(e) => ServerDiff::unreachable(&server.name, &e),
+            };
+            diffs.push(diff);
+        }
+        sp.stop_success("Done");
+        diffs.extend(lock.removed(&cfg).map(ServerDiff::removed));
+
+        for diff in &diffs {
+            info!("\n{diff}");
+        }
+
+        let breaking: usize = diffs.iter().map(|d| d.count(true)).sum();
+        let compatible: usize = diffs.iter().map(|d| d.count(false)).sum();
+        let unreachable = diffs.iter().filter(|d| d.error.is_some()).count();
+        info!("");
+        info!(
+            "{} breaking and {} compatible change{}",
+            fmt_bold(&breaking.to_string()),
+            fmt_bold(&compatible.to_string()),
+            if compatible == 1 { "" } else { "s" }
+        );
+
+        if self.update {
+            lock::record(&cfg, &live);
+            info!(
+                "Recorded live interfaces in {}",
+                fmt_bold(lock_path.as_str())
+            );
+        } else if breaking > 0 || unreachable > 0 {
+            let mut problems = vec![];
+            if breaking > 0 {
+                problems.push(format!(
+                    "{breaking} breaking change{}",
+                    if breaking == 1 { "" } else { "s" }
+                ));
+            }
+            if unreachable > 0 {
+                problems.push(format!(
+                    "{unreachable} unreachable server{}",
+                    if unreachable == 1 { "" } else { "s" }
+                ));
+            }
+            anyhow::bail!(
+                "Found {}, run `pctx diff --update` to accept the changes",
+                problems.join(" and ")
+            );
+        }
+
+        Ok(cfg)
+    }
+}
+
+/// Change of a single function (or the namespace itself)
+struct FunctionChange {
+    subject: String,
+    message: String,
+    breaking: bool,
+}
+
+/// Changes between the recorded and live interface of an upstream server
+struct ServerDiff {
+    name: String,
+    error: Option<String>,
+    changes: Vec<FunctionChange>,
+}
+
+impl ServerDiff {
+    fn new(name: &str, recorded: Option<&UpstreamMcp>, live: &UpstreamMcp) -> Self {
+        let mut changes = vec![];
+        let Some(recorded) = recorded else {
+            changes.push(FunctionChange {
+                subject: live.namespace.clone(),
+                message: format!(
+                    "new server with {} function{}",
+                    live.tools.len(),
+                    if live.tools.len() == 1 { "" } else { "s" }
+                ),
+                breaking: false,
+            });
+            return Self {
+                name: name.into(),
+                error: None,
+                changes,
+            };
+        };
+
+        if recorded.namespace != live.namespace {
+            changes.push(FunctionChange {
+                subject: recorded.namespace.clone(),
+                message: format!("namespace renamed to `{}`", live.namespace),
+                breaking: true,
+            });
+        }
+
+        for old in recorded.tools.values() {
+            let subject = format!("{}.{}", recorded.namespace, old.fn_name);
+            match live.tools.values().find(|t| t.tool_name == old.tool_name) {
+                Some(new) => changes.extend(function_changes(&subject, old, new)),
+                None => changes.push(FunctionChange {
+                    subject,
+                    message: "function removed".into(),
+                    breaking: true,
+                }),
+            }
+        }
+        for new in live.tools.values() {
+            if !recorded
+                .tools
+                .values()
+                .any(|t| t.tool_name == new.tool_name)
+            {
+                changes.push(FunctionChange {
+                    subject: format!("{}.{}", live.namespace, new.fn_name),
+                    message: "function added".into(),
+                    breaking: false,
+                });
+            }
+        }
+
+        Self {
+            name: name.into(),
+            error: None,
+            changes,
+        }
+    }
+
+    fn removed(recorded: &UpstreamMcp) -> Self {
+        Self {
+            name: recorded.name.clone(),
+            error: None,
+            changes: vec![FunctionChange {
+                subject: recorded.namespace.clone(),
+                message: format!(
+                    "server removed from the config with {} function{}",
+                    recorded.tools.len(),
+                    if recorded.tools.len() == 1 { "" } else { "s" }
+                ),
+                breaking: true,
+            }],
+        }
+    }
+
+    fn unreachable(name: &str, err: &anyhow::Error) -> Self {
+        Self {
+            name: name.into(),
+            error: Some(format!("Failed fetching tools: {err}")),
+            changes: vec![],
+        }
+    }
+
+    fn count(&self, breaking: bool) -> usize {
+        self.changes
+            .iter()
+            .filter(|c| c.breaking == breaking)
+            .count()
+    }
+}
+
+/// Changes between two versions of the same upstream tool
+fn function_changes(subject: &str, old: &UpstreamTool, new: &UpstreamTool) -> Vec<FunctionChange> {
+    let mut changes = vec![];
+    let mut push = |message: String, breaking: bool| {
+        changes.push(FunctionChange {
+            subject: subject.into(),
+            message,
+            breaking,
+        });
+    };
+
+    if old.fn_name != new.fn_name {
+        push(format!("renamed to `{}`", new.fn_name), true);
+    }
+
+    let mut schema_changes = diff_schemas(&old.input_schema, &new.input_schema, SchemaRole::Input);
+    match (&old.output_schema, &new.output_schema) {
+        (Some(old_schema), Some(new_schema)) => {
+            schema_changes.extend(diff_schemas(old_schema, new_schema, SchemaRole::Output));
+        }
+        (None, Some(_)) => push("now declares its output type".into(), false),
+        // callers relying on the declared output type lose it
+        (Some(_), None) => push("no longer declares its output type".into(), true),
+        (None, None) => {}
+    }
+    let schemas_changed =
+        !schema_changes.is_empty() || old.output_schema.is_some() != new.output_schema.is_some();
+    for change in schema_changes {
+        push(
+            format!("{}: {}", change.path, change.message),
+            change.breaking,
+        );
+    }
+
+    if !schemas_changed && (old.types != new.types || old.description != new.description) {
+        push("documentation changed".into(), false);
+    }
+
+    changes
+}
+
+impl Display for ServerDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields: Vec<String> = if let Some(e) = &self.error {
+            vec![fmt_error(e)]
+        } else if self.changes.is_empty() {
+            vec![fmt_dimmed("no changes")]
+        } else {
+            self.changes
+                .iter()
+                .map(|c| {
+                    let label = if c.breaking {
+                        fmt_red("breaking")
+                    } else {
+                        fmt_green("compatible")
+                    };
+                    format!("{label} {}: {}", fmt_yellow(&c.subject), c.message)
+                })
+                .collect()
+        };
+
+        let tree = fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                if i < fields.len() - 1 {
+                    format!("├── {f}")
+                } else {
+                    format!("└── {f}")
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        write!(f, "{}\n{tree}", fmt_cyan(&self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+    use pctx_config::server::ServerConfig;
+    use serde_json::json;
+
+    fn server() -> ServerConfig {
+        serde_json::from_value(json!({ "name": "github", "url": "https://mcp.example.com/mcp" }))
+            .unwrap()
+    }
+
+    fn tool(
+        name: &str,
+        input: &serde_json::Value,
+        output: Option<&serde_json::Value>,
+    ) -> rmcp::model::Tool {
+        let mut tool = rmcp::model::Tool::new(
+            name.to_string(),
+            format!("Upstream {name}"),
+            input.as_object().unwrap().clone(),
+        );
+        tool.output_schema = output.map(|o| std::sync::Arc::new(o.as_object().unwrap().clone()));
+        tool
+    }
+
+    fn upstream(tools: Vec<rmcp::model::Tool>) -> UpstreamMcp {
+        let server = server();
+        UpstreamMcp {
+            name: server.name.clone(),
+            namespace: server.namespace(),
+            description: "GitHub".into(),
+            url: None,
+            tools: UpstreamTool::from_tools(&server, tools).unwrap(),
+            resources: vec![],
+            prompts: IndexMap::new(),
+            stale: false,
+        }
+    }
+
+    fn issue_input(required: &[&str]) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": { "id": { "type": "integer" }, "repo": { "type": "string" } },
+            "required": required,
+        })
+    }
+
+    fn single(old: &rmcp::model::Tool, new: &rmcp::model::Tool) -> Vec<(String, bool)> {
+        let old = upstream(vec![old.clone()]);
+        let new = upstream(vec![new.clone()]);
+        let (old, new) = (&old.tools[0], &new.tools[0]);
+        function_changes("Github.getIssue", old, new)
+            .into_iter()
+            .map(|c| (c.message, c.breaking))
+            .collect()
+    }
+
+    #[test]
+    fn test_server_diff_functions() {
+        let recorded = upstream(vec![
+            tool("get_issue", &issue_input(&["id"]), None),
+            tool("delete_issue", &issue_input(&["id"]), None),
+        ]);
+        let live = upstream(vec![
+            tool("get_issue", &issue_input(&["id"]), None),
+            tool("create_issue", &issue_input(&["repo"]), None),
+        ]);
+
+        let diff = ServerDiff::new("github", Some(&recorded), &live);
+        let changes: Vec<(&str, &str, bool)> = diff
+            .changes
+            .iter()
+            .map(|c| (c.subject.as_str(), c.message.as_str(), c.breaking))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("Github.deleteIssue", "function removed", true),
+                ("Github.createIssue", "function added", false),
+            ]
+        );
+        assert_eq!((diff.count(true), diff.count(false)), (1, 1));
+
+        let unchanged = ServerDiff::new("github", Some(&recorded), &recorded);
+        assert!(unchanged.changes.is_empty());
+
+        let new = ServerDiff::new("github", None, &live);
+        assert_eq!(new.count(true), 0);
+        assert_eq!(new.changes[0].message, "new server with 2 functions");
+    }
+
+    #[test]
+    fn test_server_diff_removed_server() {
+        let diff = ServerDiff::removed(&upstream(vec![tool("get_issue", &issue_input(&[]), None)]));
+        assert_eq!(diff.name, "github");
+        assert_eq!(diff.count(true), 1);
+        assert_eq!(
+            diff.changes[0].message,
+            "server removed from the config with 1 function"
+        );
+    }
+
+    #[test]
+    fn test_function_changes_input() {
+        let old = tool("get_issue", &issue_input(&["id"]), None);
+
+        let changes = single(
+            &old,
+            &tool("get_issue", &issue_input(&["id", "repo"]), None),
+        );
+        assert_eq!(changes.len(), 1);
+        assert!(
+            changes[0].0.ends_with("repo: is now required"),
+            "{changes:?}"
+        );
+        assert!(changes[0].1, "New required arguments should be breaking");
+
+        let changes = single(&old, &tool("get_issue", &issue_input(&[]), None));
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].0.ends_with("id: is now optional"), "{changes:?}");
+        assert!(!changes[0].1, "Optional arguments should be compatible");
+    }
+
+    #[test]
+    fn test_function_changes_output() {
+        let output = json!({
+            "type": "object",
+            "properties": { "title": { "type": "string" } },
+        });
+        let with_output = tool("get_issue", &issue_input(&["id"]), Some(&output));
+        let without_output = tool("get_issue", &issue_input(&["id"]), None);
+
+        assert_eq!(
+            single(&with_output, &without_output),
+            [("no longer declares its output type".to_string(), true)]
+        );
+        assert_eq!(
+            single(&without_output, &with_output),
+            [("now declares its output type".to_string(), false)]
+        );
+
+        let fewer_fields = tool(
+            "get_issue",
+            &issue_input(&["id"]),
+            Some(&json!({ "type": "object", "properties": {} })),
+        );
+        let changes = single(&with_output, &fewer_fields);
+        assert_eq!(changes.len(), 1);
+        assert!(
+            changes[0].0.ends_with("title: property removed"),
+            "{changes:?}"
+        );
+        assert!(changes[0].1, "Removed result fields should be breaking");
+    }
+
+    #[test]
+    fn test_function_changes_docs() {
+        let old = tool("get_issue", &issue_input(&["id"]), None);
+        let mut new = old.clone();
+        new.description = Some("Fetches a single issue".into());
+
+        assert_eq!(
+            single(&old, &new),
+            [("documentation changed".to_string(), false)]
+        );
+        assert!(single(&old, &old).is_empty());
+    }
+}
@@ -80,10 +80,15 @@ impl InitCmd {
                     .prompt()?;
                 let add_cmd = AddCmd {
                     name: name.clone(),
-                    url: url.parse()?,
+                    url: Some(url.parse()?),
+                    command: vec![],
+                    transport: None,
+                    namespace: None,
                     force: false,
                     bearer: None,
                     header: None,
+                    env: None,
+                    cwd: None,
                 };
                 match add_cmd.handle(cfg.clone(), false).await {
                     Ok(updated) => {
@@ -5,10 +5,9 @@ use clap::Parser;
 use log::info;
 use pctx_config::{
     Config,
-    server::{McpConnectionError, ServerConfig},
+    server::{McpConnectionError, ServerConfig, ServerConnection},
 };
 use rmcp::model::InitializeResult;
-use url::Url;
 
 use crate::utils::{
     spinner::Spinner,
@@ -33,7 +32,7 @@ impl ListCmd {
         let num_servers = cfg.servers.len();
         let mut sp = Spinner::new(format!("Listing upstream MCPs... 0/{num_servers}"));
         let mut summaries = vec![];
-        for (i, server) in cfg.servers.iter().enumerate() {
+        for (i, server) in cfg.resolved_servers().iter().enumerate() {
             sp.update_text(format!("Listing upstream MCPs... {}/{num_servers}", i + 1));
             summaries.push(UpstreamMcpSummary::new(server).await);
         }
@@ -49,7 +48,7 @@ impl ListCmd {
 }
 
 struct UpstreamMcpSummary {
-    pub url: Url,
+    pub connection: ServerConnection,
     pub name: String,
     pub error: Option<String>,
     pub init_res: Option<InitializeResult>,
@@ -75,11 +74,19 @@ impl UpstreamMcpSummary {
             Err(McpConnectionError::RequiresAuth) => {
                 (Some("Requires authentication".into()), None, vec![])
             }
+            Err(McpConnectionError::RequiresOAuth) => (
+                Some(format!(
+                    "Requires OAuth login, run `pctx auth login {}`",
+                    server.name
+                )),
+                None,
+                vec![],
+            ),
             Err(McpConnectionError::Failed(msg)) => (Some(msg), None, vec![]),
         };
 
         Self {
-            url: server.url.clone(),
+            connection: server.connection.clone(),
             name: server.name.clone(),
             error,
             init_res,
@@ -90,12 +97,20 @@ impl UpstreamMcpSummary {
 impl Display for UpstreamMcpSummary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut fields = vec![];
-        let url_field = format!("{}: {}", fmt_bold("URL"), &self.url);
+        let connection_label = match &self.connection {
+            ServerConnection::Http(_) => "URL",
+            ServerConnection::Stdio(_) => "Command",
+        };
+        let connection_field = format!("{}: {}", fmt_bold(connection_label), &self.connection);
 
         if let Some(e) = &self.error {
-            fields.extend([fmt_error(e), url_field]);
+            fields.extend([fmt_error(e), connection_field]);
         } else {
-            fields.extend([fmt_success("Connected"), url_field]);
+            fields.extend([fmt_success("Connected"), connection_field]);
+
+            if let ServerConnection::Http(http) = &self.connection {
+                fields.push(format!("{}: {}", fmt_bold("Transport"), http.transport));
+            }
 
             if let Some(init_res) = &self.init_res {
                 fields.push(format!(
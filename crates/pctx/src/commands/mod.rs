@@ -1,4 +1,6 @@
 pub(crate) mod add;
+pub(crate) mod auth;
+pub(crate) mod diff;
 pub(crate) mod init;
 pub(crate) mod list;
 pub(crate) mod remove;
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use clap::Parser;
 use log::info;
-use pctx_config::Config;
+use pctx_config::{Config, auth::AuthConfig, oauth::OAuthCredentials};
 
 use crate::utils::styles::{fmt_bold, fmt_dimmed, fmt_success};
 
@@ -13,7 +13,14 @@ pub struct RemoveCmd {
 
 impl RemoveCmd {
     pub(crate) fn handle(&self, mut cfg: Config) -> Result<Config> {
+        let oauth = cfg
+            .get_server(&self.name)
+            .is_some_and(|s| matches!(s.auth(), Some(AuthConfig::OAuth { .. })));
+
         cfg.remove_server(&self.name)?;
+        if oauth {
+            OAuthCredentials::remove(&self.name)?;
+        }
 
         cfg.save()?;
 
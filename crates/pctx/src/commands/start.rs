@@ -1,14 +1,20 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
 use log::{info, warn};
 use pctx_config::Config;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
 
 use crate::{
-    mcp::{PctxMcp, upstream::UpstreamMcp},
+    mcp::{
+        PctxMcp, Transport,
+        lock::{self, Lockfile},
+        upstream::UpstreamMcp,
+    },
     utils::{
         CHECK, MARK,
         spinner::Spinner,
-        styles::{fmt_bold, fmt_cyan, fmt_error, fmt_green, fmt_red, fmt_yellow},
+        styles::{fmt_bold, fmt_cyan, fmt_dimmed, fmt_error, fmt_green, fmt_red, fmt_yellow},
     },
 };
 
@@ -18,9 +24,29 @@ pub struct StartCmd {
     #[arg(short, long, default_value = "8080")]
     pub port: u16,
 
-    /// Host address to bind to (use 0.0.0.0 for external access)
+    /// Host address to bind to (use 0.0.0.0 for external access, protected with `inbound_auth` in the config)
     #[arg(long, default_value = "127.0.0.1")]
     pub host: String,
+
+    /// Serve the interfaces cached in the lockfile (e.g. `pctx.lock`) for
+    /// upstream servers that can't be reached, marked as stale until they are
+    #[arg(long)]
+    pub allow_stale: bool,
+
+    /// Serve over stdin/stdout instead of HTTP, for MCP clients that launch
+    /// pctx as a subprocess. Logs are written to stderr.
+    #[arg(long, conflicts_with_all = ["port", "host"])]
+    pub stdio: bool,
+
+    /// Seconds a session's sandbox is kept after its last `execute`, top-level
+    /// declarations and values stored on `globalThis` are discarded with it
+    #[arg(long, default_value = "900")]
+    pub session_timeout: u64,
+
+    /// Maximum number of sessions whose sandbox is kept at the same time,
+    /// `execute` fails for new sessions until another one is idle
+    #[arg(long, default_value = "64")]
+    pub max_sessions: usize,
 }
 
 impl StartCmd {
@@ -30,33 +56,81 @@ impl StartCmd {
                 "No upstream MCP servers configured. Add servers with 'pctx add <name> <url>'"
             );
         }
+        cfg.validate()
+            .context(format!("Invalid config {}", cfg.path()))?;
 
-        // Connect to each MCP server and fetch their tool definitions
-        let mut sp = Spinner::new("");
+        // Connect to all MCP servers concurrently and fetch their tool definitions
+        let total = cfg.servers.len();
+        let mut sp = Spinner::new(format!(
+            "Creating {} interface for {} upstream MCP servers",
+            fmt_bold("Code Mode"),
+            fmt_cyan(&total.to_string())
+        ));
 
-        let mut upstream_servers = Vec::new();
-        let mut fails = Vec::new();
-        for server in &cfg.servers {
+        let mut discovery = JoinSet::new();
+        for (i, server) in cfg.resolved_servers().into_iter().enumerate() {
+            discovery.spawn(async move {
+                let started = Instant::now();
+                let res = UpstreamMcp::discover(&server).await;
+                (i, res, started.elapsed())
+            });
+        }
+
+        let mut results = Vec::with_capacity(total);
+        while let Some(joined) = discovery.join_next().await {
+            results.push(joined?);
             sp.update_text(format!(
-                "Creating {} interface for {}",
+                "Creating {} interface for upstream MCP servers ({}/{total})",
                 fmt_bold("Code Mode"),
-                fmt_cyan(&server.name)
+                results.len()
             ));
-            match UpstreamMcp::from_server(server).await {
+        }
+        // report in config order rather than completion order
+        results.sort_by_key(|(i, ..)| *i);
+
+        let lock = if self.allow_stale {
+            Lockfile::load(&cfg).unwrap_or_else(|e| {
+                warn!("{e:#}, no cached interfaces are available");
+                Lockfile::default()
+            })
+        } else {
+            Lockfile::default()
+        };
+
+        let mut upstream_servers = Vec::new();
+        let mut timings = Vec::new();
+        let mut fails = Vec::new();
+        for (i, res, elapsed) in results {
+            let name = &cfg.servers[i].name;
+            let elapsed = fmt_dimmed(&format!("({:.1}s)", elapsed.as_secs_f32()));
+            match res {
                 Ok(upstream) => {
+                    timings.push(format!("{} {elapsed}", fmt_cyan(name)));
                     upstream_servers.push(upstream);
                 }
                 Err(e) => {
+                    let cached = lock.cached(&cfg.resolve_server(&cfg.servers[i]));
+                    let fallback = if cached.is_some() {
+                        ", serving its cached interface"
+                    } else {
+                        ""
+                    };
                     fails.push(fmt_error(&format!(
-                        "Failed creating {} for {}: {e}",
+                        "Failed creating {} for {} {elapsed}: {e}{fallback}",
                         fmt_bold("Code Mode"),
-                        fmt_cyan(&server.name)
+                        fmt_cyan(name)
                     )));
+                    if let Some(cached) = cached {
+                        timings.push(format!("{} {}", fmt_cyan(name), fmt_yellow("(stale)")));
+                        upstream_servers.push(cached);
+                    }
                 }
             }
         }
+        lock::record_missing(&cfg, &upstream_servers);
 
-        let symbol = if upstream_servers.len() == cfg.servers.len() {
+        let fresh = upstream_servers.iter().filter(|u| !u.stale).count();
+        let symbol = if fresh == total {
             fmt_green(CHECK)
         } else if upstream_servers.is_empty() {
             fmt_red(MARK)
@@ -65,10 +139,15 @@ impl StartCmd {
         };
 
         let plural = if upstream_servers.len() > 1 { "s" } else { "" };
+        let timings = if timings.is_empty() {
+            String::new()
+        } else {
+            format!(": {}", timings.join(", "))
+        };
         sp.stop_and_persist(
             &symbol,
             format!(
-                "{} interface generated for {} upstream MCP server{}",
+                "{} interface generated for {} upstream MCP server{}{timings}",
                 fmt_bold("Code Mode"),
                 fmt_cyan(&upstream_servers.len().to_string()),
                 plural
@@ -78,9 +157,23 @@ impl StartCmd {
             warn!("{fail}");
         }
 
-        PctxMcp::new(cfg.clone(), upstream_servers, &self.host, self.port)
-            .serve()
-            .await?;
+        let transport = if self.stdio {
+            Transport::Stdio
+        } else {
+            Transport::Http {
+                host: self.host.clone(),
+                port: self.port,
+            }
+        };
+        PctxMcp::new(
+            cfg.clone(),
+            upstream_servers,
+            transport,
+            Duration::from_secs(self.session_timeout),
+            self.max_sessions,
+        )
+        .serve()
+        .await?;
 
         info!("Shutting down...");
 
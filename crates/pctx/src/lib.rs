@@ -6,7 +6,8 @@ use camino::Utf8PathBuf;
 use clap::{Parser, Subcommand};
 
 use crate::commands::{
-    add::AddCmd, init::InitCmd, list::ListCmd, remove::RemoveCmd, start::StartCmd,
+    add::AddCmd, auth::AuthCmd, diff::DiffCmd, init::InitCmd, list::ListCmd, remove::RemoveCmd,
+    start::StartCmd,
 };
 use pctx_config::Config;
 
@@ -51,6 +52,8 @@ impl Cli {
             Commands::List(cmd) => cmd.handle(cfg?).await?,
             Commands::Add(cmd) => cmd.handle(cfg?, true).await?,
             Commands::Remove(cmd) => cmd.handle(cfg?)?,
+            Commands::Auth(cmd) => cmd.handle(cfg?).await?,
+            Commands::Diff(cmd) => cmd.handle(cfg?).await?,
             Commands::Start(cmd) => cmd.handle(cfg?).await?,
         };
 
@@ -60,6 +63,7 @@ impl Cli {
 
 #[derive(Debug, Subcommand)]
 #[command(styles=utils::styles::get_styles())]
+#[allow(clippy::large_enum_variant)]
 pub enum Commands {
     /// List MCP servers and test connections
     #[command(long_about = "Lists configured MCP servers and tests the connection to each.")]
@@ -73,8 +77,21 @@ pub enum Commands {
     #[command(long_about = "Remove an MCP server from the configuration.")]
     Remove(RemoveCmd),
 
+    /// Manage OAuth logins of MCP servers
+    #[command(long_about = "Log into and out of OAuth protected MCP servers.")]
+    Auth(AuthCmd),
+
+    /// Compare upstream tools against the lockfile
+    #[command(
+        long_about = "Compare the live tools of the upstream MCP servers against the interfaces recorded in the lockfile \
+(e.g. pctx.lock), reporting added, removed and changed functions. Exits with an error on breaking changes."
+    )]
+    Diff(DiffCmd),
+
     /// Start the PCTX server
-    #[command(long_about = "Start the PCTX server (exposes /mcp endpoint).")]
+    #[command(
+        long_about = "Start the PCTX server (exposes /mcp endpoint, or stdin/stdout with --stdio)."
+    )]
     Start(StartCmd),
 
     /// Initialize configuration file
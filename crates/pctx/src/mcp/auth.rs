@@ -0,0 +1,893 @@
+//! Authentication of the clients connecting to the HTTP MCP endpoint
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use log::{debug, info, warn};
+use pctx_config::{
+    inbound::{InboundAuthConfig, JwtConfig},
+    network::NetworkConfig,
+    server::ServerConfig,
+};
+use ring::{
+    digest::{self, SHA256},
+    signature::{self, RsaPublicKeyComponents, UnparsedPublicKey},
+};
+use rmcp::service::{RequestContext, RoleServer};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Minimum time between two fetches of a JWKS URL, so tokens signed with
+/// unknown keys can't make pctx hammer the identity provider
+const JWKS_MIN_REFRESH_SECS: u64 = 60;
+
+/// Header API keys can be sent in instead of `Authorization`
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Client verified by [`InboundAuth`], stored in the extensions of its requests
+#[derive(Debug, Clone)]
+pub(crate) struct ClientIdentity {
+    /// API key name or the JWT `sub` claim
+    pub(crate) subject: String,
+    pub(crate) method: AuthMethod,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AuthMethod {
+    ApiKey,
+    Jwt,
+}
+
+impl Display for ClientIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.method {
+            AuthMethod::ApiKey => write!(f, "API key '{}'", self.subject),
+            AuthMethod::Jwt => write!(f, "JWT subject '{}'", self.subject),
+        }
+    }
+}
+
+impl ClientIdentity {
+    /// Identity of the client that sent the MCP request of `context`, `None`
+    /// if inbound auth is disabled or the server is served over stdio
+    pub(crate) fn of(context: &RequestContext<RoleServer>) -> Option<Self> {
+        context
+            .extensions
+            .get::<Parts>()
+            .and_then(|parts| parts.extensions.get::<Self>())
+            .cloned()
+    }
+}
+
+/// Credentials the client that sent the MCP request of `context` forwards
+/// for the `servers` with `forward` auth, keyed by server name
+pub(crate) fn forwarded_credentials(
+    context: &RequestContext<RoleServer>,
+    servers: &[ServerConfig],
+) -> HashMap<String, String> {
+    let Some(parts) = context.extensions.get::<Parts>() else {
+        return HashMap::new();
+    };
+
+    servers
+        .iter()
+        .filter_map(|server| {
+            let header = server.forwarded_header()?;
+            let value = parts.headers.get(header.as_str())?.to_str().ok()?;
+            Some((server.name.clone(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Why a request was rejected, answered with a `401 Unauthorized`
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum AuthError {
+    #[error("Missing credentials")]
+    Missing,
+    #[error("{0}")]
+    Invalid(String),
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        // RFC 6750: no error code when the client didn't try to authenticate
+        let challenge = match &self {
+            Self::Missing => r#"Bearer realm="pctx""#.to_string(),
+            Self::Invalid(reason) => format!(
+                r#"Bearer realm="pctx", error="invalid_token", error_description="{}""#,
+                reason.replace(['"', '\\'], "'")
+            ),
+        };
+        let mut response = (StatusCode::UNAUTHORIZED, self.to_string()).into_response();
+        if let Ok(value) = HeaderValue::from_str(&challenge) {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, value);
+        }
+        response
+    }
+}
+
+/// Verifies the API keys and JWTs configured in `inbound_auth`
+pub(crate) struct InboundAuth {
+    /// SHA-256 digests of the API keys with the client name they identify,
+    /// digests are compared so the comparison time reveals nothing about the keys
+    api_keys: Vec<(Vec<u8>, String)>,
+    jwt: Option<JwtVerifier>,
+}
+
+impl InboundAuth {
+    /// Resolves the API keys and loads the JWKS of `cfg`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an API key cannot be resolved or
+    /// the JWKS file cannot be read
+    pub(crate) async fn new(
+        cfg: &InboundAuthConfig,
+        network: Option<&NetworkConfig>,
+    ) -> Result<Self> {
+        let mut api_keys = vec![];
+        for key in &cfg.api_keys {
+            let resolved = key
+                .key
+                .resolve()
+                .await
+                .context(format!("Failed resolving API key '{}'", key.name))?;
+            api_keys.push((digest_of(&resolved), key.name.clone()));
+        }
+
+        let jwt = match &cfg.jwt {
+            Some(jwt) => Some(JwtVerifier::new(jwt.clone(), network).await?),
+            None => None,
+        };
+
+        Ok(Self { api_keys, jwt })
+    }
+
+    /// Describes the accepted credentials, e.g. for the startup banner
+    pub(crate) fn methods(&self) -> String {
+        let mut methods = vec![];
+        if !self.api_keys.is_empty() {
+            methods.push("API keys");
+        }
+        if self.jwt.is_some() {
+            methods.push("JWT");
+        }
+        methods.join(", ")
+    }
+
+    /// Identity of the client presenting the credentials in `headers`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no credentials were presented or
+    /// they are not valid
+    pub(crate) async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+    ) -> Result<ClientIdentity, AuthError> {
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| {
+                v.strip_prefix("Bearer ")
+                    .or_else(|| v.strip_prefix("bearer "))
+            })
+            .map(str::trim);
+        let api_key = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
+
+        let Some(credential) = api_key.or(bearer) else {
+            return Err(AuthError::Missing);
+        };
+
+        let digest = digest_of(credential);
+        if let Some((_, name)) = self.api_keys.iter().find(|(d, _)| *d == digest) {
+            return Ok(ClientIdentity {
+                subject: name.clone(),
+                method: AuthMethod::ApiKey,
+            });
+        }
+
+        match (&self.jwt, bearer) {
+            (Some(jwt), Some(token)) if api_key.is_none() => jwt.verify(token).await,
+            _ => Err(AuthError::Invalid("Unknown API key".into())),
+        }
+    }
+}
+
+/// Middleware rejecting requests without valid credentials, the verified
+/// [`ClientIdentity`] is added to the extensions of the others
+pub(crate) async fn authenticate(
+    State(auth): State<Arc<InboundAuth>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match auth.authenticate(request.headers()).await {
+        Ok(identity) => {
+            debug!("Authenticated {identity}");
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        Err(e) => {
+            info!("Rejected unauthenticated request: {e}");
+            e.into_response()
+        }
+    }
+}
+
+fn digest_of(key: &str) -> Vec<u8> {
+    digest::digest(&SHA256, key.as_bytes()).as_ref().to_vec()
+}
+
+/// Public key of a JSON Web Key Set
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    alg: Option<String>,
+    // RSA
+    n: Option<String>,
+    e: Option<String>,
+    // EC and OKP
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// Registered claims checked by [`JwtVerifier`]
+#[derive(Debug, Deserialize)]
+struct JwtClaims {
+    iss: Option<String>,
+    aud: Option<Audience>,
+    exp: Option<u64>,
+    nbf: Option<u64>,
+    sub: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, audience: &str) -> bool {
+        match self {
+            Self::One(a) => a == audience,
+            Self::Many(all) => all.iter().any(|a| a == audience),
+        }
+    }
+}
+
+/// Verifies JWTs against a JWKS, refetching keys from `jwks_url` when a token
+/// is signed with a key it doesn't know yet (e.g. after a key rotation)
+struct JwtVerifier {
+    cfg: JwtConfig,
+    client: reqwest::Client,
+    keys: RwLock<Arc<Jwks>>,
+    fetched_at: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl JwtVerifier {
+    async fn new(cfg: JwtConfig, network: Option<&NetworkConfig>) -> Result<Self> {
+        let client = network
+            .cloned()
+            .unwrap_or_default()
+            .client_builder()
+            .await?
+            .build()
+            .context("Failed building JWKS client")?;
+
+        let keys = if let Some(path) = &cfg.jwks_file {
+            let contents = tokio::fs::read_to_string(path)
+                .await
+                .context(format!("Failed reading JWKS file: {path}"))?;
+            serde_json::from_str(&contents).context(format!("Invalid JWKS file: {path}"))?
+        } else {
+            Jwks::default()
+        };
+
+        let verifier = Self {
+            cfg,
+            client,
+            keys: RwLock::new(Arc::new(keys)),
+            fetched_at: tokio::sync::Mutex::new(None),
+        };
+        if verifier.cfg.jwks_url.is_some() {
+            // the identity provider being down must not keep pctx from starting,
+            // keys are fetched again on the first request
+            if let Err(e) = verifier.refresh().await {
+                warn!("{e:#}");
+            }
+        }
+        Ok(verifier)
+    }
+
+    /// Refetches the keys from `jwks_url`, at most once per [`JWKS_MIN_REFRESH_SECS`]
+    async fn refresh(&self) -> Result<bool> {
+        let Some(url) = &self.cfg.jwks_url else {
+            return Ok(false);
+        };
+        let mut fetched_at = self.fetched_at.lock().await;
+        if fetched_at.is_some_and(|at| at.elapsed() < Duration::from_secs(JWKS_MIN_REFRESH_SECS)) {
+            return Ok(false);
+        }
+        *fetched_at = Some(Instant::now());
+
+        debug!("Fetching JWKS from {url}");
+        let jwks: Jwks = self
+            .client
+            .get(url.clone())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context(format!("Failed fetching JWKS from {url}"))?
+            .json()
+            .await
+            .context(format!("Invalid JWKS at {url}"))?;
+        *self.keys.write().unwrap() = Arc::new(jwks);
+        Ok(true)
+    }
+
+    async fn verify(&self, token: &str) -> Result<ClientIdentity, AuthError> {
+        let invalid = |reason: &str| AuthError::Invalid(reason.into());
+
+        let mut segments = token.split('.');
+        let (Some(header_b64), Some(claims_b64), Some(signature_b64), None) = (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) else {
+            return Err(invalid("Malformed token"));
+        };
+        let header: JwtHeader =
+            decode_segment(header_b64).ok_or_else(|| invalid("Malformed token header"))?;
+        let claims: JwtClaims =
+            decode_segment(claims_b64).ok_or_else(|| invalid("Malformed token claims"))?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature_b64)
+            .map_err(|_| invalid("Malformed token signature"))?;
+        let message = &token.as_bytes()[..header_b64.len() + 1 + claims_b64.len()];
+
+        let mut verified = self.check_signature(&header, message, &signature);
+        if verified.is_none() {
+            // the token may be signed with a key published since the last fetch
+            match self.refresh().await {
+                Ok(true) => verified = self.check_signature(&header, message, &signature),
+                Ok(false) => {}
+                Err(e) => warn!("{e:#}"),
+            }
+        }
+        match verified {
+            Some(true) => {}
+            Some(false) => return Err(invalid("Invalid token signature")),
+            None => return Err(invalid("Token signed with an unknown key")),
+        }
+
+        self.check_claims(&claims)?;
+
+        Ok(ClientIdentity {
+            subject: claims.sub.unwrap_or_else(|| "unknown".into()),
+            method: AuthMethod::Jwt,
+        })
+    }
+
+    /// Whether `signature` is valid for one of the keys `header` may refer to,
+    /// `None` if there is no such key
+    fn check_signature(
+        &self,
+        header: &JwtHeader,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Option<bool> {
+        let keys = self.keys.read().unwrap().clone();
+        let mut candidates = keys
+            .keys
+            .iter()
+            .filter(|k| header.kid.is_none() || k.kid == header.kid)
+            .filter(|k| k.alg.as_ref().is_none_or(|alg| *alg == header.alg))
+            .peekable();
+        candidates.peek()?;
+
+        Some(candidates.any(|k| verify_signature(&header.alg, k, message, signature)))
+    }
+
+    fn check_claims(&self, claims: &JwtClaims) -> Result<(), AuthError> {
+        let invalid = |reason: &str| AuthError::Invalid(reason.into());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let leeway = self.cfg.leeway().as_secs();
+
+        if claims.iss.as_deref() != Some(self.cfg.issuer.as_str()) {
+            return Err(invalid("Unexpected token issuer"));
+        }
+        if !claims
+            .aud
+            .as_ref()
+            .is_some_and(|aud| aud.contains(&self.cfg.audience))
+        {
+            return Err(invalid("Unexpected token audience"));
+        }
+        match claims.exp {
+            Some(exp) if exp.saturating_add(leeway) > now => {}
+            Some(_) => return Err(invalid("Token expired")),
+            None => return Err(invalid("Token has no expiration")),
+        }
+        if claims
+            .nbf
+            .is_some_and(|nbf| nbf > now.saturating_add(leeway))
+        {
+            return Err(invalid("Token not valid yet"));
+        }
+        Ok(())
+    }
+}
+
+fn decode_segment<T: serde::de::DeserializeOwned>(segment: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(segment).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Whether `signature` of `message` is valid for `key` with the JWS algorithm `alg`
+fn verify_signature(alg: &str, key: &Jwk, message: &[u8], signature: &[u8]) -> bool {
+    let decode = |v: &Option<String>| v.as_ref().and_then(|v| URL_SAFE_NO_PAD.decode(v).ok());
+
+    match (alg, key.kty.as_str()) {
+        ("RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512", "RSA") => {
+            let (Some(n), Some(e)) = (decode(&key.n), decode(&key.e)) else {
+                return false;
+            };
+            let params = match alg {
+                "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                _ => &signature::RSA_PSS_2048_8192_SHA512,
+            };
+            RsaPublicKeyComponents { n, e }
+                .verify(params, message, signature)
+                .is_ok()
+        }
+        ("ES256" | "ES384", "EC") => {
+            let (Some(x), Some(y)) = (decode(&key.x), decode(&key.y)) else {
+                return false;
+            };
+            let algorithm = match (alg, key.crv.as_deref()) {
+                ("ES256", Some("P-256")) => &signature::ECDSA_P256_SHA256_FIXED,
+                ("ES384", Some("P-384")) => &signature::ECDSA_P384_SHA384_FIXED,
+                _ => return false,
+            };
+            // uncompressed SEC1 point
+            let point = [&[0x04], x.as_slice(), y.as_slice()].concat();
+            UnparsedPublicKey::new(algorithm, point)
+                .verify(message, signature)
+                .is_ok()
+        }
+        ("EdDSA", "OKP") if key.crv.as_deref() == Some("Ed25519") => {
+            let Some(x) = decode(&key.x) else {
+                return false;
+            };
+            UnparsedPublicKey::new(&signature::ED25519, x)
+                .verify(message, signature)
+                .is_ok()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::post};
+    use base64::engine::general_purpose::STANDARD;
+    use ring::{
+        rand::SystemRandom,
+        signature::{
+            ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair, RSA_PKCS1_SHA256, RsaKeyPair,
+        },
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    const ISSUER: &str = "https://auth.example.com/";
+    const AUDIENCE: &str = "pctx";
+    const LEEWAY: u64 = 60;
+    /// PKCS#8 RSA key only used to sign test tokens
+    const RSA_KEY: &str = include_str!("./fixtures/jwt_rsa_key.pem");
+
+    enum SigningKey {
+        Rsa(RsaKeyPair),
+        Ec(EcdsaKeyPair),
+    }
+
+    struct Signer {
+        kid: &'static str,
+        key: SigningKey,
+    }
+
+    impl Signer {
+        fn rsa(kid: &'static str) -> Self {
+            let der: String = RSA_KEY
+                .lines()
+                .filter(|l| !l.starts_with("-----"))
+                .collect();
+            let der = STANDARD.decode(der).unwrap();
+            Self {
+                kid,
+                key: SigningKey::Rsa(RsaKeyPair::from_pkcs8(&der).unwrap()),
+            }
+        }
+
+        fn ec(kid: &'static str) -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let key =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                    .unwrap();
+            Self {
+                kid,
+                key: SigningKey::Ec(key),
+            }
+        }
+
+        fn alg(&self) -> &'static str {
+            match self.key {
+                SigningKey::Rsa(_) => "RS256",
+                SigningKey::Ec(_) => "ES256",
+            }
+        }
+
+        fn jwk(&self) -> Jwk {
+            let mut jwk = Jwk {
+                kty: String::new(),
+                kid: Some(self.kid.into()),
+                alg: None,
+                n: None,
+                e: None,
+                crv: None,
+                x: None,
+                y: None,
+            };
+            match &self.key {
+                SigningKey::Rsa(key) => {
+                    let public = RsaPublicKeyComponents::<Vec<u8>>::from(key.public());
+                    jwk.kty = "RSA".into();
+                    jwk.n = Some(URL_SAFE_NO_PAD.encode(public.n));
+                    jwk.e = Some(URL_SAFE_NO_PAD.encode(public.e));
+                }
+                SigningKey::Ec(key) => {
+                    // uncompressed SEC1 point
+                    let point = key.public_key().as_ref();
+                    jwk.kty = "EC".into();
+                    jwk.crv = Some("P-256".into());
+                    jwk.x = Some(URL_SAFE_NO_PAD.encode(&point[1..33]));
+                    jwk.y = Some(URL_SAFE_NO_PAD.encode(&point[33..]));
+                }
+            }
+            jwk
+        }
+
+        fn sign(&self, header: &Value, claims: &Value) -> String {
+            let message = format!(
+                "{}.{}",
+                URL_SAFE_NO_PAD.encode(header.to_string()),
+                URL_SAFE_NO_PAD.encode(claims.to_string())
+            );
+            let rng = SystemRandom::new();
+            let signature = match &self.key {
+                SigningKey::Rsa(key) => {
+                    let mut signature = vec![0; key.public().modulus_len()];
+                    key.sign(&RSA_PKCS1_SHA256, &rng, message.as_bytes(), &mut signature)
+                        .unwrap();
+                    signature
+                }
+                SigningKey::Ec(key) => key
+                    .sign(&rng, message.as_bytes())
+                    .unwrap()
+                    .as_ref()
+                    .to_vec(),
+            };
+            format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature))
+        }
+
+        fn token(&self, claims: &Value) -> String {
+            self.sign(&json!({ "alg": self.alg(), "kid": self.kid }), claims)
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn claims() -> Value {
+        json!({
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "sub": "alice",
+            "exp": now() + 300,
+        })
+    }
+
+    fn verifier(signers: &[&Signer]) -> JwtVerifier {
+        JwtVerifier {
+            cfg: JwtConfig {
+                jwks_url: None,
+                jwks_file: None,
+                issuer: ISSUER.into(),
+                audience: AUDIENCE.into(),
+                leeway: Some(LEEWAY),
+            },
+            client: reqwest::Client::new(),
+            keys: RwLock::new(Arc::new(Jwks {
+                keys: signers.iter().map(|s| s.jwk()).collect(),
+            })),
+            fetched_at: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    async fn rejection(verifier: &JwtVerifier, token: &str) -> String {
+        match verifier.verify(token).await {
+            Ok(identity) => panic!("token should be rejected, was accepted as {identity}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jwt_valid_signatures() {
+        let rsa = Signer::rsa("rsa");
+        let ec = Signer::ec("ec");
+        let verifier = verifier(&[&rsa, &ec]);
+
+        for signer in [&rsa, &ec] {
+            let identity = verifier.verify(&signer.token(&claims())).await.unwrap();
+            assert_eq!(identity.subject, "alice", "{} token", signer.alg());
+            assert_eq!(identity.method, AuthMethod::Jwt);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jwt_rejects_bad_signatures() {
+        let rsa = Signer::rsa("rsa");
+        let verifier = verifier(&[&rsa]);
+
+        // algorithm the key can't verify
+        let wrong_alg = rsa.sign(&json!({ "alg": "HS256", "kid": "rsa" }), &claims());
+        assert_eq!(
+            rejection(&verifier, &wrong_alg).await,
+            "Invalid token signature"
+        );
+
+        // unsigned
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "none", "kid": "rsa" }).to_string());
+        let payload = URL_SAFE_NO_PAD.encode(claims().to_string());
+        assert_eq!(
+            rejection(&verifier, &format!("{header}.{payload}.")).await,
+            "Invalid token signature"
+        );
+
+        // key missing from the JWKS
+        let unknown = Signer::ec("other").token(&claims());
+        assert_eq!(
+            rejection(&verifier, &unknown).await,
+            "Token signed with an unknown key"
+        );
+
+        // claims changed after signing
+        let token = rsa.token(&claims());
+        let mut segments: Vec<&str> = token.split('.').collect();
+        let mut tampered_claims = claims();
+        tampered_claims["sub"] = json!("mallory");
+        let tampered_payload = URL_SAFE_NO_PAD.encode(tampered_claims.to_string());
+        segments[1] = &tampered_payload;
+        assert_eq!(
+            rejection(&verifier, &segments.join(".")).await,
+            "Invalid token signature"
+        );
+
+        // signature changed
+        let mut signature = URL_SAFE_NO_PAD
+            .decode(token.rsplit('.').next().unwrap())
+            .unwrap();
+        signature[0] ^= 1;
+        let tampered_signature = URL_SAFE_NO_PAD.encode(signature);
+        segments[1] = token.split('.').nth(1).unwrap();
+        segments[2] = &tampered_signature;
+        assert_eq!(
+            rejection(&verifier, &segments.join(".")).await,
+            "Invalid token signature"
+        );
+
+        assert_eq!(rejection(&verifier, "not-a-jwt").await, "Malformed token");
+    }
+
+    #[tokio::test]
+    async fn test_jwt_checks_claims() {
+        let ec = Signer::ec("ec");
+        let verifier = verifier(&[&ec]);
+
+        let mut wrong_issuer = claims();
+        wrong_issuer["iss"] = json!("https://evil.example.com/");
+        assert_eq!(
+            rejection(&verifier, &ec.token(&wrong_issuer)).await,
+            "Unexpected token issuer"
+        );
+
+        let mut wrong_audience = claims();
+        wrong_audience["aud"] = json!(["other", "another"]);
+        assert_eq!(
+            rejection(&verifier, &ec.token(&wrong_audience)).await,
+            "Unexpected token audience"
+        );
+
+        let mut audiences = claims();
+        audiences["aud"] = json!(["other", AUDIENCE]);
+        assert!(verifier.verify(&ec.token(&audiences)).await.is_ok());
+
+        let mut no_expiration = claims();
+        no_expiration.as_object_mut().unwrap().remove("exp");
+        assert_eq!(
+            rejection(&verifier, &ec.token(&no_expiration)).await,
+            "Token has no expiration"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_jwt_leeway() {
+        let ec = Signer::ec("ec");
+        let verifier = verifier(&[&ec]);
+        let half = LEEWAY / 2;
+
+        let mut expired_within_leeway = claims();
+        expired_within_leeway["exp"] = json!(now() - half);
+        assert!(
+            verifier
+                .verify(&ec.token(&expired_within_leeway))
+                .await
+                .is_ok()
+        );
+
+        let mut expired = claims();
+        expired["exp"] = json!(now() - LEEWAY - half);
+        assert_eq!(
+            rejection(&verifier, &ec.token(&expired)).await,
+            "Token expired"
+        );
+
+        let mut not_before_within_leeway = claims();
+        not_before_within_leeway["nbf"] = json!(now() + half);
+        assert!(
+            verifier
+                .verify(&ec.token(&not_before_within_leeway))
+                .await
+                .is_ok()
+        );
+
+        let mut not_before = claims();
+        not_before["nbf"] = json!(now() + LEEWAY + half);
+        assert_eq!(
+            rejection(&verifier, &ec.token(&not_before)).await,
+            "Token not valid yet"
+        );
+    }
+
+    async fn api_key_auth() -> InboundAuth {
+        let cfg: InboundAuthConfig = serde_json::from_value(json!({
+            "api_keys": [{ "name": "ci", "key": "secret-key" }]
+        }))
+        .unwrap();
+        InboundAuth::new(&cfg, None).await.unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                (
+                    header::HeaderName::from_static(name),
+                    HeaderValue::from_str(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_api_keys() {
+        let auth = api_key_auth().await;
+
+        for presented in [
+            headers(&[("x-api-key", "secret-key")]),
+            headers(&[("authorization", "Bearer secret-key")]),
+        ] {
+            let identity = auth.authenticate(&presented).await.unwrap();
+            assert_eq!(identity.subject, "ci");
+            assert_eq!(identity.method, AuthMethod::ApiKey);
+        }
+
+        assert!(matches!(
+            auth.authenticate(&headers(&[("x-api-key", "secret-key2")]))
+                .await,
+            Err(AuthError::Invalid(_))
+        ));
+        assert!(matches!(
+            auth.authenticate(&headers(&[("authorization", "Bearer wrong")]))
+                .await,
+            Err(AuthError::Invalid(_))
+        ));
+        assert!(matches!(
+            auth.authenticate(&HeaderMap::new()).await,
+            Err(AuthError::Missing)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_middleware_rejects_with_challenge() {
+        let router = Router::new()
+            .route("/mcp", post(|| async { "served" }))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(api_key_auth().await),
+                authenticate,
+            ));
+        let request = |key: Option<&str>| {
+            let mut request = axum::http::Request::post("/mcp");
+            if let Some(key) = key {
+                request = request.header(API_KEY_HEADER, key);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let missing = router.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            missing.headers()[header::WWW_AUTHENTICATE],
+            r#"Bearer realm="pctx""#
+        );
+
+        let invalid = router
+            .clone()
+            .oneshot(request(Some("wrong")))
+            .await
+            .unwrap();
+        assert_eq!(invalid.status(), StatusCode::UNAUTHORIZED);
+        let challenge = invalid.headers()[header::WWW_AUTHENTICATE]
+            .to_str()
+            .unwrap();
+        assert!(
+            challenge.contains(r#"error="invalid_token""#),
+            "Unexpected challenge: {challenge}"
+        );
+
+        let served = router.oneshot(request(Some("secret-key"))).await.unwrap();
+        assert_eq!(served.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,526 @@
+//! Live catalog of upstream MCP servers and their tools
+
+use anyhow::Result;
+use deno_executor::MCPConnectionManager;
+use indexmap::IndexMap;
+use log::{debug, error, info, warn};
+use pctx_config::{Config, server::ServerConfig};
+use rmcp::{RoleClient, RoleServer, service::Peer};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::time::Duration;
+use tokio::{
+    task::{JoinHandle, JoinSet},
+    time::{Instant, Interval, MissedTickBehavior},
+};
+
+use crate::mcp::{
+    lock,
+    search::SearchIndex,
+    upstream::{UpstreamMcp, UpstreamTool},
+};
+
+/// Seconds between checks of a watched connection, and to wait before
+/// reconnecting once it was lost
+const RECONNECT_DELAY_SECS: u64 = 5;
+
+/// Config and upstream servers currently served, swapped as a whole on config reloads
+pub(crate) struct CatalogState {
+    pub(crate) config: Config,
+    pub(crate) upstream: Vec<UpstreamMcp>,
+    /// Hosts executions may reach, derived from `upstream`
+    pub(crate) allowed_hosts: Vec<String>,
+    /// Search index of the functions of `upstream`
+    pub(crate) index: SearchIndex,
+}
+
+/// Upstream servers and their tools, shared by every downstream session
+///
+/// Tools are regenerated in place whenever an upstream server reports that
+/// its tool list changed (or on its `refresh_interval`), after which every
+/// connected downstream client gets a `notifications/tools/list_changed`.
+#[derive(Clone)]
+pub(crate) struct UpstreamCatalog {
+    state: Arc<RwLock<CatalogState>>,
+    peers: Arc<Mutex<Vec<Peer<RoleServer>>>>,
+    watchers: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+}
+
+impl UpstreamCatalog {
+    pub(crate) fn new(config: Config, upstream: Vec<UpstreamMcp>) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(CatalogState {
+                allowed_hosts: allowed_hosts(&upstream),
+                index: SearchIndex::new(&upstream),
+                config,
+                upstream,
+            })),
+            peers: Arc::default(),
+            watchers: Arc::default(),
+        }
+    }
+
+    /// Currently served state
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (i.e., a thread panicked while holding the lock)
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, CatalogState> {
+        self.state.read().unwrap()
+    }
+
+    /// Registers a downstream client to be notified about tool changes,
+    /// forgetting the clients that disconnected
+    pub(crate) fn subscribe(&self, peer: Peer<RoleServer>) {
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|p| !p.is_transport_closed());
+        peers.push(peer);
+    }
+
+    /// Replaces the tools of the upstream server `name`, notifying downstream
+    /// clients if anything changed
+    pub(crate) async fn update_tools(&self, name: &str, tools: IndexMap<String, UpstreamTool>) {
+        {
+            let mut state = self.state.write().unwrap();
+            let Some(mcp) = state.upstream.iter_mut().find(|m| m.name == name) else {
+                return;
+            };
+            if mcp.tools == tools && !mcp.stale {
+                debug!("Tools of '{name}' are unchanged");
+                return;
+            }
+            let plural = if tools.len() == 1 { "" } else { "s" };
+            if mcp.stale {
+                info!(
+                    "'{name}' is reachable again, replacing its cached interface with {} tool{plural}",
+                    tools.len()
+                );
+            } else {
+                info!(
+                    "Tools of '{name}' changed, now serving {} tool{plural}",
+                    tools.len()
+                );
+            }
+            mcp.tools = tools;
+            mcp.stale = false;
+            state.index = SearchIndex::new(&state.upstream);
+        }
+
+        self.notify_peers().await;
+    }
+
+    /// Applies a reloaded config, only servers that were added or whose
+    /// configuration changed are reconnected and regenerated
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, leaving the served state untouched, if the config is invalid
+    pub(crate) async fn reload(
+        &self,
+        config: Config,
+        connections: &MCPConnectionManager,
+    ) -> Result<()> {
+        config.validate()?;
+        if json!(self.read().config.inbound_auth) != json!(config.inbound_auth) {
+            warn!("Changes to `inbound_auth` take effect once pctx is restarted");
+        }
+
+        // compare resolved servers so changes to the global settings are picked up
+        let previous: HashMap<String, serde_json::Value> = self
+            .read()
+            .config
+            .resolved_servers()
+            .iter()
+            .map(|s| (s.name.clone(), json!(s)))
+            .collect();
+        let changed: Vec<ServerConfig> = config
+            .resolved_servers()
+            .into_iter()
+            .filter(|s| previous.get(&s.name) != Some(&json!(s)))
+            .collect();
+        let removed: Vec<String> = previous
+            .keys()
+            .filter(|name| config.get_server(name).is_none())
+            .cloned()
+            .collect();
+
+        let mut discovery = JoinSet::new();
+        for server in changed.iter().cloned() {
+            discovery.spawn(async move {
+                let res = UpstreamMcp::discover(&server).await;
+                (server.name, res)
+            });
+        }
+        let mut discovered = HashMap::new();
+        while let Some(joined) = discovery.join_next().await {
+            let (name, res) = joined?;
+            match res {
+                Ok(upstream) => {
+                    discovered.insert(name, upstream);
+                }
+                Err(e) => error!("Failed creating Code Mode interface for '{name}': {e}"),
+            }
+        }
+
+        // stop everything tied to the outdated servers before swapping
+        for name in removed.iter().chain(changed.iter().map(|s| &s.name)) {
+            if let Some(watcher) = self.watchers.lock().unwrap().remove(name) {
+                watcher.abort();
+            }
+            connections.close(name).await;
+        }
+
+        let fresh: Vec<UpstreamMcp> = discovered.values().cloned().collect();
+        lock::record_missing(&config, &fresh);
+
+        let unchanged = config.servers.len() - changed.len();
+        let failed = changed.len() - discovered.len();
+        let mut served = vec![];
+        {
+            let mut state = self.state.write().unwrap();
+            let mut current: HashMap<String, UpstreamMcp> = state
+                .upstream
+                .drain(..)
+                .map(|m| (m.name.clone(), m))
+                .collect();
+            let mut upstream = vec![];
+            for server in &config.servers {
+                if let Some(fresh) = discovered.get(&server.name) {
+                    upstream.push(fresh.clone());
+                    continue;
+                }
+                let Some(previous) = current.remove(&server.name) else {
+                    continue;
+                };
+                let Some(settings) = changed.iter().find(|s| s.name == server.name) else {
+                    upstream.push(previous);
+                    continue;
+                };
+                // keep serving a server that can't be reached with its new
+                // settings, flagged as stale until its watcher refreshes it
+                match previous.into_stale(settings) {
+                    Ok(stale) => {
+                        warn!(
+                            "Serving the previous interface of '{}' until it can be reached",
+                            server.name
+                        );
+                        upstream.push(stale);
+                    }
+                    Err(e) => error!(
+                        "Failed regenerating the previous interface of '{}': {e}",
+                        server.name
+                    ),
+                }
+            }
+            served.extend(upstream.iter().map(|m| m.name.clone()));
+
+            *state = CatalogState {
+                allowed_hosts: allowed_hosts(&upstream),
+                index: SearchIndex::new(&upstream),
+                config,
+                upstream,
+            };
+        }
+
+        for server in changed.iter().filter(|s| served.contains(&s.name)) {
+            self.watch(server, connections);
+        }
+
+        info!(
+            "Config reloaded: {} server{} updated, {failed} failed, {} removed, {unchanged} unchanged",
+            discovered.len(),
+            if discovered.len() == 1 { "" } else { "s" },
+            removed.len(),
+        );
+        if !changed.is_empty() || !removed.is_empty() {
+            self.notify_peers().await;
+        }
+
+        Ok(())
+    }
+
+    /// Spawns a task keeping the tools of `server` up to date, replacing any
+    /// previous one. Servers that neither notify about tool changes nor have a
+    /// `refresh_interval` are not watched.
+    ///
+    /// Tool changes are received on the pooled connection of `connections`
+    /// executions use, the server is not connected to a second time.
+    pub(crate) fn watch(&self, server: &ServerConfig, connections: &MCPConnectionManager) {
+        let watcher = tokio::spawn(
+            self.clone()
+                .refresh_loop(server.clone(), connections.clone()),
+        );
+        if let Some(previous) = self
+            .watchers
+            .lock()
+            .unwrap()
+            .insert(server.name.clone(), watcher)
+        {
+            previous.abort();
+        }
+    }
+
+    /// Stops every refresh task
+    pub(crate) fn shutdown(&self) {
+        for (_, watcher) in self.watchers.lock().unwrap().drain() {
+            watcher.abort();
+        }
+    }
+
+    fn is_stale(&self, name: &str) -> bool {
+        self.read()
+            .upstream
+            .iter()
+            .any(|m| m.name == name && m.stale)
+    }
+
+    async fn notify_peers(&self) {
+        let peers = self.peers.lock().unwrap().clone();
+        for peer in peers {
+            if let Err(e) = peer.notify_tool_list_changed().await {
+                debug!("Failed notifying downstream client about tool changes: {e}");
+            }
+        }
+        self.peers
+            .lock()
+            .unwrap()
+            .retain(|p| !p.is_transport_closed());
+    }
+
+    async fn refresh_loop(self, server: ServerConfig, connections: MCPConnectionManager) {
+        let interval = server.refresh_interval();
+        // subscribed once, notifications of reconnected connections arrive here too
+        let mut changes = connections.tool_changes(&server.name);
+
+        loop {
+            let peer = match connections.peer(&server).await {
+                Ok(peer) => peer,
+                Err(e) => {
+                    debug!(
+                        "Failed subscribing to tool changes of '{}': {e}",
+                        server.name
+                    );
+                    tokio::time::sleep(Duration::from_secs(RECONNECT_DELAY_SECS)).await;
+                    continue;
+                }
+            };
+
+            let notifies = peer
+                .peer_info()
+                .and_then(|info| info.capabilities.tools.as_ref())
+                .and_then(|tools| tools.list_changed)
+                .unwrap_or_default();
+            if !notifies && interval.is_none() {
+                debug!(
+                    "'{}' does not notify about tool changes, not watching it",
+                    server.name
+                );
+                // replace the interface served from the lockfile now that the server is reachable
+                if self.is_stale(&server.name) {
+                    self.refresh(&server, &peer).await;
+                }
+                return;
+            }
+
+            // catch up on changes made while we weren't subscribed
+            self.refresh(&server, &peer).await;
+
+            let mut ticker = interval.map(|d| {
+                let mut ticker = tokio::time::interval_at(Instant::now() + d, d);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                ticker
+            });
+
+            loop {
+                tokio::select! {
+                    () = closed(&peer) => break,
+                    Some(()) = changes.recv() => {
+                        debug!("'{}' notified that its tools changed", server.name);
+                    }
+                    () = tick(ticker.as_mut()) => {}
+                }
+                self.refresh(&server, &peer).await;
+            }
+
+            debug!(
+                "Connection to '{}' was closed, reconnecting to watch its tools",
+                server.name
+            );
+            tokio::time::sleep(Duration::from_secs(RECONNECT_DELAY_SECS)).await;
+        }
+    }
+
+    async fn refresh(&self, server: &ServerConfig, peer: &Peer<RoleClient>) {
+        let tools = match peer.list_all_tools().await {
+            Ok(listed) => UpstreamTool::from_tools(server, listed),
+            Err(e) => Err(e.into()),
+        };
+        match tools {
+            Ok(tools) => self.update_tools(&server.name, tools).await,
+            Err(e) => warn!("Failed refreshing tools of '{}': {e}", server.name),
+        }
+    }
+}
+
+/// Hosts executions need to reach the given upstream servers
+fn allowed_hosts(upstream: &[UpstreamMcp]) -> Vec<String> {
+    upstream
+        .iter()
+        .filter_map(|m| {
+            // stdio upstreams have no host to allow
+            let url = m.url.as_ref()?;
+            let host = url.host_str()?;
+            if let Some(port) = url.port() {
+                Some(format!("{host}:{port}"))
+            } else {
+                let default_port = if url.scheme() == "https" { 443 } else { 80 };
+                Some(format!("{host}:{default_port}"))
+            }
+        })
+        .collect()
+}
+
+/// Waits for the next tick of `ticker`, forever if there is none
+async fn tick(ticker: Option<&mut Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the transport of `peer` to close, checked every [`RECONNECT_DELAY_SECS`]
+async fn closed(peer: &Peer<RoleClient>) {
+    let mut check = tokio::time::interval(Duration::from_secs(RECONNECT_DELAY_SECS));
+    while !peer.is_transport_closed() {
+        check.tick().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+    use indexmap::IndexMap;
+
+    use crate::mcp::upstream::UpstreamTool;
+
+    const MISSING_COMMAND: &str = "pctx-test-command-that-does-not-exist";
+
+    fn config(servers: &serde_json::Value) -> Config {
+        // never written, reloads only record servers that could be discovered
+        let path = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!("pctx-catalog-test-{}", std::process::id()))
+            .join("pctx.json");
+        serde_json::from_value::<Config>(json!({ "name": "test", "servers": servers }))
+            .unwrap()
+            .with_path(&path)
+    }
+
+    fn interface(server: &ServerConfig) -> UpstreamMcp {
+        let listed = ["get_issue", "delete_issue"]
+            .into_iter()
+            .map(|name| {
+                rmcp::model::Tool::new(
+                    name,
+                    format!("Upstream {name}"),
+                    json!({ "type": "object" }).as_object().unwrap().clone(),
+                )
+            })
+            .collect();
+        UpstreamMcp {
+            name: server.name.clone(),
+            namespace: server.namespace(),
+            description: server.name.clone(),
+            url: server.url().cloned(),
+            tools: UpstreamTool::from_tools(server, listed).unwrap(),
+            resources: vec![],
+            prompts: IndexMap::new(),
+            stale: false,
+        }
+    }
+
+    fn tool_names(mcp: &UpstreamMcp) -> Vec<&str> {
+        mcp.tools.keys().map(String::as_str).collect()
+    }
+
+    #[tokio::test]
+    async fn test_reload_diffs_servers() {
+        let previous = config(&json!([
+            { "name": "kept", "url": "http://127.0.0.1:1/mcp" },
+            { "name": "changed", "url": "http://127.0.0.1:1/mcp" },
+            { "name": "removed", "url": "http://127.0.0.1:1/mcp" }
+        ]));
+        let upstream = previous.resolved_servers().iter().map(interface).collect();
+        let catalog = UpstreamCatalog::new(previous, upstream);
+        let connections = MCPConnectionManager::new();
+
+        let reloaded = config(&json!([
+            { "name": "kept", "url": "http://127.0.0.1:1/mcp" },
+            { "name": "changed", "command": MISSING_COMMAND, "exclude_tools": ["delete_*"] },
+            { "name": "added", "command": MISSING_COMMAND }
+        ]));
+        catalog.reload(reloaded, &connections).await.unwrap();
+
+        {
+            let state = catalog.read();
+            let served: Vec<&str> = state.upstream.iter().map(|m| m.name.as_str()).collect();
+            assert_eq!(
+                served,
+                ["kept", "changed"],
+                "Removed servers and added servers that can't be reached should not be served"
+            );
+
+            let kept = &state.upstream[0];
+            assert!(!kept.stale, "Unchanged servers should be left untouched");
+            assert_eq!(tool_names(kept), ["getIssue", "deleteIssue"]);
+
+            let changed = &state.upstream[1];
+            assert!(
+                changed.stale,
+                "Changed servers that can't be reached should be flagged as stale"
+            );
+            assert_eq!(
+                tool_names(changed),
+                ["getIssue"],
+                "Stale interfaces should follow the new tool filters"
+            );
+            assert!(changed.url.is_none());
+        }
+
+        {
+            let watchers = catalog.watchers.lock().unwrap();
+            assert!(
+                watchers.contains_key("changed"),
+                "Changed servers kept stale should be watched until they can be reached"
+            );
+            assert!(!watchers.contains_key("added"));
+            assert!(!watchers.contains_key("removed"));
+        }
+
+        catalog.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_reload_unchanged_config() {
+        let cfg = config(&json!([{ "name": "kept", "url": "http://127.0.0.1:1/mcp" }]));
+        let upstream = cfg.resolved_servers().iter().map(interface).collect();
+        let catalog = UpstreamCatalog::new(cfg.clone(), upstream);
+
+        catalog
+            .reload(cfg, &MCPConnectionManager::new())
+            .await
+            .unwrap();
+
+        let state = catalog.read();
+        assert_eq!(state.upstream.len(), 1);
+        assert!(!state.upstream[0].stale);
+        assert!(
+            catalog.watchers.lock().unwrap().is_empty(),
+            "Unchanged servers should not be reconnected"
+        );
+    }
+}
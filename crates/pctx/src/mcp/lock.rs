@@ -0,0 +1,248 @@
+//! Lockfile caching the generated interfaces of upstream servers
+
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use indexmap::IndexMap;
+use log::{debug, warn};
+use pctx_config::{Config, server::ServerConfig};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+
+use crate::mcp::upstream::UpstreamMcp;
+
+/// Format version of the lockfile, lockfiles of other versions are ignored
+const LOCKFILE_VERSION: u32 = 1;
+
+/// Interfaces of the upstream servers, stored next to the config
+/// (`pctx.json` -> `pctx.lock`) so they can be served while a server is
+/// unreachable
+///
+/// The lockfile is also the baseline `pctx diff` reports drift against, so
+/// recorded interfaces are only replaced by `pctx diff --update`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Lockfile {
+    version: u32,
+    /// Generated interface of each upstream server keyed by server name
+    servers: IndexMap<String, UpstreamMcp>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            servers: IndexMap::new(),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Lockfile path of `cfg`
+    pub(crate) fn path(cfg: &Config) -> Utf8PathBuf {
+        cfg.path().with_extension("lock")
+    }
+
+    /// Loads the lockfile of `cfg`, empty if there is none yet
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the lockfile cannot be read or is invalid
+    pub(crate) fn load(cfg: &Config) -> Result<Self> {
+        let path = Self::path(cfg);
+        if !path.exists() {
+            debug!("No lockfile at {path}");
+            return Ok(Self::default());
+        }
+
+        let contents =
+            fs::read_to_string(&path).context(format!("Failed reading lockfile: {path}"))?;
+        let lock: Self =
+            serde_json::from_str(&contents).context(format!("Failed loading lockfile: {path}"))?;
+        if lock.version != LOCKFILE_VERSION {
+            warn!(
+                "Ignoring lockfile {path} of unsupported version {}",
+                lock.version
+            );
+            return Ok(Self::default());
+        }
+
+        Ok(lock)
+    }
+
+    /// Saves the lockfile next to the config of `cfg`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the lockfile cannot be written
+    pub(crate) fn save(&self, cfg: &Config) -> Result<()> {
+        let dest = Self::path(cfg);
+        debug!("Saving lockfile to {dest}");
+        let contents = serde_json::to_string_pretty(self).unwrap_or(json!(self).to_string());
+
+        fs::write(&dest, contents).context(format!("Failed writing lockfile: {dest}"))?;
+
+        Ok(())
+    }
+
+    /// Records freshly discovered servers, forgetting servers `cfg` no longer has
+    pub(crate) fn update(&mut self, cfg: &Config, discovered: &[UpstreamMcp]) {
+        self.servers
+            .retain(|name, _| cfg.get_server(name).is_some());
+        for upstream in discovered.iter().filter(|u| !u.stale) {
+            self.servers.insert(upstream.name.clone(), upstream.clone());
+        }
+        self.sort(cfg);
+    }
+
+    /// Records freshly discovered servers that have no recorded interface yet,
+    /// returning whether any was added
+    pub(crate) fn add_missing(&mut self, cfg: &Config, discovered: &[UpstreamMcp]) -> bool {
+        let mut added = false;
+        for upstream in discovered.iter().filter(|u| !u.stale) {
+            if !self.servers.contains_key(&upstream.name) {
+                self.servers.insert(upstream.name.clone(), upstream.clone());
+                added = true;
+            }
+        }
+        self.sort(cfg);
+        added
+    }
+
+    /// Orders the recorded servers like `cfg`
+    fn sort(&mut self, cfg: &Config) {
+        self.servers
+            .sort_by_cached_key(|name, _| cfg.servers.iter().position(|s| &s.name == name));
+    }
+
+    /// Recorded interface of the server `name`
+    pub(crate) fn get(&self, name: &str) -> Option<&UpstreamMcp> {
+        self.servers.get(name)
+    }
+
+    /// Recorded servers `cfg` no longer has
+    pub(crate) fn removed<'a>(&'a self, cfg: &'a Config) -> impl Iterator<Item = &'a UpstreamMcp> {
+        self.servers
+            .iter()
+            .filter(|(name, _)| cfg.get_server(name).is_none())
+            .map(|(_, upstream)| upstream)
+    }
+
+    /// Cached interface of `server`, regenerated with its current settings
+    /// and marked as stale, see [`UpstreamMcp::into_stale`]
+    pub(crate) fn cached(&self, server: &ServerConfig) -> Option<UpstreamMcp> {
+        let recorded = self.servers.get(&server.name)?.clone();
+        recorded
+            .into_stale(server)
+            .inspect_err(|e| {
+                warn!(
+                    "Failed regenerating the cached interface of '{}': {e}",
+                    server.name
+                );
+            })
+            .ok()
+    }
+}
+
+/// Records freshly discovered servers in the lockfile of `cfg`, replacing
+/// their recorded interfaces, failures are only logged
+pub(crate) fn record(cfg: &Config, discovered: &[UpstreamMcp]) {
+    let mut lock = Lockfile::load(cfg).unwrap_or_else(|e| {
+        warn!("{e:#}, recreating it");
+        Lockfile::default()
+    });
+    lock.update(cfg, discovered);
+    if let Err(e) = lock.save(cfg) {
+        warn!("Failed updating lockfile: {e:#}");
+    }
+}
+
+/// Records the freshly discovered servers missing from the lockfile of
+/// `cfg`, recorded interfaces are kept as the baseline of `pctx diff`,
+/// failures are only logged
+pub(crate) fn record_missing(cfg: &Config, discovered: &[UpstreamMcp]) {
+    let mut lock = match Lockfile::load(cfg) {
+        Ok(lock) => lock,
+        Err(e) => {
+            // don't overwrite a lockfile that may only be broken by a merge conflict
+            warn!("{e:#}, not updating it");
+            return;
+        }
+    };
+    if lock.add_missing(cfg, discovered)
+        && let Err(e) = lock.save(cfg)
+    {
+        warn!("Failed updating lockfile: {e:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::upstream::UpstreamTool;
+
+    fn server(settings: serde_json::Value) -> ServerConfig {
+        let mut cfg =
+            json!({ "name": "github", "url": "https://mcp.example.com/mcp?token=secret" });
+        cfg.as_object_mut()
+            .unwrap()
+            .extend(serde_json::from_value::<serde_json::Map<_, _>>(settings).unwrap());
+        serde_json::from_value(cfg).unwrap()
+    }
+
+    fn recorded(server: &ServerConfig) -> UpstreamMcp {
+        let listed = ["get_issue", "delete_issue"]
+            .into_iter()
+            .map(|name| {
+                rmcp::model::Tool::new(
+                    name,
+                    format!("Upstream {name}"),
+                    json!({ "type": "object" }).as_object().unwrap().clone(),
+                )
+            })
+            .collect();
+        UpstreamMcp {
+            name: server.name.clone(),
+            namespace: server.namespace(),
+            description: "GitHub".into(),
+            url: server.url().cloned(),
+            tools: UpstreamTool::from_tools(server, listed).unwrap(),
+            resources: vec![],
+            prompts: IndexMap::new(),
+            stale: false,
+        }
+    }
+
+    #[test]
+    fn test_cached_applies_current_settings() {
+        let mut lock = Lockfile::default();
+        lock.servers
+            .insert("github".into(), recorded(&server(json!({}))));
+
+        let current = server(json!({
+            "exclude_tools": ["delete_*"],
+            "tools": { "get_issue": { "name": "fetchIssue", "description": "Fetches an issue" } }
+        }));
+        let cached = lock.cached(&current).unwrap();
+
+        assert!(cached.stale);
+        let names: Vec<&str> = cached.tools.keys().map(String::as_str).collect();
+        assert_eq!(names, ["fetchIssue"], "Excluded tools should not come back");
+        assert_eq!(
+            cached.tools["fetchIssue"].description.as_deref(),
+            Some("Fetches an issue")
+        );
+    }
+
+    #[test]
+    fn test_lockfile_omits_connection() {
+        let mut lock = Lockfile::default();
+        lock.servers
+            .insert("github".into(), recorded(&server(json!({}))));
+
+        let saved = serde_json::to_string(&lock).unwrap();
+        assert!(
+            !saved.contains("secret") && !saved.contains("mcp.example.com"),
+            "Lockfile should not record the server's URL: {saved}"
+        );
+    }
+}
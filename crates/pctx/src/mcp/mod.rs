@@ -1,14 +1,23 @@
-// pub(crate) mod client;
+pub(crate) mod auth;
+pub(crate) mod catalog;
+pub(crate) mod lock;
+pub(crate) mod reload;
+pub(crate) mod search;
 pub(crate) mod tools;
 pub(crate) mod upstream;
 
 use anyhow::Result;
-use log::info;
+use deno_executor::{MCPConnectionManager, SessionLimit};
+use log::{info, warn};
 use pctx_config::Config;
-use rmcp::transport::{
-    StreamableHttpServerConfig,
-    streamable_http_server::{StreamableHttpService, session::local::LocalSessionManager},
+use rmcp::{
+    ServiceExt,
+    transport::{
+        StreamableHttpServerConfig,
+        streamable_http_server::{StreamableHttpService, session::local::LocalSessionManager},
+    },
 };
+use std::{net::IpAddr, sync::Arc, time::Duration};
 use tabled::{
     Table,
     builder::Builder,
@@ -23,72 +32,129 @@ use terminal_size::terminal_size;
 
 use crate::utils::{LOGO, styles::fmt_dimmed};
 use crate::{
-    mcp::{tools::PtcxTools, upstream::UpstreamMcp},
+    mcp::{auth::InboundAuth, catalog::UpstreamCatalog, tools::PtcxTools, upstream::UpstreamMcp},
     utils::styles::fmt_cyan,
 };
 
+/// Transport the pctx MCP server is served over
+pub(crate) enum Transport {
+    /// Streamable HTTP at `http://<host>:<port>/mcp`
+    Http { host: String, port: u16 },
+    /// stdin/stdout, for clients launching pctx as a subprocess
+    Stdio,
+}
+
 pub(crate) struct PctxMcp {
     config: Config,
     upstream: Vec<UpstreamMcp>,
-    host: String,
-    port: u16,
+    transport: Transport,
+    /// Inactivity after which a session's sandbox is discarded
+    session_timeout: Duration,
+    /// Maximum number of sessions whose sandbox is kept at the same time
+    max_sessions: usize,
 }
 
 impl PctxMcp {
-    pub(crate) fn new(config: Config, upstream: Vec<UpstreamMcp>, host: &str, port: u16) -> Self {
+    pub(crate) fn new(
+        config: Config,
+        upstream: Vec<UpstreamMcp>,
+        transport: Transport,
+        session_timeout: Duration,
+        max_sessions: usize,
+    ) -> Self {
         Self {
             config,
             upstream,
-            host: host.into(),
-            port,
+            transport,
+            session_timeout,
+            max_sessions,
         }
     }
 
     pub(crate) async fn serve(&self) -> Result<()> {
-        let allowed_hosts = self
-            .upstream
-            .iter()
-            .filter_map(|m| {
-                let host = m.url.host_str()?;
-                if let Some(port) = m.url.port() {
-                    Some(format!("{host}:{port}"))
-                } else {
-                    let default_port = if m.url.scheme() == "https" { 443 } else { 80 };
-                    Some(format!("{host}:{default_port}"))
-                }
-            })
-            .collect::<Vec<_>>();
-
         self.banner();
 
-        let tools = PtcxTools::new(self.config.clone(), allowed_hosts.clone())
-            .with_upstream_mcps(self.upstream.clone());
-        let service = StreamableHttpService::new(
-            move || Ok(tools.clone()),
-            LocalSessionManager::default().into(),
-            StreamableHttpServerConfig {
-                stateful_mode: false,
-                ..Default::default()
-            },
+        // upstream connections are shared by every execution for the lifetime of the server
+        let connections = MCPConnectionManager::new();
+        let catalog = UpstreamCatalog::new(self.config.clone(), self.upstream.clone());
+        for server in &self.config.resolved_servers() {
+            if self.upstream.iter().any(|m| m.name == server.name) {
+                catalog.watch(server, &connections);
+            }
+        }
+        let config_watcher = tokio::spawn(reload::watch_config(
+            self.config.path(),
+            catalog.clone(),
+            connections.clone(),
+        ));
+
+        let tools = PtcxTools::new(
+            catalog.clone(),
+            connections.clone(),
+            self.session_timeout,
+            SessionLimit::new(self.max_sessions),
         );
+        match &self.transport {
+            Transport::Http { host, port } => {
+                // sessions are kept so clients can be notified when upstream tools
+                // change, and each gets its own sandbox
+                let service = StreamableHttpService::new(
+                    move || Ok(tools.for_session()),
+                    LocalSessionManager::default().into(),
+                    StreamableHttpServerConfig {
+                        stateful_mode: true,
+                        ..Default::default()
+                    },
+                );
 
-        let router = axum::Router::new().nest_service("/mcp", service);
-        let tcp_listener =
-            tokio::net::TcpListener::bind(format!("{}:{}", &self.host, self.port)).await?;
+                let mut router = axum::Router::new().nest_service("/mcp", service);
+                // built once, config reloads can't change `inbound_auth` (see `UpstreamCatalog::reload`)
+                if let Some(cfg) = &self.config.inbound_auth {
+                    let auth = InboundAuth::new(cfg, self.config.network.as_ref()).await?;
+                    info!("Clients must authenticate with {}", auth.methods());
+                    router = router.layer(axum::middleware::from_fn_with_state(
+                        Arc::new(auth),
+                        auth::authenticate,
+                    ));
+                } else if !is_loopback(host) {
+                    warn!(
+                        "Serving on {host} without `inbound_auth`, anyone reaching this port can use the upstream servers' credentials"
+                    );
+                }
+                let tcp_listener = tokio::net::TcpListener::bind(format!("{host}:{port}")).await?;
 
-        let _ = axum::serve(tcp_listener, router)
-            .with_graceful_shutdown(async {
-                tokio::signal::ctrl_c()
-                    .await
-                    .expect("failed graceful shutdown");
-            })
-            .await;
+                let _ = axum::serve(tcp_listener, router)
+                    .with_graceful_shutdown(async {
+                        tokio::signal::ctrl_c()
+                            .await
+                            .expect("failed graceful shutdown");
+                    })
+                    .await;
+            }
+            Transport::Stdio => {
+                let service = tools.serve(rmcp::transport::stdio()).await?;
+                // the client closing stdin ends the session
+                tokio::select! {
+                    res = service.waiting() => {
+                        res?;
+                    }
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+        }
+
+        config_watcher.abort();
+        catalog.shutdown();
+        connections.close_all().await;
 
         Ok(())
     }
 
     fn banner(&self) {
-        let mcp_url = format!("http://{}:{}/mcp", self.host, self.port);
+        let mcp_url = match &self.transport {
+            Transport::Http { host, port } => format!("http://{host}:{port}/mcp"),
+            Transport::Stdio => "stdio".to_string(),
+        };
         let logo_max_length = LOGO
             .lines()
             .map(|line| line.chars().count())
@@ -105,7 +171,14 @@ impl PctxMcp {
             builder.push_record(["🌎 Server URL", &mcp_url]);
             builder.push_record([
                 "🔨 Tools",
-                &["list_functions", "get_function_details", "execute"].join(", "),
+                &[
+                    "list_functions",
+                    "search_functions",
+                    "get_function_details",
+                    "execute",
+                    "reset_session",
+                ]
+                .join(", "),
             ]);
             builder.push_record([
                 "📖 Docs",
@@ -169,7 +242,15 @@ impl PctxMcp {
 
             info!("\n{banner}\n");
         } else {
-            info!("PCTX listening at {mcp_url}...");
+            match self.transport {
+                Transport::Http { .. } => info!("PCTX listening at {mcp_url}..."),
+                Transport::Stdio => info!("PCTX serving over stdio..."),
+            }
         }
     }
 }
+
+/// Whether `host` only accepts connections from this machine
+fn is_loopback(host: &str) -> bool {
+    host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
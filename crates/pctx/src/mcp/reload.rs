@@ -0,0 +1,52 @@
+//! Hot-reloading of the config file while the server is running
+
+use camino::Utf8PathBuf;
+use deno_executor::MCPConnectionManager;
+use log::{debug, error, info};
+use pctx_config::Config;
+use std::time::{Duration, SystemTime};
+use tokio::time::MissedTickBehavior;
+
+use crate::mcp::catalog::UpstreamCatalog;
+
+/// Seconds between checks of the config file for changes
+const CONFIG_POLL_INTERVAL_SECS: u64 = 1;
+
+/// Watches the config file at `path`, applying every change to `catalog`
+///
+/// Invalid configs are logged and ignored, leaving the served state untouched.
+pub(crate) async fn watch_config(
+    path: Utf8PathBuf,
+    catalog: UpstreamCatalog,
+    connections: MCPConnectionManager,
+) {
+    let mut last_modified = modified(&path);
+    let mut ticker = tokio::time::interval(Duration::from_secs(CONFIG_POLL_INTERVAL_SECS));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        let current = modified(&path);
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        info!("Config {path} changed, reloading...");
+        let res = match Config::load(&path) {
+            Ok(cfg) => catalog.reload(cfg, &connections).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            error!("Failed reloading config, keeping the previous one: {e:#}");
+        }
+    }
+}
+
+fn modified(path: &Utf8PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .inspect_err(|e| debug!("Failed reading modification time of {path}: {e}"))
+        .ok()
+}
@@ -0,0 +1,295 @@
+//! Ranked keyword search over the functions of upstream servers
+
+use indexmap::IndexSet;
+
+use crate::mcp::upstream::UpstreamMcp;
+
+/// BM25 term frequency saturation
+const K1: f64 = 1.2;
+/// BM25 document length normalization
+const B: f64 = 0.75;
+/// Times the terms of names and titles are counted, so they outrank
+/// description matches
+const NAME_BOOST: usize = 2;
+/// Weight of a term that only starts with a query term (e.g. `issue` in `issues`)
+const PREFIX_WEIGHT: f64 = 0.5;
+/// Shortest query term also matched as a prefix
+const MIN_PREFIX_LEN: usize = 3;
+
+/// Function matching a search, best matches have the highest score
+pub(crate) struct SearchHit {
+    pub(crate) namespace: String,
+    pub(crate) fn_name: String,
+    pub(crate) score: f64,
+}
+
+struct Document {
+    namespace: String,
+    fn_name: String,
+    terms: Vec<String>,
+}
+
+/// BM25 index of the names, titles and descriptions of upstream tools,
+/// rebuilt whenever the tools of an upstream server change
+#[derive(Default)]
+pub(crate) struct SearchIndex {
+    documents: Vec<Document>,
+    avg_len: f64,
+}
+
+impl SearchIndex {
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn new(upstream: &[UpstreamMcp]) -> Self {
+        let mut documents = vec![];
+        for mcp in upstream {
+            for tool in mcp.tools.values() {
+                let names = [
+                    Some(&mcp.namespace),
+                    Some(&tool.fn_name),
+                    Some(&tool.tool_name),
+                    tool.title.as_ref(),
+                ];
+                let mut terms = vec![];
+                for _ in 0..NAME_BOOST {
+                    terms.extend(names.iter().flatten().flat_map(|n| tokenize(n)));
+                }
+                if let Some(description) = &tool.description {
+                    terms.extend(tokenize(description));
+                }
+
+                documents.push(Document {
+                    namespace: mcp.namespace.clone(),
+                    fn_name: tool.fn_name.clone(),
+                    terms,
+                });
+            }
+        }
+
+        let total_len: usize = documents.iter().map(|d| d.terms.len()).sum();
+        let avg_len = if documents.is_empty() {
+            0.0
+        } else {
+            total_len as f64 / documents.len() as f64
+        };
+
+        Self { documents, avg_len }
+    }
+
+    /// Up to `limit` functions matching `query`, best match first
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let query_terms: IndexSet<String> = tokenize(query).collect();
+        let num_docs = self.documents.len() as f64;
+
+        let mut scores = vec![0.0; self.documents.len()];
+        for query_term in &query_terms {
+            let frequencies: Vec<f64> = self
+                .documents
+                .iter()
+                .map(|d| term_frequency(&d.terms, query_term))
+                .collect();
+            let matching = frequencies.iter().filter(|tf| **tf > 0.0).count() as f64;
+            if matching == 0.0 {
+                continue;
+            }
+
+            let idf = ((num_docs - matching + 0.5) / (matching + 0.5) + 1.0).ln();
+            for ((score, tf), doc) in scores.iter_mut().zip(&frequencies).zip(&self.documents) {
+                if *tf > 0.0 {
+                    let norm = 1.0 - B + B * doc.terms.len() as f64 / self.avg_len;
+                    *score += idf * tf * (K1 + 1.0) / (tf + K1 * norm);
+                }
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = self
+            .documents
+            .iter()
+            .zip(scores)
+            .filter(|(_, score)| *score > 0.0)
+            .map(|(doc, score)| SearchHit {
+                namespace: doc.namespace.clone(),
+                fn_name: doc.fn_name.clone(),
+                score,
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+/// Occurrences of `query_term` in `terms`, terms it is only a prefix of
+/// count for [`PREFIX_WEIGHT`]
+fn term_frequency(terms: &[String], query_term: &str) -> f64 {
+    terms
+        .iter()
+        .map(|t| {
+            if t == query_term {
+                1.0
+            } else if query_term.len() >= MIN_PREFIX_LEN && t.starts_with(query_term) {
+                PREFIX_WEIGHT
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+/// Lowercase words of `text`, identifiers are split on `_`, `-` and camelCase
+/// boundaries (`searchIssues` -> `search`, `issues`)
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .flat_map(split_camel_case)
+        .filter(|w| w.chars().count() > 1)
+        .map(str::to_lowercase)
+}
+
+fn split_camel_case(word: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut prev_lower = false;
+    for (i, c) in word.char_indices() {
+        if c.is_uppercase() && prev_lower {
+            parts.push(&word[start..i]);
+            start = i;
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+    }
+    parts.push(&word[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::upstream::UpstreamTool;
+    use indexmap::IndexMap;
+    use pctx_config::server::ServerConfig;
+    use serde_json::json;
+
+    fn upstream(name: &str, tools: &[(&str, &str)]) -> UpstreamMcp {
+        let server: ServerConfig =
+            serde_json::from_value(json!({ "name": name, "url": "https://mcp.example.com/mcp" }))
+                .unwrap();
+        let listed = tools
+            .iter()
+            .map(|(name, description)| {
+                rmcp::model::Tool::new(
+                    (*name).to_string(),
+                    (*description).to_string(),
+                    json!({ "type": "object" }).as_object().unwrap().clone(),
+                )
+            })
+            .collect();
+        UpstreamMcp {
+            name: server.name.clone(),
+            namespace: server.namespace(),
+            description: name.into(),
+            url: None,
+            tools: UpstreamTool::from_tools(&server, listed).unwrap(),
+            resources: vec![],
+            prompts: IndexMap::new(),
+            stale: false,
+        }
+    }
+
+    fn index() -> SearchIndex {
+        SearchIndex::new(&[
+            upstream(
+                "github",
+                &[
+                    ("search_issues", "Search issues in a repository"),
+                    ("create_issue", "Create a new issue"),
+                    (
+                        "list_pull_requests",
+                        "List pull requests, including those closing issues",
+                    ),
+                ],
+            ),
+            upstream("slack", &[("post_message", "Post a message to a channel")]),
+        ])
+    }
+
+    fn found(hits: &[SearchHit]) -> Vec<String> {
+        hits.iter()
+            .map(|h| format!("{}.{}", h.namespace, h.fn_name))
+            .collect()
+    }
+
+    #[test]
+    fn test_search_ranking() {
+        let index = index();
+
+        let hits = index.search("search issues", 10);
+        assert_eq!(
+            found(&hits),
+            ["Github.searchIssues", "Github.listPullRequests"],
+            "`issues` should not match the shorter `issue`"
+        );
+        assert!(
+            hits.windows(2).all(|w| w[0].score >= w[1].score),
+            "Hits should be ordered by score"
+        );
+
+        // names outrank descriptions
+        let hits = index.search("issue", 10);
+        assert_eq!(found(&hits).last().unwrap(), "Github.listPullRequests");
+
+        assert_eq!(found(&index.search("message", 10)), ["Slack.postMessage"]);
+        assert!(index.search("deploy", 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_identifier_queries() {
+        let index = index();
+
+        for query in [
+            "createIssue",
+            "create_issue",
+            "create-issue",
+            "CREATE ISSUE",
+        ] {
+            let hits = index.search(query, 10);
+            assert_eq!(
+                found(&hits).first().map(String::as_str),
+                Some("Github.createIssue"),
+                "Query {query:?} should match `create_issue` first"
+            );
+        }
+        assert_eq!(
+            found(&index.search("Github.listPullRequests", 1)),
+            ["Github.listPullRequests"]
+        );
+    }
+
+    #[test]
+    fn test_search_empty_query() {
+        let index = index();
+        assert!(index.search("", 10).is_empty());
+        assert!(index.search("  _ - . ", 10).is_empty());
+        // single characters are not indexed
+        assert!(index.search("a", 10).is_empty());
+
+        assert!(SearchIndex::new(&[]).search("issue", 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_limit() {
+        let index = index();
+        assert_eq!(index.search("issue", 10).len(), 3);
+        assert_eq!(
+            found(&index.search("search issues", 1)),
+            ["Github.searchIssues"]
+        );
+        assert!(index.search("issue", 0).is_empty());
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = |text| tokenize(text).collect::<Vec<_>>();
+        assert_eq!(tokens("searchIssues"), ["search", "issues"]);
+        assert_eq!(tokens("search_issues"), ["search", "issues"]);
+        assert_eq!(tokens("getHTTPResponse v2"), ["get", "httpresponse", "v2"]);
+    }
+}
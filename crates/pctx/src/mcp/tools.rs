@@ -1,43 +1,73 @@
 use anyhow::Result;
-use codegen::generate_docstring;
+use deno_executor::{ExecuteOptions, MCPConnectionManager, Session, SessionLimit, ToolProgress};
 use indexmap::{IndexMap, IndexSet};
-use log::info;
-use pctx_config::Config;
+use log::{debug, info};
+use pctx_config::server::ServerConfig;
 use rmcp::{
     ErrorData as McpError, ServerHandler,
-    handler::server::{router::tool::ToolRouter, wrapper::Parameters},
+    handler::server::{router::tool::ToolRouter, tool::ToolCallContext, wrapper::Parameters},
     model::{
-        CallToolResult, Content, Implementation, ProtocolVersion, ServerCapabilities, ServerInfo,
+        AnnotateAble, CallToolRequestParam, CallToolResult, Content, Implementation,
+        ListToolsResult, PaginatedRequestParam, ProgressNotificationParam, ProgressToken,
+        ProtocolVersion, RawAudioContent, RawContent, ServerCapabilities, ServerInfo, Tool,
     },
-    schemars, tool, tool_handler, tool_router,
+    schemars,
+    service::{NotificationContext, Peer, RequestContext, RoleServer},
+    tool, tool_router,
 };
 use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{Mutex, mpsc};
 
-use crate::mcp::upstream::UpstreamMcp;
+use crate::mcp::{
+    auth::{ClientIdentity, forwarded_credentials},
+    catalog::UpstreamCatalog,
+};
 
 type McpResult<T> = Result<T, McpError>;
 
+/// Separates the namespace from the upstream tool name in passthrough tool
+/// names (e.g. `Notion__search`)
+const PASSTHROUGH_SEPARATOR: &str = "__";
+
+/// Functions returned by `search_functions` unless the agent sets a `limit`
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
 #[derive(Clone)]
 pub(crate) struct PtcxTools {
-    config: Config,
-    allowed_hosts: Vec<String>,
-    upstream: Vec<UpstreamMcp>,
+    catalog: UpstreamCatalog,
+    connections: MCPConnectionManager,
+    /// Sandbox of the MCP session, started by its first `execute`
+    session: Arc<Mutex<Option<Session>>>,
+    session_timeout: Duration,
+    /// Shared by the sandboxes of every MCP session
+    session_limit: SessionLimit,
     tool_router: ToolRouter<PtcxTools>,
 }
 #[tool_router]
 impl PtcxTools {
-    pub(crate) fn new(config: Config, allowed_hosts: Vec<String>) -> Self {
+    pub(crate) fn new(
+        catalog: UpstreamCatalog,
+        connections: MCPConnectionManager,
+        session_timeout: Duration,
+        session_limit: SessionLimit,
+    ) -> Self {
         Self {
-            config,
-            allowed_hosts,
-            upstream: vec![],
+            catalog,
+            connections,
+            session: Arc::default(),
+            session_timeout,
+            session_limit,
             tool_router: Self::tool_router(),
         }
     }
 
-    pub(crate) fn with_upstream_mcps(mut self, upstream: Vec<UpstreamMcp>) -> Self {
-        self.upstream = upstream;
-        self
+    /// Handler of a new MCP session, sharing everything but the sandbox
+    pub(crate) fn for_session(&self) -> Self {
+        Self {
+            session: Arc::default(),
+            ..self.clone()
+        }
     }
 
     #[tool(
@@ -49,21 +79,27 @@ impl PtcxTools {
         2. Then call get_function_details() for specific functions you need to understand
         3. Finally call execute() to run your TypeScript code
 
-        This returns function signatures without full details."
+        This returns function signatures without full details. Servers exposing resources or prompts
+        also get 'Namespace.resources.read(uri)' and 'Namespace.prompts.promptName(args)' functions.
+        If you already know what you are looking for, search_functions() returns fewer tokens."
     )]
     async fn list_functions(&self) -> McpResult<CallToolResult> {
         let namespaces: Vec<String> = self
+            .catalog
+            .read()
             .upstream
             .iter()
             .map(|m| {
-                let fns: Vec<String> = m.tools.iter().map(|(_, t)| t.fn_signature(false)).collect();
+                let mut fns: Vec<String> =
+                    m.tools.iter().map(|(_, t)| t.fn_signature(false)).collect();
+                fns.extend(m.nested_namespaces(false));
 
                 format!(
                     "{docstring}
 namespace {namespace} {{
   {fns}
 }}",
-                    docstring = generate_docstring(&m.description),
+                    docstring = m.docstring(),
                     namespace = &m.namespace,
                     fns = fns.join("\n\n")
                 )
@@ -77,6 +113,61 @@ namespace {namespace} {{
         )]))
     }
 
+    #[tool(
+        title = "Search Functions",
+        description = "Search the available functions by keywords, returning the best matches first.
+
+        WHEN TO USE: Instead of list_functions() when you know what you need (e.g. 'create issue', 'send slack message'),
+        especially when many functions are available.
+
+        This returns one-line signatures ranked by relevance score. Call get_function_details() for the
+        functions you plan to use before calling execute()."
+    )]
+    async fn search_functions(
+        &self,
+        Parameters(SearchFunctionsInput { query, limit }): Parameters<SearchFunctionsInput>,
+    ) -> McpResult<CallToolResult> {
+        let state = self.catalog.read();
+        let hits = state
+            .index
+            .search(&query, limit.unwrap_or(DEFAULT_SEARCH_LIMIT));
+
+        let mut matches = vec![];
+        for hit in hits {
+            let Some(tool) = state
+                .upstream
+                .iter()
+                .find(|m| m.namespace == hit.namespace)
+                .and_then(|m| m.tools.get(&hit.fn_name))
+            else {
+                continue;
+            };
+            let summary = tool
+                .description
+                .as_deref()
+                .and_then(|d| d.lines().find(|l| !l.trim().is_empty()))
+                .unwrap_or_default()
+                .trim();
+            matches.push(format!(
+                "// score {score:.2}{sep}{summary}\n{namespace}.{fn_name}(input: {input}): Promise<{output}>",
+                score = hit.score,
+                sep = if summary.is_empty() { "" } else { " - " },
+                namespace = hit.namespace,
+                fn_name = tool.fn_name,
+                input = tool.input_type,
+                output = tool.output_type,
+            ));
+        }
+
+        let content = if matches.is_empty() {
+            format!("No functions match \"{query}\", try other keywords or list_functions()")
+        } else {
+            matches.join("\n\n")
+        };
+
+        Ok(CallToolResult::success(vec![Content::text(content)]))
+    }
+
     #[tool(
         title = "Get Function Details",
         description = "Get detailed information about specific functions you want to use.
@@ -114,7 +205,8 @@ namespace {namespace} {{
         let mut namespace_details = vec![];
 
         for (namespace, functions) in by_namespace {
-            if let Some(mcp) = self.upstream.iter().find(|m| m.namespace == namespace) {
+            let state = self.catalog.read();
+            if let Some(mcp) = state.upstream.iter().find(|m| m.namespace == namespace) {
                 let mut fn_details = vec![];
                 for fn_name in functions {
                     if let Some(tool) = mcp.tools.get(&fn_name) {
@@ -128,7 +220,7 @@ namespace {namespace} {{
 namespace {namespace} {{
   {fns}
 }}",
-                        docstring = generate_docstring(&mcp.description),
+                        docstring = mcp.docstring(),
                         namespace = &mcp.namespace,
                         fns = fn_details.join("\n\n")
                     ));
@@ -167,8 +259,11 @@ namespace {namespace} {{
         IMPORTANT RULES:
         - Functions MUST be called as 'Namespace.functionName' (e.g., 'Notion.apiPostSearch')
         - Only functions from list_functions() are available - no fetch(), fs, or other Node/Deno APIs
-        - Variables don't persist between execute() calls - return or log anything you need later
+        - Top-level const/let/function/class declarations (outside run(), top-level await is allowed) and values stored
+          on globalThis persist between execute() calls (e.g. const rows = await Notion.apiPostSearch({}); later: rows.length)
+          until reset_session() or a period of inactivity - return or log anything else you need later
         - Add console.log() statements between API calls to track progress if errors occur
+        - Failed calls throw an MCPToolError (server, tool, content, isTransportError, code) - use try/catch to recover
         - Code runs in an isolated Deno sandbox with restricted network access
 
         RETURN TYPE NOTE:
@@ -177,30 +272,38 @@ namespace {namespace} {{
         - Do NOT call JSON.parse() on results - they're already objects
         - Access properties directly (e.g., result.data) or inspect with console.log() first
         - If you see 'Promise<any>', the structure is unknown - log it to see what's returned
+        - Tool results made of several content blocks are arrays of { type: 'text' | 'image' | 'audio' | 'resource' | 'resource_link', ... }
+        - Returning an image or audio block (e.g. return images[0]) attaches it to the response instead of inlining its base64 data
         "
     )]
     async fn execute(
         &self,
         Parameters(ExecuteInput { code }): Parameters<ExecuteInput>,
+        context: RequestContext<RoleServer>,
     ) -> McpResult<CallToolResult> {
-        let registrations = self
-            .upstream
-            .iter()
-            .map(|m| format!("registerMCP({});", &m.registration))
-            .collect::<Vec<String>>()
-            .join("\n\n");
-        let namespaces = self
-            .upstream
+        let (upstream, allowed_hosts, network, servers) = {
+            let state = self.catalog.read();
+            (
+                state.upstream.clone(),
+                state.allowed_hosts.clone(),
+                state.config.network.clone(),
+                state.config.resolved_servers(),
+            )
+        };
+        let credentials = forwarded_credentials(&context, &servers);
+        let namespaces = upstream
             .iter()
             .map(|m| {
-                let fns: Vec<String> = m.tools.iter().map(|(_, t)| t.fn_impl(&m.name)).collect();
+                let mut fns: Vec<String> =
+                    m.tools.iter().map(|(_, t)| t.fn_impl(&m.name)).collect();
+                fns.extend(m.nested_namespaces(true));
 
                 format!(
                     "{docstring}
 namespace {namespace} {{
   {fns}
 }}",
-                    docstring = generate_docstring(&m.description),
+                    docstring = m.docstring(),
                     namespace = &m.namespace,
                     fns = fns.join("\n\n")
                 )
@@ -210,8 +313,6 @@ namespace {namespace} {{
 
         let to_execute = format!(
             "
-{registrations}
-
 {namespaces}
 
 {code}
@@ -219,30 +320,41 @@ namespace {namespace} {{
 export default await run();"
         );
 
-        info!("Executing code in sandbox");
-
-        let allowed_hosts = self.allowed_hosts.clone();
-        let code_to_execute = to_execute.clone();
-
-        let result = tokio::task::spawn_blocking(move || -> Result<_, anyhow::Error> {
-            // Create a new current-thread runtime for Deno ops that use deno_unsync
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .map_err(|e| anyhow::anyhow!("Failed to create runtime: {e}"))?;
+        match ClientIdentity::of(&context) {
+            Some(identity) => info!("Executing code in sandbox for {identity}"),
+            None => info!("Executing code in sandbox"),
+        }
 
-            rt.block_on(async {
-                deno_executor::execute(&code_to_execute, Some(allowed_hosts))
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Execution error: {e}"))
+        // upstream calls report progress on, and are cancelled with, this request
+        let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+        tokio::spawn(relay_progress(
+            progress_rx,
+            context.peer.clone(),
+            context.meta.get_progress_token(),
+        ));
+
+        let options = ExecuteOptions {
+            allowed_hosts: Some(allowed_hosts),
+            // server configs and forwarded credentials stay with the connections,
+            // out of the sandbox's reach
+            connections: Some(
+                self.connections
+                    .scoped(progress_tx, context.ct.clone())
+                    .configured(servers)
+                    .forwarding(credentials),
+            ),
+            network,
+        };
+        let session = self
+            .session
+            .lock()
+            .await
+            .get_or_insert_with(|| {
+                Session::new(self.session_timeout).limited_by(&self.session_limit)
             })
-        })
-        .await
-        .map_err(|e| {
-            log::error!("Task join failed: {e}");
-            McpError::internal_error(format!("Task join failed: {e}"), None)
-        })?
-        .map_err(|e| {
+            .clone();
+
+        let result = session.execute(&to_execute, options).await.map_err(|e| {
             log::error!("Sandbox execution error: {e}");
             McpError::internal_error(format!("Execution failed: {e}"), None)
         })?;
@@ -253,6 +365,12 @@ export default await run();"
             log::warn!("Sandbox execution failed: {:?}", result.stderr);
         }
 
+        let mut output = result.output;
+        let mut media = vec![];
+        if let Some(output) = output.as_mut() {
+            extract_media(output, &mut media);
+        }
+
         let text_result = format!(
             "Code Executed Successfully: {success}
 
@@ -268,20 +386,231 @@ export default await run();"
 {stderr}
 ",
             success = result.success,
-            return_val = serde_json::to_string_pretty(&result.output)
-                .unwrap_or(json!(result.output).to_string()),
+            return_val = serde_json::to_string_pretty(&output).unwrap_or(json!(output).to_string()),
             stdout = result.stdout,
             stderr = result.stderr,
         );
 
+        let mut content = vec![Content::text(text_result)];
+        content.extend(media);
         if result.success {
-            Ok(CallToolResult::success(vec![Content::text(text_result)]))
+            Ok(CallToolResult::success(content))
         } else {
-            Ok(CallToolResult::error(vec![Content::text(text_result)]))
+            Ok(CallToolResult::error(content))
+        }
+    }
+
+    #[tool(
+        title = "Reset Session",
+        description = "Discard the top-level declarations and globalThis values kept from previous execute() calls. The next execute() starts from a fresh sandbox."
+    )]
+    async fn reset_session(&self) -> McpResult<CallToolResult> {
+        let reset = self.session.lock().await.take().is_some();
+        let message = if reset {
+            "Session reset, the next execute() starts from a fresh sandbox"
+        } else {
+            "No session to reset"
+        };
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+impl PtcxTools {
+    /// Upstream tools of servers with `passthrough` enabled, exposed as they
+    /// are under `<namespace>__<tool>`
+    fn passthrough_tools(&self) -> Vec<Tool> {
+        let state = self.catalog.read();
+        let mut tools = vec![];
+        for mcp in &state.upstream {
+            let Some(server) = state
+                .config
+                .get_server(&mcp.name)
+                .map(|s| state.config.resolve_server(s))
+                .filter(ServerConfig::passthrough)
+            else {
+                continue;
+            };
+
+            for t in mcp
+                .tools
+                .values()
+                .filter(|t| server.allows_tool(&t.tool_name))
+            {
+                let mut tool = Tool::new(
+                    format!("{}{PASSTHROUGH_SEPARATOR}{}", mcp.namespace, t.tool_name),
+                    t.description.clone().unwrap_or_default(),
+                    schema_object(&t.input_schema),
+                );
+                tool.title.clone_from(&t.title);
+                tool.output_schema = t.output_schema.as_ref().map(schema_object);
+                tools.push(tool);
+            }
+        }
+        tools
+    }
+
+    /// Upstream server and tool name the passthrough tool `name` refers to,
+    /// `None` if it isn't one of [`PtcxTools::passthrough_tools`]
+    fn passthrough_target(&self, name: &str) -> Option<(ServerConfig, String)> {
+        let state = self.catalog.read();
+        state.upstream.iter().find_map(|mcp| {
+            let tool_name = name
+                .strip_prefix(mcp.namespace.as_str())?
+                .strip_prefix(PASSTHROUGH_SEPARATOR)?;
+            let server = state
+                .config
+                .resolve_server(state.config.get_server(&mcp.name)?);
+            (server.passthrough()
+                && server.allows_tool(tool_name)
+                && mcp.tools.values().any(|t| t.tool_name == tool_name))
+            .then(|| (server, tool_name.to_string()))
+        })
+    }
+
+    /// Proxies a passthrough tool call to the upstream server through the
+    /// pooled connection `execute` uses, relaying progress and cancellation
+    async fn call_passthrough(
+        &self,
+        server: &ServerConfig,
+        tool_name: String,
+        arguments: Option<serde_json::Map<String, serde_json::Value>>,
+        context: &RequestContext<RoleServer>,
+    ) -> CallToolResult {
+        match ClientIdentity::of(context) {
+            Some(identity) => info!(
+                "Passing call of '{tool_name}' through to '{}' for {identity}",
+                server.name
+            ),
+            None => info!("Passing call of '{tool_name}' through to '{}'", server.name),
+        }
+
+        let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+        tokio::spawn(relay_progress(
+            progress_rx,
+            context.peer.clone(),
+            context.meta.get_progress_token(),
+        ));
+
+        let params = CallToolRequestParam {
+            name: tool_name.into(),
+            arguments,
+        };
+        self.connections
+            .scoped(progress_tx, context.ct.clone())
+            .configured(vec![server.clone()])
+            .forwarding(forwarded_credentials(context, std::slice::from_ref(server)))
+            .call_tool(server, params)
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("Passthrough call to '{}' failed: {e}", server.name);
+                CallToolResult::error(vec![Content::text(e.to_string())])
+            })
+    }
+}
+
+/// `schema` as the JSON schema object MCP tools declare, tools always take
+/// an object so a missing `type` defaults to it
+fn schema_object(schema: &serde_json::Value) -> Arc<serde_json::Map<String, serde_json::Value>> {
+    let mut object = schema.as_object().cloned().unwrap_or_default();
+    object.entry("type").or_insert_with(|| json!("object"));
+    Arc::new(object)
+}
+
+/// Relays the progress upstream servers report for an execution's (or a
+/// passthrough call's) tool calls to the downstream client, as progress of
+/// the request
+async fn relay_progress(
+    mut progress: mpsc::UnboundedReceiver<ToolProgress>,
+    peer: Peer<RoleServer>,
+    token: Option<ProgressToken>,
+) {
+    // the client didn't ask for progress
+    let Some(token) = token else {
+        return;
+    };
+
+    // several tools may report concurrently, so downstream progress counts the
+    // relayed notifications to keep increasing
+    let mut relayed = 0u32;
+    while let Some(p) = progress.recv().await {
+        relayed += 1;
+        let progress = match p.total {
+            Some(total) => format!("{}/{total}", p.progress),
+            None => p.progress.to_string(),
+        };
+        let message = match p.message {
+            Some(m) => format!("{}.{} {progress}: {m}", p.server, p.tool),
+            None => format!("{}.{} {progress}", p.server, p.tool),
+        };
+
+        if let Err(e) = peer
+            .notify_progress(ProgressNotificationParam {
+                progress_token: token.clone(),
+                progress: f64::from(relayed),
+                total: None,
+                message: Some(message),
+            })
+            .await
+        {
+            debug!("Failed relaying progress to downstream client: {e}");
+        }
+    }
+}
+
+/// Moves the image and audio blocks (`{ type, data, mimeType }`) found in a
+/// return value into `media`, leaving a reference to the attached block
+/// instead of the base64 data
+fn extract_media(value: &mut serde_json::Value, media: &mut Vec<Content>) {
+    match value {
+        serde_json::Value::Array(items) => {
+            for item in items {
+                extract_media(item, media);
+            }
+        }
+        serde_json::Value::Object(obj) => {
+            let block = match (
+                obj.get("type").and_then(serde_json::Value::as_str),
+                obj.get("data").and_then(serde_json::Value::as_str),
+                obj.get("mimeType").and_then(serde_json::Value::as_str),
+            ) {
+                (Some("image"), Some(data), Some(mime_type)) => {
+                    Some(Content::image(data, mime_type))
+                }
+                (Some("audio"), Some(data), Some(mime_type)) => Some(
+                    RawContent::Audio(RawAudioContent {
+                        data: data.into(),
+                        mime_type: mime_type.into(),
+                    })
+                    .no_annotation(),
+                ),
+                _ => None,
+            };
+
+            if let Some(block) = block {
+                media.push(block);
+                // the text block comes first, so the first attachment is block 2
+                obj.insert(
+                    "data".into(),
+                    json!(format!("<attached as content block {}>", media.len() + 1)),
+                );
+            } else {
+                for v in obj.values_mut() {
+                    extract_media(v, media);
+                }
+            }
         }
+        _ => {}
     }
 }
 
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub(crate) struct SearchFunctionsInput {
+    /// Keywords describing what you want to do, e.g. "create github issue"
+    pub query: String,
+    /// Maximum number of functions to return, defaults to 10
+    pub limit: Option<usize>,
+}
+
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub(crate) struct GetFunctionDetailsInput {
     /// List of functions to get details of. Functions should be in the form "<namespace>.<function name>".
@@ -306,12 +635,40 @@ pub(crate) struct ExecuteInput {
     pub code: String,
 }
 
-#[tool_handler]
 impl ServerHandler for PtcxTools {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> McpResult<ListToolsResult> {
+        let mut tools = self.tool_router.list_all();
+        tools.extend(self.passthrough_tools());
+        Ok(ListToolsResult::with_all_items(tools))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> McpResult<CallToolResult> {
+        if !self.tool_router.has_route(&request.name)
+            && let Some((server, tool_name)) = self.passthrough_target(&request.name)
+        {
+            return Ok(self
+                .call_passthrough(&server, tool_name, request.arguments, &context)
+                .await);
+        }
+
+        let tcc = ToolCallContext::new(self, request, context);
+        self.tool_router.call(tcc).await
+    }
+
     fn get_info(&self) -> ServerInfo {
+        let state = self.catalog.read();
         let default_description = format!(
             "This server provides tools to explore SDK functions and execute SDK scripts for the following services: {}",
-            self.upstream
+            state
+                .upstream
                 .iter()
                 .map(|m| m.name.as_str())
                 .collect::<Vec<&str>>()
@@ -320,19 +677,342 @@ impl ServerHandler for PtcxTools {
 
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_tool_list_changed()
+                .build(),
             server_info: Implementation {
-                name: self.config.name.clone(),
-                title: Some(self.config.name.clone()),
-                version: self.config.version.clone(),
+                name: state.config.name.clone(),
+                title: Some(state.config.name.clone()),
+                version: state.config.version.clone(),
                 ..Default::default()
             },
             instructions: Some(
-                self.config
+                state
+                    .config
                     .description
                     .clone()
                     .unwrap_or(default_description),
             ),
         }
     }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        self.catalog.subscribe(context.peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pctx_config::Config;
+
+    use crate::mcp::upstream::{UpstreamMcp, UpstreamTool};
+
+    /// Handler serving `servers` with the tools `listed`, discovered before
+    /// the config's tool filters applied
+    fn tools(servers: &serde_json::Value, listed: &[(&str, &[&str])]) -> PtcxTools {
+        let cfg: Config =
+            serde_json::from_value(json!({ "name": "test", "servers": servers })).unwrap();
+        let upstream = listed
+            .iter()
+            .map(|(name, tools)| {
+                let server: ServerConfig = serde_json::from_value(
+                    json!({ "name": name, "url": "http://127.0.0.1:1/mcp" }),
+                )
+                .unwrap();
+                let configured = cfg.get_server(name).unwrap();
+                let listed = tools
+                    .iter()
+                    .map(|t| {
+                        Tool::new(
+                            (*t).to_string(),
+                            format!("Upstream {t}"),
+                            json!({ "type": "object" }).as_object().unwrap().clone(),
+                        )
+                    })
+                    .collect();
+                UpstreamMcp {
+                    name: server.name.clone(),
+                    namespace: configured.namespace(),
+                    description: server.name.clone(),
+                    url: server.url().cloned(),
+                    tools: UpstreamTool::from_tools(&server, listed).unwrap(),
+                    resources: vec![],
+                    prompts: IndexMap::new(),
+                    stale: false,
+                }
+            })
+            .collect();
+
+        PtcxTools::new(
+            UpstreamCatalog::new(cfg, upstream),
+            MCPConnectionManager::new(),
+            Duration::from_secs(60),
+            SessionLimit::new(1),
+        )
+    }
+
+    fn target(tools: &PtcxTools, name: &str) -> Option<(String, String)> {
+        tools
+            .passthrough_target(name)
+            .map(|(server, tool)| (server.name, tool))
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_target_separator_in_names() {
+        let tools = tools(
+            &json!([
+                { "name": "github", "url": "http://127.0.0.1:1/mcp", "passthrough": true },
+                {
+                    "name": "issues",
+                    "namespace": "Github__issues",
+                    "url": "http://127.0.0.1:1/mcp",
+                    "passthrough": true
+                }
+            ]),
+            &[
+                ("github", &["issues__list", "get_repo"]),
+                ("issues", &["get", "__close"]),
+            ],
+        );
+
+        let expected = |server: &str, tool: &str| Some((server.to_string(), tool.to_string()));
+        assert_eq!(
+            target(&tools, "Github__issues__list"),
+            expected("github", "issues__list")
+        );
+        // the `Github` server has no `issues__get` tool
+        assert_eq!(
+            target(&tools, "Github__issues__get"),
+            expected("issues", "get")
+        );
+        assert_eq!(
+            target(&tools, "Github__issues____close"),
+            expected("issues", "__close")
+        );
+        assert_eq!(
+            target(&tools, "Github__get_repo"),
+            expected("github", "get_repo")
+        );
+
+        for unknown in [
+            "Github__missing",
+            "Github_get_repo",
+            "get_repo",
+            "Github__",
+            "Gitlab__get_repo",
+        ] {
+            assert_eq!(
+                target(&tools, unknown),
+                None,
+                "{unknown} should not be routed"
+            );
+        }
+
+        let mut listed: Vec<String> = tools
+            .passthrough_tools()
+            .into_iter()
+            .map(|t| t.name.into_owned())
+            .collect();
+        listed.sort();
+        assert_eq!(
+            listed,
+            [
+                "Github__get_repo",
+                "Github__issues____close",
+                "Github__issues__get",
+                "Github__issues__list"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_target_rejects_hidden_tools() {
+        let tools = tools(
+            &json!([
+                {
+                    "name": "github",
+                    "url": "http://127.0.0.1:1/mcp",
+                    "passthrough": true,
+                    "exclude_tools": ["delete_*"]
+                },
+                { "name": "slack", "url": "http://127.0.0.1:1/mcp" }
+            ]),
+            &[
+                ("github", &["get_issue", "delete_issue"]),
+                ("slack", &["post_message"]),
+            ],
+        );
+
+        assert!(target(&tools, "Github__get_issue").is_some());
+        assert_eq!(
+            target(&tools, "Github__delete_issue"),
+            None,
+            "Tools hidden by `exclude_tools` should not be callable"
+        );
+        assert_eq!(
+            target(&tools, "Slack__post_message"),
+            None,
+            "Servers without `passthrough` should not be callable"
+        );
+
+        let listed: Vec<String> = tools
+            .passthrough_tools()
+            .into_iter()
+            .map(|t| t.name.into_owned())
+            .collect();
+        assert_eq!(listed, ["Github__get_issue"]);
+    }
+
+    /// Client collecting the progress notifications it receives
+    struct ProgressClient(mpsc::UnboundedSender<ProgressNotificationParam>);
+
+    impl rmcp::ClientHandler for ProgressClient {
+        async fn on_progress(
+            &self,
+            params: ProgressNotificationParam,
+            _context: NotificationContext<rmcp::RoleClient>,
+        ) {
+            let _ = self.0.send(params);
+        }
+    }
+
+    fn progress(
+        tool: &str,
+        progress: f64,
+        total: Option<f64>,
+        message: Option<&str>,
+    ) -> ToolProgress {
+        ToolProgress {
+            server: "github".into(),
+            tool: tool.into(),
+            progress,
+            total,
+            message: message.map(String::from),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_relay_progress() {
+        use rmcp::ServiceExt;
+
+        let (server_transport, client_transport) = tokio::io::duplex(4096);
+        let server = tokio::spawn(tools(&json!([]), &[]).serve(server_transport));
+        let (received_tx, mut received) = mpsc::unbounded_channel();
+        let client = ProgressClient(received_tx)
+            .serve(client_transport)
+            .await
+            .unwrap();
+        let server = server.await.unwrap().unwrap();
+
+        let token = ProgressToken(rmcp::model::NumberOrString::Number(7));
+        let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+        let relay = tokio::spawn(relay_progress(
+            progress_rx,
+            server.peer().clone(),
+            Some(token.clone()),
+        ));
+        progress_tx
+            .send(progress("search_issues", 1.0, Some(4.0), Some("page 1")))
+            .unwrap();
+        progress_tx
+            .send(progress("get_issue", 0.5, None, None))
+            .unwrap();
+        drop(progress_tx);
+        relay.await.unwrap();
+
+        let mut relayed = vec![];
+        for _ in 0..2 {
+            let p = tokio::time::timeout(Duration::from_secs(5), received.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(p.progress_token, token);
+            relayed.push((p.progress, p.message.unwrap()));
+        }
+        // downstream progress keeps increasing across tools
+        assert_eq!(
+            relayed,
+            [
+                (1.0, "github.search_issues 1/4: page 1".to_string()),
+                (2.0, "github.get_issue 0.5".to_string()),
+            ]
+        );
+
+        // without a progress token nothing is relayed, even while the execution runs
+        let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            relay_progress(progress_rx, server.peer().clone(), None),
+        )
+        .await
+        .unwrap();
+        assert!(
+            progress_tx
+                .send(progress("get_issue", 1.0, None, None))
+                .is_err()
+        );
+
+        client.cancel().await.unwrap();
+    }
+
+    #[test]
+    fn test_extract_media_mixed_results() {
+        let mut output = json!({
+            "text": { "type": "text", "text": "Two charts attached" },
+            "charts": [
+                { "type": "image", "data": "aW1hZ2Ux", "mimeType": "image/png" },
+                { "type": "text", "text": "between the charts" },
+                { "type": "image", "data": "aW1hZ2Uy", "mimeType": "image/jpeg" }
+            ],
+            "narration": { "type": "audio", "data": "YXVkaW8=", "mimeType": "audio/wav" },
+            // not a complete block, left as it is
+            "thumbnail": { "type": "image", "data": "dGh1bWI=" }
+        });
+        let mut media = vec![];
+        extract_media(&mut output, &mut media);
+
+        assert_eq!(
+            output,
+            json!({
+                "text": { "type": "text", "text": "Two charts attached" },
+                "charts": [
+                    { "type": "image", "data": "<attached as content block 2>", "mimeType": "image/png" },
+                    { "type": "text", "text": "between the charts" },
+                    { "type": "image", "data": "<attached as content block 3>", "mimeType": "image/jpeg" }
+                ],
+                "narration": { "type": "audio", "data": "<attached as content block 4>", "mimeType": "audio/wav" },
+                "thumbnail": { "type": "image", "data": "dGh1bWI=" }
+            })
+        );
+
+        let attached: Vec<(&str, &str)> = media
+            .iter()
+            .map(|c| match &c.raw {
+                RawContent::Image(image) => (image.data.as_str(), image.mime_type.as_str()),
+                RawContent::Audio(audio) => (audio.data.as_str(), audio.mime_type.as_str()),
+                other => panic!("Unexpected content block: {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            attached,
+            [
+                ("aW1hZ2Ux", "image/png"),
+                ("aW1hZ2Uy", "image/jpeg"),
+                ("YXVkaW8=", "audio/wav")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_media_text_only() {
+        let original = json!([{ "type": "text", "text": "no media" }, "plain", 42, null]);
+        let mut output = original.clone();
+        let mut media = vec![];
+        extract_media(&mut output, &mut media);
+
+        assert!(media.is_empty());
+        assert_eq!(output, original);
+    }
 }
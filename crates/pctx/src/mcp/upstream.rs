@@ -1,24 +1,83 @@
 use anyhow::Result;
-use codegen::{case::Case, generate_docstring};
+use codegen::{
+    case::{Case, legalize_identifier},
+    generate_docstring,
+};
 use indexmap::IndexMap;
-use log::debug;
-use pctx_config::server::ServerConfig;
+use log::{debug, warn};
+use pctx_config::server::{ServerConfig, ToolOverride};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::{collections::HashSet, sync::Arc, time::Duration};
 use url::Url;
 
+/// Seconds waited before the first startup retry, grows linearly with each attempt
+const RETRY_BACKOFF_SECS: u64 = 1;
+
+/// Most resources listed in a namespace's docstring, servers can expose thousands
+const MAX_LISTED_RESOURCES: usize = 50;
+
+/// Names of the nested namespaces holding resource & prompt functions, tools
+/// can't use them as function names
+const RESERVED_FN_NAMES: &[&str] = &["resources", "prompts"];
+
 #[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct UpstreamMcp {
     pub(crate) name: String,
     pub(crate) namespace: String,
     pub(crate) description: String,
-    pub(crate) url: Url,
+    /// URL of HTTP servers, not recorded in the lockfile as it can hold credentials
+    #[serde(skip)]
+    pub(crate) url: Option<Url>,
     pub(crate) tools: IndexMap<String, UpstreamTool>,
-    pub(crate) registration: serde_json::Value,
+    /// Resources the server advertises, empty if it doesn't support them
+    pub(crate) resources: Vec<UpstreamResource>,
+    /// Prompts the server advertises keyed by function name, empty if it doesn't support them
+    pub(crate) prompts: IndexMap<String, UpstreamPrompt>,
+    /// Whether this interface was loaded from the lockfile because the server
+    /// couldn't be reached, cleared once its tools are refreshed
+    #[serde(skip)]
+    pub(crate) stale: bool,
 }
 impl UpstreamMcp {
+    /// Fetches the tools of `server` like [`UpstreamMcp::from_server`], giving up
+    /// after the server's `connect_timeout` and retrying up to `retries` times
+    pub(crate) async fn discover(server: &ServerConfig) -> Result<Self> {
+        let timeout = server.connect_timeout();
+        let retries = server.retries.unwrap_or_default();
+
+        let mut attempt = 0;
+        loop {
+            let res = match tokio::time::timeout(timeout, Self::from_server(server)).await {
+                Ok(res) => res,
+                Err(_) => Err(anyhow::anyhow!(
+                    "timed out after {}s",
+                    timeout.as_secs_f32()
+                )),
+            };
+
+            match res {
+                Err(e) if attempt < retries => {
+                    attempt += 1;
+                    debug!(
+                        "Fetching tools from '{}' failed ({e}), retrying ({attempt}/{retries})...",
+                        server.name
+                    );
+                    tokio::time::sleep(Duration::from_secs(
+                        RETRY_BACKOFF_SECS * u64::from(attempt),
+                    ))
+                    .await;
+                }
+                res => return res,
+            }
+        }
+    }
+
     pub(crate) async fn from_server(server: &ServerConfig) -> Result<Self> {
-        debug!("Fetching tools from '{}'({})...", &server.name, &server.url);
+        debug!(
+            "Fetching tools from '{}'({})...",
+            &server.name, &server.connection
+        );
 
         let mcp_client = server.connect().await?;
 
@@ -27,54 +86,431 @@ impl UpstreamMcp {
             server.name
         );
 
-        let listed_tools = mcp_client.list_all_tools().await?;
-        debug!("Found {} tools", listed_tools.len());
+        let tools = UpstreamTool::from_tools(server, mcp_client.list_all_tools().await?)?;
 
-        let mut tools = IndexMap::new();
-        for t in listed_tools {
-            let tool = UpstreamTool::from_tool(t)?;
-            tools.insert(tool.fn_name.clone(), tool);
-        }
+        let capabilities = mcp_client
+            .peer_info()
+            .map(|p| p.capabilities.clone())
+            .unwrap_or_default();
+        let resources = if capabilities.resources.is_some() {
+            match mcp_client.list_all_resources().await {
+                Ok(listed) => listed.into_iter().map(UpstreamResource::from).collect(),
+                Err(e) => {
+                    warn!("Failed listing resources of '{}': {e}", server.name);
+                    vec![]
+                }
+            }
+        } else {
+            vec![]
+        };
+        let prompts = if capabilities.prompts.is_some() {
+            match mcp_client.list_all_prompts().await {
+                Ok(listed) => UpstreamPrompt::from_prompts(&server.name, listed),
+                Err(e) => {
+                    warn!("Failed listing prompts of '{}': {e}", server.name);
+                    IndexMap::new()
+                }
+            }
+        } else {
+            IndexMap::new()
+        };
 
         let description = mcp_client
             .peer_info()
             .and_then(|p| p.server_info.title.clone())
-            .unwrap_or(format!("MCP server at {}", server.url));
+            // not the connection, URLs and commands can hold credentials
+            .unwrap_or(format!("MCP server '{}'", server.name));
 
         mcp_client.cancel().await?;
 
         Ok(Self {
             name: server.name.clone(),
-            namespace: Case::Pascal.sanitize(&server.name),
+            namespace: server.namespace(),
             description,
-            url: server.url.clone(),
+            url: server.url().cloned(),
             tools,
-            registration: json!(server),
+            resources,
+            prompts,
+            stale: false,
         })
     }
+
+    /// Interface regenerated with the current settings of `server` and flagged
+    /// as stale, for interfaces served while the server can't be reached
+    ///
+    /// Tools are regenerated like freshly listed ones, so tools the server's
+    /// filters exclude by now are dropped and its current tool overrides applied.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a tool's interface cannot be regenerated
+    pub(crate) fn into_stale(mut self, server: &ServerConfig) -> Result<Self> {
+        let recorded = self.tools.values().map(UpstreamTool::listed).collect();
+        self.tools = UpstreamTool::from_tools(server, recorded)?;
+        self.namespace = server.namespace();
+        self.url = server.url().cloned();
+        self.stale = true;
+        Ok(self)
+    }
+
+    /// Docstring of the server's namespace, flagging interfaces served from the lockfile
+    pub(crate) fn docstring(&self) -> String {
+        if self.stale {
+            generate_docstring(&format!(
+                "[STALE] {}\n\nThe server could not be reached, these signatures were cached by an earlier run and calls may fail",
+                self.description
+            ))
+        } else {
+            generate_docstring(&self.description)
+        }
+    }
+
+    /// Nested `resources` and `prompts` namespaces of the server, only
+    /// declaring their functions unless `with_impl` is set
+    pub(crate) fn nested_namespaces(&self, with_impl: bool) -> Vec<String> {
+        let mut namespaces = vec![];
+
+        if !self.resources.is_empty() {
+            let mut listed: Vec<String> = self
+                .resources
+                .iter()
+                .take(MAX_LISTED_RESOURCES)
+                .map(UpstreamResource::summary)
+                .collect();
+            if self.resources.len() > MAX_LISTED_RESOURCES {
+                listed.push(format!(
+                    "- ...and {} more",
+                    self.resources.len() - MAX_LISTED_RESOURCES
+                ));
+            }
+            let sig = format!(
+                "{docstring}\nexport async function read(uri: string): Promise<MCPResourceContents[]>",
+                docstring = generate_docstring(&format!(
+                    "Reads a resource of this server by URI. Available resources:\n\n{}",
+                    listed.join("\n")
+                )),
+            );
+            let read = if with_impl {
+                format!(
+                    "{sig} {{\n  return await readMCPResource({{ name: {name}, uri }});\n}}",
+                    name = json!(&self.name),
+                )
+            } else {
+                sig
+            };
+            namespaces.push(format!("export namespace resources {{\n  {read}\n}}"));
+        }
+
+        if !self.prompts.is_empty() {
+            let fns: Vec<String> = self
+                .prompts
+                .values()
+                .map(|p| {
+                    if with_impl {
+                        p.fn_impl(&self.name)
+                    } else {
+                        p.fn_signature()
+                    }
+                })
+                .collect();
+            namespaces.push(format!(
+                "export namespace prompts {{\n  {}\n}}",
+                fns.join("\n\n")
+            ));
+        }
+
+        namespaces
+    }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Picks unique and legal function names for `items`, given as pairs of the
+/// upstream name and the preferred function name
+///
+/// Names that collide (e.g. `get-item` and `get_item`) get a numeric suffix,
+/// handed out in upstream name order so the result doesn't depend on the order
+/// the server lists them in.
+fn unique_fn_names(
+    server_name: &str,
+    kind: &str,
+    items: &[(&str, String)],
+    reserved: &[&str],
+) -> Vec<String> {
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by(|a, b| items[*a].0.cmp(items[*b].0));
+
+    let mut taken: HashSet<String> = reserved.iter().map(ToString::to_string).collect();
+    let mut names = vec![String::new(); items.len()];
+    for i in order {
+        let (upstream_name, preferred) = &items[i];
+
+        let base = legalize_identifier(preferred);
+        let mut name = base.clone();
+        let mut suffix = 2;
+        while !taken.insert(name.clone()) {
+            name = format!("{base}{suffix}");
+            suffix += 1;
+        }
+
+        if name != *preferred {
+            warn!(
+                "{kind} '{upstream_name}' of '{server_name}' is exposed as function '{name}' (instead of '{preferred}')"
+            );
+        }
+        names[i] = name;
+    }
+    names
+}
+
+/// Picks a unique and legal function name for each of `tools`, see [`unique_fn_names`]
+fn function_names(server: &ServerConfig, tools: &[rmcp::model::Tool]) -> Vec<String> {
+    let items: Vec<(&str, String)> = tools
+        .iter()
+        .map(|t| {
+            let tool_name = t.name.as_ref();
+            let preferred = server
+                .tools
+                .get(tool_name)
+                .and_then(|o| o.name.clone())
+                .unwrap_or_else(|| Case::Camel.sanitize(tool_name));
+            (tool_name, preferred)
+        })
+        .collect();
+    unique_fn_names(&server.name, "Tool", &items, RESERVED_FN_NAMES)
+}
+
+/// Resource advertised by an upstream server
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct UpstreamResource {
+    pub(crate) uri: String,
+    pub(crate) name: String,
+    pub(crate) description: Option<String>,
+    pub(crate) mime_type: Option<String>,
+}
+
+impl From<rmcp::model::Resource> for UpstreamResource {
+    fn from(resource: rmcp::model::Resource) -> Self {
+        let resource = resource.raw;
+        Self {
+            uri: resource.uri,
+            name: resource.title.unwrap_or(resource.name),
+            description: resource.description,
+            mime_type: resource.mime_type,
+        }
+    }
+}
+
+impl UpstreamResource {
+    /// One line docstring summary of the resource
+    fn summary(&self) -> String {
+        let mut summary = format!("- `{}` {}", self.uri, self.name);
+        if let Some(mime_type) = &self.mime_type {
+            summary = format!("{summary} ({mime_type})");
+        }
+        if let Some(description) = &self.description {
+            summary = format!("{summary}: {}", description.replace('\n', " "));
+        }
+        summary
+    }
+}
+
+/// Prompt advertised by an upstream server
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct UpstreamPrompt {
+    pub(crate) prompt_name: String,
+    pub(crate) title: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) fn_name: String,
+    pub(crate) arguments: Vec<rmcp::model::PromptArgument>,
+}
+
+impl UpstreamPrompt {
+    /// Generates the prompts of an upstream server keyed by function name
+    pub(crate) fn from_prompts(
+        server_name: &str,
+        listed: Vec<rmcp::model::Prompt>,
+    ) -> IndexMap<String, Self> {
+        debug!("Found {} prompts", listed.len());
+
+        let items: Vec<(&str, String)> = listed
+            .iter()
+            .map(|p| (p.name.as_str(), Case::Camel.sanitize(&p.name)))
+            .collect();
+        let fn_names = unique_fn_names(server_name, "Prompt", &items, &[]);
+
+        listed
+            .into_iter()
+            .zip(fn_names)
+            .map(|(p, fn_name)| {
+                let prompt = Self {
+                    prompt_name: p.name,
+                    title: p.title,
+                    description: p.description,
+                    fn_name: fn_name.clone(),
+                    arguments: p.arguments.unwrap_or_default(),
+                };
+                (fn_name, prompt)
+            })
+            .collect()
+    }
+
+    pub(crate) fn fn_signature(&self) -> String {
+        let mut docstring_content = format!(
+            "{title}{desc}",
+            title = &self
+                .title
+                .as_ref()
+                .map(|t| format!("{t}\n\n"))
+                .unwrap_or_default(),
+            desc = &self.description.clone().unwrap_or_default()
+        );
+        for arg in &self.arguments {
+            if let Some(desc) = &arg.description {
+                docstring_content = format!("{docstring_content}\n@param args.{} {desc}", arg.name);
+            }
+        }
+
+        let params = if self.arguments.is_empty() {
+            String::new()
+        } else {
+            let fields: Vec<String> = self
+                .arguments
+                .iter()
+                .map(|a| {
+                    format!(
+                        "{name}{optional}: string;",
+                        name = json!(&a.name),
+                        optional = if a.required.unwrap_or_default() {
+                            ""
+                        } else {
+                            "?"
+                        }
+                    )
+                })
+                .collect();
+            format!("args: {{ {} }}", fields.join(" "))
+        };
+
+        format!(
+            "{docstring}\nexport async function {fn_name}({params}): Promise<MCPPrompt>",
+            docstring = generate_docstring(docstring_content.trim()),
+            fn_name = &self.fn_name,
+        )
+    }
+
+    pub(crate) fn fn_impl(&self, mcp_name: &str) -> String {
+        format!(
+            "{fn_sig} {{
+  return await getMCPPrompt({{
+    name: {name},
+    prompt: {prompt},
+    arguments: {args},
+  }});
+}}",
+            fn_sig = self.fn_signature(),
+            name = json!(mcp_name),
+            prompt = json!(&self.prompt_name),
+            args = if self.arguments.is_empty() {
+                "{}"
+            } else {
+                "args"
+            },
+        )
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub(crate) struct UpstreamTool {
     pub(crate) tool_name: String,
     pub(crate) title: Option<String>,
     pub(crate) description: Option<String>,
     pub(crate) fn_name: String,
+    /// Extra docstring notes from the tool's config override
+    pub(crate) notes: Option<String>,
+    /// Docstring usage examples from the tool's config override
+    pub(crate) examples: Vec<String>,
     pub(crate) input_type: String,
     pub(crate) output_type: String,
     pub(crate) types: String,
+    /// JSON schemas the types were generated from, kept to detect schema drift
+    pub(crate) input_schema: serde_json::Value,
+    pub(crate) output_schema: Option<serde_json::Value>,
 }
 
 impl UpstreamTool {
-    pub(crate) fn from_tool(tool: rmcp::model::Tool) -> Result<Self> {
-        let fn_name = Case::Camel.sanitize(&tool.name);
+    /// Generates the tools of an upstream server keyed by function name,
+    /// skipping tools filtered out by the server's config
+    pub(crate) fn from_tools(
+        server: &ServerConfig,
+        listed: Vec<rmcp::model::Tool>,
+    ) -> Result<IndexMap<String, Self>> {
+        debug!("Found {} tools", listed.len());
+
+        for name in server.tools.keys() {
+            if !listed.iter().any(|t| t.name == name.as_str()) {
+                warn!(
+                    "Override of tool '{name}' of '{}' matches no tool",
+                    server.name
+                );
+            }
+        }
+
+        let listed: Vec<rmcp::model::Tool> = listed
+            .into_iter()
+            .filter(|t| {
+                let allowed = server.allows_tool(&t.name);
+                if !allowed {
+                    debug!("Skipping tool '{}' filtered out by config", &t.name);
+                }
+                allowed
+            })
+            .collect();
+        let fn_names = function_names(server, &listed);
+
+        let mut tools = IndexMap::new();
+        for (t, fn_name) in listed.into_iter().zip(fn_names) {
+            let tool_override = server
+                .tools
+                .get(t.name.as_ref())
+                .cloned()
+                .unwrap_or_default();
+            let tool = Self::from_tool(t, fn_name, tool_override)?;
+            tools.insert(tool.fn_name.clone(), tool);
+        }
+        Ok(tools)
+    }
+
+    /// Tool as the server listed it, as far as the recorded interface tells:
+    /// overrides applied when it was generated are kept
+    pub(crate) fn listed(&self) -> rmcp::model::Tool {
+        let schema = |s: &serde_json::Value| Arc::new(s.as_object().cloned().unwrap_or_default());
+        rmcp::model::Tool {
+            name: self.tool_name.clone().into(),
+            title: self.title.clone(),
+            description: self.description.clone().map(Into::into),
+            input_schema: schema(&self.input_schema),
+            output_schema: self.output_schema.as_ref().map(schema),
+            annotations: None,
+            icons: None,
+        }
+    }
+
+    /// Generates the TypeScript interface of a tool exposed as the function
+    /// `fn_name`, with `tool_override` applied before anything is generated
+    pub(crate) fn from_tool(
+        tool: rmcp::model::Tool,
+        fn_name: String,
+        tool_override: ToolOverride,
+    ) -> Result<Self> {
         debug!(
             "Generating Typescript interface for tool: '{}' -> function {fn_name}",
             &tool.name
         );
 
+        let input_schema = json!(tool.input_schema);
+        let output_schema = tool.output_schema.map(|s| json!(s));
+
         let input_types =
-            codegen::typegen::generate_types(json!(tool.input_schema), &format!("{fn_name}Input"))?;
+            codegen::typegen::generate_types(input_schema.clone(), &format!("{fn_name}Input"))?;
         debug!(
             "Generated {} types for input schema",
             input_types.types_generated
@@ -82,9 +518,9 @@ impl UpstreamTool {
 
         let mut types = input_types.types;
 
-        let output_type = if let Some(output_schema) = tool.output_schema {
+        let output_type = if let Some(output_schema) = &output_schema {
             let output_types = codegen::typegen::generate_types(
-                json!(output_schema),
+                output_schema.clone(),
                 &format!("{fn_name}Output"),
             )?;
             debug!(
@@ -100,17 +536,23 @@ impl UpstreamTool {
 
         Ok(Self {
             tool_name: tool.name.to_string(),
-            title: tool.title,
-            description: tool.description.map(String::from),
-            fn_name: codegen::case::Case::Camel.sanitize(&tool.name),
+            title: tool_override.title.or(tool.title),
+            description: tool_override
+                .description
+                .or(tool.description.map(String::from)),
+            fn_name,
+            notes: tool_override.notes,
+            examples: tool_override.examples,
             input_type: input_types.type_signature,
             output_type,
             types,
+            input_schema,
+            output_schema,
         })
     }
 
     pub(crate) fn fn_signature(&self, include_types: bool) -> String {
-        let docstring_content = format!(
+        let mut docstring_content = format!(
             "{title}{desc}",
             title = &self
                 .title
@@ -119,6 +561,12 @@ impl UpstreamTool {
                 .unwrap_or_default(),
             desc = &self.description.clone().unwrap_or_default()
         );
+        if let Some(notes) = &self.notes {
+            docstring_content = format!("{docstring_content}\n\n{notes}");
+        }
+        for example in &self.examples {
+            docstring_content = format!("{docstring_content}\n\n@example\n{example}");
+        }
 
         let types = if include_types && !self.types.is_empty() {
             format!("{}\n\n", &self.types)
@@ -151,3 +599,128 @@ impl UpstreamTool {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str) -> rmcp::model::Tool {
+        rmcp::model::Tool::new(
+            name.to_string(),
+            format!("Upstream {name}"),
+            json!({ "type": "object" }).as_object().unwrap().clone(),
+        )
+    }
+
+    fn server(settings: &serde_json::Value) -> ServerConfig {
+        let mut cfg = json!({ "name": "github", "url": "https://mcp.example.com/mcp" });
+        cfg.as_object_mut()
+            .unwrap()
+            .extend(settings.as_object().unwrap().clone());
+        serde_json::from_value(cfg).unwrap()
+    }
+
+    fn items<'a>(names: &[(&'a str, &str)]) -> Vec<(&'a str, String)> {
+        names
+            .iter()
+            .map(|(upstream, preferred)| (*upstream, (*preferred).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_unique_fn_names_colliding() {
+        let names = unique_fn_names(
+            "github",
+            "Tool",
+            &items(&[("get_item", "getItem"), ("get-item", "getItem")]),
+            &[],
+        );
+        assert_eq!(names, ["getItem2", "getItem"]);
+
+        // suffixes follow upstream names, not the order the server lists them in
+        let names = unique_fn_names(
+            "github",
+            "Tool",
+            &items(&[("get-item", "getItem"), ("get_item", "getItem")]),
+            &[],
+        );
+        assert_eq!(names, ["getItem", "getItem2"]);
+
+        // a suffixed name can't take the name of another function
+        let names = unique_fn_names(
+            "github",
+            "Tool",
+            &items(&[("a", "getItem"), ("b", "getItem"), ("c", "getItem2")]),
+            &[],
+        );
+        assert_eq!(names, ["getItem", "getItem2", "getItem22"]);
+    }
+
+    #[test]
+    fn test_unique_fn_names_reserved() {
+        let names = unique_fn_names(
+            "github",
+            "Tool",
+            &items(&[
+                ("eval", "eval"),
+                ("delete", "delete"),
+                ("arguments", "arguments"),
+                ("resources", "resources"),
+                ("123", "123"),
+            ]),
+            RESERVED_FN_NAMES,
+        );
+        assert_eq!(
+            names,
+            ["eval_", "delete_", "arguments_", "resources2", "_123"]
+        );
+    }
+
+    #[test]
+    fn test_function_names_after_camel_case() {
+        let server = server(&json!({}));
+        let tools = UpstreamTool::from_tools(
+            &server,
+            vec![tool("list_issues"), tool("listIssues"), tool("list-issues")],
+        )
+        .unwrap();
+
+        let names: Vec<(&str, &str)> = tools
+            .values()
+            .map(|t| (t.tool_name.as_str(), t.fn_name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("list_issues", "listIssues3"),
+                ("listIssues", "listIssues2"),
+                ("list-issues", "listIssues"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_names_with_overrides() {
+        let server = server(&json!({
+            "tools": { "search": { "name": "findIssues" } }
+        }));
+        let tools = UpstreamTool::from_tools(
+            &server,
+            vec![tool("find_issues"), tool("search"), tool("prompts")],
+        )
+        .unwrap();
+
+        let names: Vec<(&str, &str)> = tools
+            .values()
+            .map(|t| (t.tool_name.as_str(), t.fn_name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("find_issues", "findIssues"),
+                ("search", "findIssues2"),
+                ("prompts", "prompts2"),
+            ]
+        );
+    }
+}
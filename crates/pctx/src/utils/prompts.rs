@@ -10,7 +10,8 @@ pub(crate) fn prompt_auth(server_name: &str) -> Result<AuthConfig> {
     let options = vec![
         "Bearer Token".to_string(),
         "Custom Headers".to_string(),
-        // format!("OAuth2 {}", fmt_dimmed("(client credentials flow)")),
+        format!("OAuth 2.1 {}", fmt_dimmed("(browser login)")),
+        format!("OAuth2 {}", fmt_dimmed("(client credentials flow)")),
     ];
     let selection = inquire::Select::new(
         "How do you want to authenticate with the MCP server",
@@ -47,29 +48,34 @@ pub(crate) fn prompt_auth(server_name: &str) -> Result<AuthConfig> {
 
             Ok(AuthConfig::Custom { headers })
         }
-        // Some(2) => {
-        //     // OAuth2
-        //     let token_url = inquire::Text::new("├── Token URL:")
-        //         .with_validator(validators::url)
-        //         .prompt()?;
-
-        //     let client_id_key = Case::Snake.sanitize(format!("{server_name}_client_id"));
-        //     let client_id = prompt_secret("├── Client ID:", "│   ", &client_id_key)?;
-
-        //     let client_secret_key = Case::Snake.sanitize(format!("{server_name}_client_secret"));
-        //     let client_secret = prompt_secret("├── Client Secret:", "│   ", &client_secret_key)?;
-
-        //     let scope = inquire::Text::new("└── Scopes:")
-        //         .with_help_message("comma separated scopes, leave empty if does not apply")
-        //         .prompt_skippable()?;
-
-        //     Ok(AuthConfig::OAuthClientCredentials {
-        //         client_id,
-        //         client_secret,
-        //         token_url: token_url.parse()?,
-        //         scope,
-        //     })
-        // }
+        Some(2) => {
+            // OAuth, credentials are obtained with `pctx auth login`
+            Ok(AuthConfig::OAuth { scopes: vec![] })
+        }
+        Some(3) => {
+            // OAuth2
+            let token_url = inquire::Text::new("├── Token URL:")
+                .with_validator(validators::url)
+                .prompt()?;
+
+            let client_id_key = Case::Snake.sanitize(format!("{server_name}_client_id"));
+            let client_id = prompt_secret("├── Client ID:", "│   ", &client_id_key)?;
+
+            let client_secret_key = Case::Snake.sanitize(format!("{server_name}_client_secret"));
+            let client_secret = prompt_secret("├── Client Secret:", "│   ", &client_secret_key)?;
+
+            let scope = inquire::Text::new("└── Scopes:")
+                .with_help_message("space separated scopes, leave empty if does not apply")
+                .prompt_skippable()?
+                .filter(|s| !s.trim().is_empty());
+
+            Ok(AuthConfig::OAuthClientCredentials {
+                client_id,
+                client_secret,
+                token_url: token_url.parse()?,
+                scope,
+            })
+        }
         _ => anyhow::bail!("Invalid selection {selection}"),
     }
 }
@@ -134,7 +140,6 @@ pub(crate) mod validators {
     use pctx_config::auth::SecretString;
 
     #[allow(clippy::unnecessary_wraps)]
-    #[allow(unused)]
     pub(crate) fn url(
         val: &str,
     ) -> Result<inquire::validator::Validation, inquire::CustomUserError> {
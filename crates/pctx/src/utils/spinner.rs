@@ -2,7 +2,7 @@ use crate::utils::{CHECK, MARK};
 
 use super::styles::{fmt_green, fmt_red, fmt_yellow};
 use log::{error, info, log_enabled, warn};
-use spinoff::{Color, spinners};
+use spinoff::{Color, Streams, spinners};
 use std::borrow::Cow;
 
 /// Wrapper around `spinoff::Spinner` to handle only
@@ -19,7 +19,13 @@ impl Spinner {
             info!("{}...", msg.into());
             None
         } else {
-            Some(spinoff::Spinner::new(spinners::Dots, msg, Color::Cyan))
+            // stderr like the logs, keeping stdout free for `pctx start --stdio`
+            Some(spinoff::Spinner::new_with_stream(
+                spinners::Dots,
+                msg,
+                Color::Cyan,
+                Streams::Stderr,
+            ))
         };
         Self { sp }
     }
@@ -37,6 +37,22 @@ async fn op_call_mcp_tool(#[serde] _args: CallMCPToolArgs) -> serde_json::Value
     serde_json::Value::Null
 }
 
+/// Read an MCP resource (async stub)
+#[deno_core::op2(async)]
+#[serde]
+#[allow(clippy::unused_async)]
+async fn op_read_mcp_resource(#[serde] _args: serde_json::Value) -> serde_json::Value {
+    serde_json::Value::Null
+}
+
+/// Get an MCP prompt (async stub)
+#[deno_core::op2(async)]
+#[serde]
+#[allow(clippy::unused_async)]
+async fn op_get_mcp_prompt(#[serde] _args: serde_json::Value) -> serde_json::Value {
+    serde_json::Value::Null
+}
+
 /// Check if an MCP server is registered (stub)
 #[deno_core::op2(fast)]
 fn op_mcp_has(_state: &mut OpState, #[string] _name: String) -> bool {
@@ -79,6 +95,8 @@ extension!(
         // Op declarations - these will be registered but not executed during snapshot
         op_register_mcp,
         op_call_mcp_tool,
+        op_read_mcp_resource,
+        op_get_mcp_prompt,
         op_mcp_has,
         op_mcp_get,
         op_mcp_delete,
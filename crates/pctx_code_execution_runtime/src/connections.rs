@@ -0,0 +1,578 @@
+//! Pooled upstream MCP client connections
+
+use crate::error::McpError;
+use log::debug;
+use pctx_config::server::{ServerConfig, client_info};
+use rmcp::{
+    ClientHandler, RoleClient,
+    model::{
+        CallToolRequest, CallToolRequestParam, CallToolResult, CancelledNotificationParam,
+        ClientInfo, ClientRequest, GetPromptRequestParam, GetPromptResult,
+        ProgressNotificationParam, ProgressToken, ReadResourceRequestParam, ReadResourceResult,
+        ServerResult,
+    },
+    service::{NotificationContext, Peer, PeerRequestOptions, RunningService, ServiceError},
+};
+use std::collections::HashMap;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+use std::time::{Duration, Instant};
+use tokio::{runtime::Handle, sync::mpsc};
+use tokio_util::sync::CancellationToken;
+
+type McpClient = RunningService<RoleClient, NotificationRelay>;
+
+/// Seconds a connection authenticated with a forwarded credential is kept
+/// after its last use
+pub(crate) const FORWARDED_IDLE_SECS: u64 = 600;
+/// Most connections authenticated with forwarded credentials kept at once,
+/// the least recently used ones are closed beyond
+pub(crate) const MAX_FORWARDED_CONNECTIONS: usize = 64;
+
+/// Progress reported by an upstream server for an in-flight tool call
+#[derive(Debug, Clone)]
+pub struct ToolProgress {
+    /// Name of the registered server
+    pub server: String,
+    /// Name of the tool being called
+    pub tool: String,
+    pub progress: f64,
+    pub total: Option<f64>,
+    pub message: Option<String>,
+}
+
+/// Tool calls awaiting progress, keyed by connection id and progress token
+type ProgressRoutes = Arc<Mutex<HashMap<(u64, ProgressToken), ProgressRoute>>>;
+
+/// Subscribers to `notifications/tools/list_changed`, keyed by server name
+type ToolChangeRoutes = Arc<Mutex<HashMap<String, Vec<mpsc::UnboundedSender<()>>>>>;
+
+struct ProgressRoute {
+    tool: String,
+    tx: mpsc::UnboundedSender<ToolProgress>,
+}
+
+/// Execution the tool calls of a scoped manager belong to
+#[derive(Clone)]
+struct CallScope {
+    progress: mpsc::UnboundedSender<ToolProgress>,
+    cancelled: CancellationToken,
+}
+
+/// Slot holding the live connection for a single upstream server
+pub(crate) type ConnectionSlot = Arc<tokio::sync::Mutex<Option<PooledConnection>>>;
+
+/// Server name and, for servers with `forward` auth, the client credential
+/// the connection authenticates with, so clients never share a session
+pub(crate) type SlotKey = (String, Option<String>);
+
+pub(crate) struct SlotEntry {
+    pub(crate) slot: ConnectionSlot,
+    pub(crate) last_used: Instant,
+}
+
+pub(crate) struct PooledConnection {
+    /// Serialized config the connection was established with, used to detect
+    /// a server being registered under the same name with different settings
+    fingerprint: serde_json::Value,
+    client: Arc<McpClient>,
+}
+
+/// Shared pool of long-lived upstream MCP client connections
+///
+/// Keeps one initialized client per upstream server name so repeated tool calls,
+/// including calls from separate executions, reuse the same session instead of
+/// performing a full initialize handshake each time. Connections are established
+/// lazily on first use and re-established transparently when the upstream
+/// session is lost.
+///
+/// Connections are spawned onto the runtime the manager was created on, so a
+/// manager created by a long-running process can be safely shared with
+/// executions driven by short-lived runtimes.
+#[derive(Clone)]
+pub struct MCPConnectionManager {
+    handle: Handle,
+    slots: Arc<Mutex<HashMap<SlotKey, SlotEntry>>>,
+    progress: ProgressRoutes,
+    tool_changes: ToolChangeRoutes,
+    next_connection: Arc<AtomicU64>,
+    scope: Option<CallScope>,
+    /// Credentials forwarded by the calling client, keyed by server name
+    credentials: Arc<HashMap<String, String>>,
+    /// Servers configured outside of the sandbox, keyed by name
+    servers: Arc<HashMap<String, ServerConfig>>,
+}
+
+impl MCPConnectionManager {
+    /// Create an empty connection manager bound to the current Tokio runtime
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime
+    pub fn new() -> Self {
+        Self {
+            handle: Handle::current(),
+            slots: Arc::new(Mutex::new(HashMap::new())),
+            progress: Arc::default(),
+            tool_changes: Arc::default(),
+            next_connection: Arc::default(),
+            scope: None,
+            credentials: Arc::default(),
+            servers: Arc::default(),
+        }
+    }
+
+    /// Create a handle on the same connections whose tool calls report
+    /// upstream progress to `progress` and are cancelled upstream (with
+    /// `notifications/cancelled`) once `cancelled` is cancelled
+    #[must_use]
+    pub fn scoped(
+        &self,
+        progress: mpsc::UnboundedSender<ToolProgress>,
+        cancelled: CancellationToken,
+    ) -> Self {
+        Self {
+            scope: Some(CallScope {
+                progress,
+                cancelled,
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Create a handle on the same connections whose requests to servers with
+    /// `forward` auth send `credentials` (keyed by server name), the
+    /// credentials of the client an execution runs for
+    ///
+    /// The credentials stay on the Rust side of the runtime, code running in
+    /// the sandbox never sees them, and are only sent to servers passed to
+    /// [`MCPConnectionManager::configured`].
+    #[must_use]
+    pub fn forwarding(&self, credentials: HashMap<String, String>) -> Self {
+        Self {
+            credentials: Arc::new(credentials),
+            ..self.clone()
+        }
+    }
+
+    /// Create a handle on the same connections that knows `servers`, the
+    /// servers configured outside of the sandbox
+    ///
+    /// Code running in the sandbox can call these servers without registering
+    /// them, but can neither register nor delete a server under their names,
+    /// so their settings (e.g. tool filters) cannot be changed from within it.
+    #[must_use]
+    pub fn configured(&self, servers: Vec<ServerConfig>) -> Self {
+        Self {
+            servers: Arc::new(servers.into_iter().map(|s| (s.name.clone(), s)).collect()),
+            ..self.clone()
+        }
+    }
+
+    /// Configuration of the server `name`, if it was configured outside of the sandbox
+    pub fn configured_server(&self, name: &str) -> Option<&ServerConfig> {
+        self.servers.get(name)
+    }
+
+    /// Call a tool on the upstream server described by `cfg`, reusing the
+    /// pooled connection if one exists
+    ///
+    /// If the pooled session turns out to be gone (e.g. the server expired it or
+    /// the stdio process exited) the connection is re-established and the call
+    /// retried once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a connection cannot be established or the tool call fails
+    pub async fn call_tool(
+        &self,
+        cfg: &ServerConfig,
+        params: CallToolRequestParam,
+    ) -> Result<CallToolResult, McpError> {
+        self.request(cfg, |client| {
+            let params = params.clone();
+            async move { self.send_call_tool(cfg, &client, params).await }
+        })
+        .await
+    }
+
+    /// Read a resource of the upstream server described by `cfg`, reconnecting
+    /// like [`MCPConnectionManager::call_tool`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a connection cannot be established or the read fails
+    pub async fn read_resource(
+        &self,
+        cfg: &ServerConfig,
+        params: ReadResourceRequestParam,
+    ) -> Result<ReadResourceResult, McpError> {
+        self.request(cfg, |client| {
+            let params = params.clone();
+            async move { client.read_resource(params).await }
+        })
+        .await
+    }
+
+    /// Get a prompt of the upstream server described by `cfg`, reconnecting
+    /// like [`MCPConnectionManager::call_tool`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a connection cannot be established or the prompt request fails
+    pub async fn get_prompt(
+        &self,
+        cfg: &ServerConfig,
+        params: GetPromptRequestParam,
+    ) -> Result<GetPromptResult, McpError> {
+        self.request(cfg, |client| {
+            let params = params.clone();
+            async move { client.get_prompt(params).await }
+        })
+        .await
+    }
+
+    /// Peer of the pooled connection to the upstream server described by
+    /// `cfg`, connecting if there is no live connection
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a connection cannot be established
+    pub async fn peer(&self, cfg: &ServerConfig) -> Result<Peer<RoleClient>, McpError> {
+        let (cfg, key) = self.forwarded(cfg);
+        let client = self.client(&cfg, &key).await?;
+        Ok(client.peer().clone())
+    }
+
+    /// Receive a message whenever a pooled connection to the server `name`
+    /// reports `notifications/tools/list_changed`, including connections
+    /// established after subscribing
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (i.e., a thread panicked while holding the lock)
+    pub fn tool_changes(&self, name: &str) -> mpsc::UnboundedReceiver<()> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut routes = self.tool_changes.lock().unwrap();
+        routes.retain(|_, subscribers| {
+            subscribers.retain(|s| !s.is_closed());
+            !subscribers.is_empty()
+        });
+        routes.entry(name.to_string()).or_default().push(tx);
+        rx
+    }
+
+    /// Close the pooled connection to the server `name`, if any
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (i.e., a thread panicked while holding the lock)
+    pub async fn close(&self, name: &str) {
+        let slots: Vec<ConnectionSlot> = {
+            let mut slots = self.slots.lock().unwrap();
+            let keys: Vec<SlotKey> = slots.keys().filter(|(n, _)| n == name).cloned().collect();
+            keys.iter()
+                .filter_map(|key| slots.remove(key))
+                .map(|entry| entry.slot)
+                .collect()
+        };
+
+        for slot in slots {
+            if let Some(conn) = slot.lock().await.take() {
+                conn.client.cancellation_token().cancel();
+            }
+        }
+    }
+
+    /// Close every pooled connection
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (i.e., a thread panicked while holding the lock)
+    pub async fn close_all(&self) {
+        let slots: Vec<ConnectionSlot> = self
+            .slots
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, e)| e.slot)
+            .collect();
+
+        for slot in slots {
+            if let Some(conn) = slot.lock().await.take() {
+                conn.client.cancellation_token().cancel();
+            }
+        }
+    }
+
+    /// Send a request with the pooled client for `cfg`, retrying once on a
+    /// fresh connection if the pooled session was lost
+    async fn request<T, F, Fut>(&self, cfg: &ServerConfig, send: F) -> Result<T, McpError>
+    where
+        F: Fn(Arc<McpClient>) -> Fut,
+        Fut: Future<Output = Result<T, ServiceError>>,
+    {
+        let (cfg, key) = self.forwarded(cfg);
+        let client = self.client(&cfg, &key).await?;
+
+        match send(client.clone()).await {
+            Err(ServiceError::TransportSend(_) | ServiceError::TransportClosed) => {
+                debug!(
+                    "Connection to MCP server \"{}\" was lost, reconnecting",
+                    cfg.name
+                );
+                self.evict(&key, &client).await;
+                let client = self.client(&cfg, &key).await?;
+                send(client).await.map_err(|e| request_error(&cfg, &e))
+            }
+            res => res.map_err(|e| request_error(&cfg, &e)),
+        }
+    }
+
+    /// `cfg` sending the credential the calling client forwarded for it, if
+    /// it has `forward` auth, and the slot of its connection
+    ///
+    /// Credentials are only sent to the server they were forwarded for: `cfg`
+    /// must have the exact settings of the configured server of its name, so a
+    /// config pointing the name at another URL or command never receives them.
+    fn forwarded(&self, cfg: &ServerConfig) -> (ServerConfig, SlotKey) {
+        let credential = self.credentials.get(&cfg.name).filter(|_| {
+            cfg.forwarded_header().is_some()
+                && self.servers.get(&cfg.name).is_some_and(|configured| {
+                    matches!(
+                        (fingerprint(configured), fingerprint(cfg)),
+                        (Ok(a), Ok(b)) if a == b
+                    )
+                })
+        });
+        match credential {
+            Some(credential) => (
+                cfg.with_forwarded_credential(credential),
+                (cfg.name.clone(), Some(credential.clone())),
+            ),
+            None => (cfg.clone(), (cfg.name.clone(), None)),
+        }
+    }
+
+    /// Send a `tools/call` request, routing its progress notifications to the
+    /// manager's scope and cancelling it with the scope
+    async fn send_call_tool(
+        &self,
+        cfg: &ServerConfig,
+        client: &McpClient,
+        params: CallToolRequestParam,
+    ) -> Result<CallToolResult, ServiceError> {
+        let Some(scope) = &self.scope else {
+            return client.call_tool(params).await;
+        };
+
+        let tool = params.name.to_string();
+        let handle = client
+            .send_cancellable_request(
+                ClientRequest::CallToolRequest(CallToolRequest::new(params)),
+                PeerRequestOptions::no_options(),
+            )
+            .await?;
+        let route = (client.service().connection, handle.progress_token.clone());
+        self.progress.lock().unwrap().insert(
+            route.clone(),
+            ProgressRoute {
+                tool,
+                tx: scope.progress.clone(),
+            },
+        );
+
+        let response = tokio::select! {
+            res = handle.rx => res.unwrap_or(Err(ServiceError::TransportClosed)),
+            () = scope.cancelled.cancelled() => {
+                let reason = Some("Execution was cancelled".to_string());
+                if let Err(e) = handle
+                    .peer
+                    .notify_cancelled(CancelledNotificationParam {
+                        request_id: handle.id,
+                        reason: reason.clone(),
+                    })
+                    .await
+                {
+                    debug!("Failed cancelling tool call on \"{}\": {e}", cfg.name);
+                }
+                Err(ServiceError::Cancelled { reason })
+            }
+        };
+        self.progress.lock().unwrap().remove(&route);
+
+        match response? {
+            ServerResult::CallToolResult(result) => Ok(result),
+            _ => Err(ServiceError::UnexpectedResponse),
+        }
+    }
+
+    /// Get the pooled client for `cfg`, connecting if there is no live connection
+    async fn client(&self, cfg: &ServerConfig, key: &SlotKey) -> Result<Arc<McpClient>, McpError> {
+        let fingerprint = fingerprint(cfg)?;
+        let slot = self.slot(key);
+        let mut slot = slot.lock().await;
+
+        if let Some(conn) = slot.as_ref() {
+            if conn.fingerprint == fingerprint && !conn.client.is_transport_closed() {
+                return Ok(conn.client.clone());
+            }
+            debug!("Replacing stale connection to MCP server \"{}\"", cfg.name);
+        }
+
+        // connect on the manager's runtime so the client's background tasks
+        // outlive the caller's runtime
+        let to_connect = cfg.clone();
+        let relay = NotificationRelay {
+            connection: self.next_connection.fetch_add(1, Ordering::Relaxed),
+            server: cfg.name.clone(),
+            routes: self.progress.clone(),
+            tool_changes: self.tool_changes.clone(),
+        };
+        let client = self
+            .handle
+            .spawn(async move { to_connect.connect_with(relay).await })
+            .await
+            .map_err(|e| McpError::Connection(format!("Connection task failed: {e}")))??;
+        debug!("Connected to MCP server \"{}\"", cfg.name);
+
+        let client = Arc::new(client);
+        *slot = Some(PooledConnection {
+            fingerprint,
+            client: client.clone(),
+        });
+
+        Ok(client)
+    }
+
+    /// Drop the pooled connection of `key` if it is still `client`
+    async fn evict(&self, key: &SlotKey, client: &Arc<McpClient>) {
+        let slot = self.slot(key);
+        let mut slot = slot.lock().await;
+        if slot
+            .as_ref()
+            .is_some_and(|conn| Arc::ptr_eq(&conn.client, client))
+        {
+            *slot = None;
+        }
+    }
+
+    fn slot(&self, key: &SlotKey) -> ConnectionSlot {
+        let mut slots = self.slots.lock().unwrap();
+        let now = Instant::now();
+        let entry = slots.entry(key.clone()).or_insert_with(|| SlotEntry {
+            slot: ConnectionSlot::default(),
+            last_used: now,
+        });
+        entry.last_used = now;
+        let slot = entry.slot.clone();
+
+        prune_forwarded(&mut slots, now);
+        slot
+    }
+}
+
+impl Default for MCPConnectionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drop the slots of connections authenticated with forwarded credentials
+/// that are idle or beyond [`MAX_FORWARDED_CONNECTIONS`], least recently
+/// used first
+///
+/// Each client credential has its own connection (a child process for stdio
+/// servers), these would otherwise pile up for as long as pctx runs. A
+/// dropped connection is closed once the calls still using it complete.
+pub(crate) fn prune_forwarded(slots: &mut HashMap<SlotKey, SlotEntry>, now: Instant) {
+    let idle = Duration::from_secs(FORWARDED_IDLE_SECS);
+    slots.retain(|(_, credential), entry| {
+        credential.is_none() || now.duration_since(entry.last_used) < idle
+    });
+
+    let mut forwarded: Vec<(SlotKey, Instant)> = slots
+        .iter()
+        .filter(|((_, credential), _)| credential.is_some())
+        .map(|(key, entry)| (key.clone(), entry.last_used))
+        .collect();
+    if forwarded.len() > MAX_FORWARDED_CONNECTIONS {
+        forwarded.sort_by_key(|(_, last_used)| *last_used);
+        let excess = forwarded.len() - MAX_FORWARDED_CONNECTIONS;
+        for (key, _) in forwarded.into_iter().take(excess) {
+            debug!(
+                "Closing least recently used connection to MCP server \"{}\"",
+                key.0
+            );
+            slots.remove(&key);
+        }
+    }
+}
+
+/// Client handler of pooled connections, forwarding `notifications/progress`
+/// to the scope of the tool call they belong to and
+/// `notifications/tools/list_changed` to the subscribers of the server
+#[derive(Clone)]
+struct NotificationRelay {
+    /// Id of the connection, progress tokens are only unique per connection
+    connection: u64,
+    server: String,
+    routes: ProgressRoutes,
+    tool_changes: ToolChangeRoutes,
+}
+
+impl ClientHandler for NotificationRelay {
+    async fn on_progress(
+        &self,
+        params: ProgressNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        let routes = self.routes.lock().unwrap();
+        if let Some(route) = routes.get(&(self.connection, params.progress_token)) {
+            let _ = route.tx.send(ToolProgress {
+                server: self.server.clone(),
+                tool: route.tool.clone(),
+                progress: params.progress,
+                total: params.total,
+                message: params.message,
+            });
+        }
+    }
+
+    async fn on_tool_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        let mut routes = self.tool_changes.lock().unwrap();
+        if let Some(subscribers) = routes.get_mut(&self.server) {
+            subscribers.retain(|tx| tx.send(()).is_ok());
+        }
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        client_info()
+    }
+}
+
+/// Serialized settings of `cfg`, two configs with the same fingerprint
+/// connect to the same server in the same way
+fn fingerprint(cfg: &ServerConfig) -> Result<serde_json::Value, McpError> {
+    serde_json::to_value(cfg)
+        .map_err(|e| McpError::Config(format!("Invalid MCP server config: {e}")))
+}
+
+fn request_error(cfg: &ServerConfig, err: &ServiceError) -> McpError {
+    let code = match err {
+        ServiceError::McpError(data) => Some(data.code.0),
+        _ => None,
+    };
+    McpError::Request {
+        message: format!("MCP server \"{}\" call failed: {err}", cfg.name),
+        code,
+        transport: matches!(
+            err,
+            ServiceError::TransportSend(_)
+                | ServiceError::TransportClosed
+                | ServiceError::Timeout { .. }
+        ),
+    }
+}
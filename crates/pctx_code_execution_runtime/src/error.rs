@@ -1,6 +1,7 @@
 //! Error types for PCTX runtime
 
 use pctx_config::server::McpConnectionError;
+use serde::Serialize;
 
 /// Error type for MCP operations
 #[derive(Debug, thiserror::Error)]
@@ -14,6 +15,37 @@ pub enum McpError {
     /// Tool call error (HTTP, parsing, etc.)
     #[error("MCP tool call error: {0}")]
     ToolCall(String),
+    /// Request the server rejected or that was lost in transit
+    #[error("MCP request error: {message}")]
+    Request {
+        message: String,
+        /// JSON-RPC error code, if the server answered with an error
+        code: Option<i32>,
+        /// Whether the request failed to reach the server or get its answer
+        transport: bool,
+    },
+    /// Upstream tool call that failed, thrown in the sandbox as an `MCPToolError`
+    #[error("{0}")]
+    Tool(Box<ToolCallError>),
+}
+
+/// Details of a failed upstream tool call, mirroring the sandbox's `MCPToolError`
+#[derive(Debug, Clone, Serialize, thiserror::Error)]
+#[serde(rename_all = "camelCase")]
+#[error("{message}")]
+pub struct ToolCallError {
+    pub message: String,
+    /// Name of the registered server
+    pub server: String,
+    /// Name of the tool that was called
+    pub tool: String,
+    /// Content blocks the server returned along with the error
+    pub content: Vec<serde_json::Value>,
+    /// Whether the call failed to reach the server rather than being rejected by it
+    pub is_transport_error: bool,
+    /// JSON-RPC error code, if the server rejected the call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<i32>,
 }
 
 impl From<McpConnectionError> for McpError {
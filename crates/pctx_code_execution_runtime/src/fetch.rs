@@ -3,6 +3,7 @@
 //! This module provides a fetch function that only allows requests to specific allowed hosts
 
 use crate::error::McpError;
+use pctx_config::network::NetworkConfig;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::sync::{Arc, RwLock};
@@ -97,6 +98,7 @@ pub(crate) async fn fetch_with_permissions(
     url: String,
     options: Option<FetchOptions>,
     allowed_hosts: &AllowedHosts,
+    network: &NetworkConfig,
 ) -> Result<FetchResponse, McpError> {
     // Parse URL and extract host (with port if present)
     let parsed_url =
@@ -120,8 +122,12 @@ pub(crate) async fn fetch_with_permissions(
         )));
     }
 
-    // Build request
-    let client = reqwest::Client::new();
+    // Build request, going through the configured proxy & TLS settings
+    let client = network
+        .client_builder()
+        .await
+        .and_then(|b| Ok(b.build()?))
+        .map_err(|e| McpError::ToolCall(format!("Invalid network settings: {e:#}")))?;
     let method = options
         .as_ref()
         .and_then(|o| o.method.as_deref())
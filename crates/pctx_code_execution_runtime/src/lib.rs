@@ -24,17 +24,26 @@
 //!
 //! ```rust,no_run
 //! use deno_core::{JsRuntime, RuntimeOptions};
-//! use pctx_code_execution_runtime::{pctx_runtime_snapshot, MCPRegistry, AllowedHosts, RUNTIME_SNAPSHOT};
+//! use pctx_code_execution_runtime::{
+//!     pctx_runtime_snapshot, AllowedHosts, MCPConnectionManager, MCPRegistry, NetworkConfig,
+//!     RUNTIME_SNAPSHOT,
+//! };
 //! use std::rc::Rc;
 //!
 //! # fn example() -> Result<(), Box<dyn std::error::Error>> {
 //! // Create a new runtime with the PCTX extension
 //! let registry = MCPRegistry::new();
+//! let connections = MCPConnectionManager::new();
 //! let allowed_hosts = AllowedHosts::new(Some(vec!["example.com".to_string()]));
 //!
 //! let mut runtime = JsRuntime::new(RuntimeOptions {
 //!     startup_snapshot: Some(RUNTIME_SNAPSHOT),
-//!     extensions: vec![pctx_runtime_snapshot::init(registry, allowed_hosts)],
+//!     extensions: vec![pctx_runtime_snapshot::init(
+//!         registry,
+//!         connections,
+//!         allowed_hosts,
+//!         NetworkConfig::default(),
+//!     )],
 //!     ..Default::default()
 //! });
 //!
@@ -60,6 +69,8 @@
 //!
 //! - `registerMCP(config)` - Register an MCP server
 //! - `callMCPTool(call)` - Call a tool on a registered server
+//! - `readMCPResource({ name, uri })` - Read a resource of a registered server
+//! - `getMCPPrompt({ name, prompt, arguments })` - Get a prompt of a registered server
 //! - `REGISTRY.has(name)` - Check if a server is registered
 //! - `REGISTRY.get(name)` - Get server configuration
 //! - `REGISTRY.delete(name)` - Remove a server
@@ -79,6 +90,7 @@
 //!
 //! - Network access is controlled via `AllowedHosts` whitelist
 //! - Each runtime instance has its own isolated MCP registry
+//! - Upstream connections can be pooled across runtimes with a shared `MCPConnectionManager`
 //! - No file system access is provided by default
 //!
 //! ## Performance
@@ -87,6 +99,7 @@
 //! - **Memory**: ~2MB base runtime overhead
 //! - **Operations**: Rust ops provide native performance
 
+mod connections;
 mod error;
 mod fetch;
 mod js_error_impl;
@@ -96,7 +109,10 @@ mod registry;
 #[cfg(test)]
 mod tests;
 
+pub use connections::{MCPConnectionManager, ToolProgress};
 pub use fetch::AllowedHosts;
+pub use pctx_config::network::NetworkConfig;
+pub use pctx_config::server::ServerConfig;
 pub use registry::MCPRegistry;
 
 /// Pre-compiled V8 snapshot containing the PCTX runtime
@@ -113,15 +129,24 @@ pub use registry::MCPRegistry;
 ///
 /// ```rust,no_run
 /// use deno_core::{JsRuntime, RuntimeOptions};
-/// use pctx_code_execution_runtime::{RUNTIME_SNAPSHOT, pctx_runtime_snapshot, MCPRegistry, AllowedHosts};
+/// use pctx_code_execution_runtime::{
+///     RUNTIME_SNAPSHOT, pctx_runtime_snapshot, MCPConnectionManager, MCPRegistry, AllowedHosts,
+///     NetworkConfig,
+/// };
 ///
 /// # fn example() -> Result<(), Box<dyn std::error::Error>> {
 /// let registry = MCPRegistry::new();
+/// let connections = MCPConnectionManager::new();
 /// let allowed_hosts = AllowedHosts::new(None);
 ///
 /// let mut runtime = JsRuntime::new(RuntimeOptions {
 ///     startup_snapshot: Some(RUNTIME_SNAPSHOT),
-///     extensions: vec![pctx_runtime_snapshot::init(registry, allowed_hosts)],
+///     extensions: vec![pctx_runtime_snapshot::init(
+///         registry,
+///         connections,
+///         allowed_hosts,
+///         NetworkConfig::default(),
+///     )],
 ///     ..Default::default()
 /// });
 /// # Ok(())
@@ -131,13 +156,15 @@ pub static RUNTIME_SNAPSHOT: &[u8] =
     include_bytes!(concat!(env!("OUT_DIR"), "/PCTX_RUNTIME_SNAPSHOT.bin"));
 
 // Deno extension providing MCP client and console capturing.
-// Initialize with MCPRegistry and AllowedHosts configuration.
+// Initialize with MCPRegistry, MCPConnectionManager, AllowedHosts and NetworkConfig configuration.
 // See README.md for complete documentation.
 deno_core::extension!(
     pctx_runtime_snapshot,
     ops = [
         ops::op_register_mcp,
         ops::op_call_mcp_tool,
+        ops::op_read_mcp_resource,
+        ops::op_get_mcp_prompt,
         ops::op_mcp_has,
         ops::op_mcp_get,
         ops::op_mcp_delete,
@@ -148,10 +175,14 @@ deno_core::extension!(
     esm = [ dir "src", "runtime.js" ],
     options = {
         registry: MCPRegistry,
+        connections: MCPConnectionManager,
         allowed_hosts: AllowedHosts,
+        network: NetworkConfig,
     },
     state = |state, options| {
         state.put(options.registry);
+        state.put(options.connections);
         state.put(options.allowed_hosts);
+        state.put(options.network);
     },
 );
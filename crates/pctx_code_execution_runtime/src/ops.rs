@@ -4,13 +4,16 @@
 
 use deno_core::OpState;
 use deno_core::op2;
-use pctx_config::server::ServerConfig;
+use pctx_config::network::NetworkConfig;
+use pctx_config::server::{ServerConfig, ServerConnection};
+use serde_json::json;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::connections::MCPConnectionManager;
 use crate::error::McpError;
 use crate::fetch::{AllowedHosts, FetchOptions, FetchResponse};
-use crate::registry::{CallMCPToolArgs, MCPRegistry};
+use crate::registry::{CallMCPToolArgs, GetMCPPromptArgs, MCPRegistry, ReadMCPResourceArgs};
 
 /// Register an MCP server
 #[op2]
@@ -19,6 +22,8 @@ pub(crate) fn op_register_mcp(
     state: &mut OpState,
     #[serde] config: ServerConfig,
 ) -> Result<(), McpError> {
+    reject_configured(state, &config.name)?;
+    reject_unsandboxed(&config)?;
     let registry = state.borrow::<MCPRegistry>();
     registry.add(config)
 }
@@ -30,11 +35,54 @@ pub(crate) async fn op_call_mcp_tool(
     state: Rc<RefCell<OpState>>,
     #[serde] args: CallMCPToolArgs,
 ) -> Result<serde_json::Value, McpError> {
-    let registry = {
+    let (registry, connections) = {
         let borrowed = state.borrow();
-        borrowed.borrow::<MCPRegistry>().clone()
+        (
+            borrowed.borrow::<MCPRegistry>().clone(),
+            borrowed.borrow::<MCPConnectionManager>().clone(),
+        )
     };
-    crate::registry::call_mcp_tool(&registry, args).await
+    // failed calls are resolved rather than rejected so the JS side can throw
+    // an `MCPToolError` carrying their details
+    match crate::registry::call_mcp_tool(&registry, &connections, args).await {
+        Ok(value) => Ok(json!({ "value": value })),
+        Err(McpError::Tool(failure)) => Ok(json!({ "error": failure })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read an MCP resource (async op)
+#[op2(async)]
+#[serde]
+pub(crate) async fn op_read_mcp_resource(
+    state: Rc<RefCell<OpState>>,
+    #[serde] args: ReadMCPResourceArgs,
+) -> Result<serde_json::Value, McpError> {
+    let (registry, connections) = {
+        let borrowed = state.borrow();
+        (
+            borrowed.borrow::<MCPRegistry>().clone(),
+            borrowed.borrow::<MCPConnectionManager>().clone(),
+        )
+    };
+    crate::registry::read_mcp_resource(&registry, &connections, args).await
+}
+
+/// Get an MCP prompt (async op)
+#[op2(async)]
+#[serde]
+pub(crate) async fn op_get_mcp_prompt(
+    state: Rc<RefCell<OpState>>,
+    #[serde] args: GetMCPPromptArgs,
+) -> Result<serde_json::Value, McpError> {
+    let (registry, connections) = {
+        let borrowed = state.borrow();
+        (
+            borrowed.borrow::<MCPRegistry>().clone(),
+            borrowed.borrow::<MCPConnectionManager>().clone(),
+        )
+    };
+    crate::registry::get_mcp_prompt(&registry, &connections, args).await
 }
 
 /// Check if an MCP server is registered
@@ -57,9 +105,10 @@ pub(crate) fn op_mcp_get(state: &mut OpState, #[string] name: String) -> Option<
 /// Delete an MCP server configuration
 #[op2(fast)]
 #[allow(clippy::needless_pass_by_value)]
-pub(crate) fn op_mcp_delete(state: &mut OpState, #[string] name: String) -> bool {
+pub(crate) fn op_mcp_delete(state: &mut OpState, #[string] name: String) -> Result<bool, McpError> {
+    reject_configured(state, &name)?;
     let registry = state.borrow::<MCPRegistry>();
-    registry.delete(&name)
+    Ok(registry.delete(&name))
 }
 
 /// Clear all MCP server configurations
@@ -69,6 +118,40 @@ pub(crate) fn op_mcp_clear(state: &mut OpState) {
     registry.clear();
 }
 
+/// Fails if `name` is a server configured outside of the sandbox, which
+/// sandboxed code must not replace
+fn reject_configured(state: &OpState, name: &str) -> Result<(), McpError> {
+    if state
+        .borrow::<MCPConnectionManager>()
+        .configured_server(name)
+        .is_some()
+    {
+        return Err(McpError::Config(format!(
+            "MCP Server \"{name}\" is configured by pctx and cannot be registered or deleted"
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects the settings of `config` that would act outside of the sandbox:
+/// stdio servers spawn a process on the host, and `network` overrides how
+/// pctx itself reaches the network
+fn reject_unsandboxed(config: &ServerConfig) -> Result<(), McpError> {
+    if matches!(config.connection, ServerConnection::Stdio(_)) {
+        return Err(McpError::Config(format!(
+            "MCP Server \"{}\" cannot be registered: stdio servers can only be configured in pctx",
+            config.name
+        )));
+    }
+    if config.network.is_some() {
+        return Err(McpError::Config(format!(
+            "MCP Server \"{}\" cannot be registered: `network` can only be configured in pctx",
+            config.name
+        )));
+    }
+    Ok(())
+}
+
 /// Fetch with host-based permissions
 #[op2(async)]
 #[serde]
@@ -77,9 +160,12 @@ pub(crate) async fn op_fetch(
     #[string] url: String,
     #[serde] options: Option<FetchOptions>,
 ) -> Result<FetchResponse, McpError> {
-    let allowed_hosts = {
+    let (allowed_hosts, network) = {
         let borrowed = state.borrow();
-        borrowed.borrow::<AllowedHosts>().clone()
+        (
+            borrowed.borrow::<AllowedHosts>().clone(),
+            borrowed.borrow::<NetworkConfig>().clone(),
+        )
     };
-    crate::fetch::fetch_with_permissions(url, options, &allowed_hosts).await
+    crate::fetch::fetch_with_permissions(url, options, &allowed_hosts, &network).await
 }
@@ -1,7 +1,12 @@
-use crate::error::McpError;
+use crate::connections::MCPConnectionManager;
+use crate::error::{McpError, ToolCallError};
 use pctx_config::server::ServerConfig;
-use rmcp::model::{CallToolRequestParam, JsonObject, RawContent};
+use rmcp::model::{
+    CallToolRequestParam, Content, GetPromptRequestParam, JsonObject, RawContent,
+    ReadResourceRequestParam, ResourceContents,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
@@ -14,6 +19,22 @@ pub(crate) struct CallMCPToolArgs {
     pub arguments: Option<JsonObject>,
 }
 
+/// Arguments for reading an MCP resource
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ReadMCPResourceArgs {
+    pub name: String,
+    pub uri: String,
+}
+
+/// Arguments for getting an MCP prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct GetMCPPromptArgs {
+    pub name: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub arguments: Option<JsonObject>,
+}
+
 /// Singleton registry for MCP server configurations
 #[derive(Clone)]
 pub struct MCPRegistry {
@@ -98,51 +119,235 @@ impl Default for MCPRegistry {
 /// Call an MCP tool on a registered server
 pub(crate) async fn call_mcp_tool(
     registry: &MCPRegistry,
+    connections: &MCPConnectionManager,
     args: CallMCPToolArgs,
 ) -> Result<serde_json::Value, McpError> {
-    // Get the server config from registry
-    let mcp_cfg = registry.get(&args.name).ok_or_else(|| {
-        McpError::ToolCall(format!(
-            "MCP Server with name \"{}\" does not exist",
-            args.name
-        ))
-    })?;
-
-    let client = mcp_cfg.connect().await?;
-    let tool_result = client
-        .call_tool(CallToolRequestParam {
-            name: args.tool.clone().into(),
-            arguments: args.arguments,
-        })
-        .await
-        .unwrap();
-    let _ = client.cancel().await;
+    let mcp_cfg = registered(registry, connections, &args.name)?;
 
-    // Check if the tool call resulted in an error
-    if tool_result.is_error.unwrap_or(false) {
+    // generated namespaces only expose allowed tools, but handwritten calls could still name others
+    if !mcp_cfg.allows_tool(&args.tool) {
         return Err(McpError::ToolCall(format!(
-            "Tool call \"{}.{}\" failed",
+            "Tool \"{}.{}\" is not allowed by the server's tool filters",
             args.name, args.tool
         )));
     }
 
+    let tool_result = connections
+        .call_tool(
+            &mcp_cfg,
+            CallToolRequestParam {
+                name: args.tool.clone().into(),
+                arguments: args.arguments,
+            },
+        )
+        .await
+        .map_err(|e| {
+            let (code, transport) = match &e {
+                McpError::Request {
+                    code, transport, ..
+                } => (*code, *transport),
+                McpError::Connection(_) => (None, true),
+                _ => return e,
+            };
+            McpError::Tool(Box::new(ToolCallError {
+                message: format!("Tool call \"{}.{}\" failed: {e}", args.name, args.tool),
+                server: args.name.clone(),
+                tool: args.tool.clone(),
+                content: vec![],
+                is_transport_error: transport,
+                code,
+            }))
+        })?;
+
+    // Check if the tool call resulted in an error
+    if tool_result.is_error.unwrap_or(false) {
+        let details: Vec<&str> = tool_result
+            .content
+            .iter()
+            .filter_map(|c| c.as_text().map(|t| t.text.as_str()))
+            .collect();
+        let mut message = format!("Tool call \"{}.{}\" failed", args.name, args.tool);
+        if !details.is_empty() {
+            message = format!("{message}: {}", details.join("\n"));
+        }
+        return Err(McpError::Tool(Box::new(ToolCallError {
+            message,
+            server: args.name,
+            tool: args.tool,
+            content: tool_result
+                .content
+                .iter()
+                .map(|c| json!(ContentPart::from(&**c)))
+                .collect(),
+            is_transport_error: false,
+            code: None,
+        })));
+    }
+
     // Prefer structuredContent if available, otherwise use content array
     if let Some(structured) = tool_result.structured_content {
         return Ok(structured);
     }
 
-    // Convert content to JSON value
-    // For simplicity, we'll extract text content and try to parse as JSON
-    if let Some(RawContent::Text(text_content)) = tool_result.content.first().map(|a| &**a) {
-        // Try to parse as JSON, fallback to string value
-        serde_json::from_str(&text_content.text)
-            .or_else(|_| Ok(serde_json::Value::String(text_content.text.clone())))
-            .map_err(|e: serde_json::Error| {
-                McpError::ToolCall(format!("Failed to parse content: {e}"))
-            })
-    } else {
-        // Return the whole content array as JSON
-        serde_json::to_value(&tool_result.content)
-            .map_err(|e| McpError::ToolCall(format!("Failed to serialize content: {e}")))
+    Ok(content_value(&tool_result.content))
+}
+
+/// Content block of a tool result as handed to the sandbox
+///
+/// Binary data (images, audio and blob resources) is kept base64 encoded
+/// alongside its MIME type.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ContentPart {
+    Text {
+        text: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Image {
+        data: String,
+        mime_type: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Audio {
+        data: String,
+        mime_type: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Resource {
+        uri: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        blob: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    ResourceLink {
+        uri: String,
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
+}
+
+impl From<&RawContent> for ContentPart {
+    fn from(content: &RawContent) -> Self {
+        match content {
+            RawContent::Text(t) => Self::Text {
+                text: t.text.clone(),
+            },
+            RawContent::Image(i) => Self::Image {
+                data: i.data.clone(),
+                mime_type: i.mime_type.clone(),
+            },
+            RawContent::Audio(a) => Self::Audio {
+                data: a.data.clone(),
+                mime_type: a.mime_type.clone(),
+            },
+            RawContent::Resource(r) => match &r.resource {
+                ResourceContents::TextResourceContents {
+                    uri,
+                    mime_type,
+                    text,
+                    ..
+                } => Self::Resource {
+                    uri: uri.clone(),
+                    mime_type: mime_type.clone(),
+                    text: Some(text.clone()),
+                    blob: None,
+                },
+                ResourceContents::BlobResourceContents {
+                    uri,
+                    mime_type,
+                    blob,
+                    ..
+                } => Self::Resource {
+                    uri: uri.clone(),
+                    mime_type: mime_type.clone(),
+                    text: None,
+                    blob: Some(blob.clone()),
+                },
+            },
+            RawContent::ResourceLink(l) => Self::ResourceLink {
+                uri: l.uri.clone(),
+                name: l.name.clone(),
+                mime_type: l.mime_type.clone(),
+            },
+        }
+    }
+}
+
+/// Value of a tool result's content as seen by the sandbox
+///
+/// A single text block is parsed as JSON (falling back on the plain string),
+/// anything else becomes an array of [`ContentPart`]s so no block is lost.
+pub(crate) fn content_value(content: &[Content]) -> serde_json::Value {
+    if let [single] = content
+        && let RawContent::Text(text_content) = &**single
+    {
+        return serde_json::from_str(&text_content.text)
+            .unwrap_or_else(|_| serde_json::Value::String(text_content.text.clone()));
     }
+
+    serde_json::Value::Array(
+        content
+            .iter()
+            .map(|c| json!(ContentPart::from(&**c)))
+            .collect(),
+    )
+}
+
+/// Read a resource of a registered server, returning its contents
+pub(crate) async fn read_mcp_resource(
+    registry: &MCPRegistry,
+    connections: &MCPConnectionManager,
+    args: ReadMCPResourceArgs,
+) -> Result<serde_json::Value, McpError> {
+    let mcp_cfg = registered(registry, connections, &args.name)?;
+
+    let result = connections
+        .read_resource(&mcp_cfg, ReadResourceRequestParam { uri: args.uri })
+        .await?;
+
+    serde_json::to_value(&result.contents)
+        .map_err(|e| McpError::ToolCall(format!("Failed to serialize resource contents: {e}")))
+}
+
+/// Get a prompt of a registered server, returning its description and messages
+pub(crate) async fn get_mcp_prompt(
+    registry: &MCPRegistry,
+    connections: &MCPConnectionManager,
+    args: GetMCPPromptArgs,
+) -> Result<serde_json::Value, McpError> {
+    let mcp_cfg = registered(registry, connections, &args.name)?;
+
+    let result = connections
+        .get_prompt(
+            &mcp_cfg,
+            GetPromptRequestParam {
+                name: args.prompt,
+                arguments: args.arguments,
+            },
+        )
+        .await?;
+
+    serde_json::to_value(&result)
+        .map_err(|e| McpError::ToolCall(format!("Failed to serialize prompt: {e}")))
+}
+
+/// Config of the server `name`, servers configured outside of the sandbox
+/// take precedence over the ones it registered
+fn registered(
+    registry: &MCPRegistry,
+    connections: &MCPConnectionManager,
+    name: &str,
+) -> Result<ServerConfig, McpError> {
+    connections
+        .configured_server(name)
+        .cloned()
+        .or_else(|| registry.get(name))
+        .ok_or_else(|| {
+            McpError::ToolCall(format!("MCP Server with name \"{name}\" does not exist"))
+        })
 }
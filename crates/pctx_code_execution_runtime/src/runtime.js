@@ -70,11 +70,38 @@ console.debug = (...args) => {
  * @param {Object} config - MCP server configuration
  * @param {string} config.name - Unique name for the MCP server
  * @param {string} config.url - URL of the MCP server
+ * @param {string} [config.transport] - HTTP transport, `streamable-http` (default) or `sse`
+ * @throws {Error} If a server with the same name is already registered or configured by pctx,
+ *   or the config describes a stdio server or `network` settings
  */
 export function registerMCP(config) {
     return ops.op_register_mcp(config);
 }
 
+/**
+ * Error thrown when an upstream MCP tool call fails
+ */
+export class MCPToolError extends Error {
+    /**
+     * @param {Object} details - Details of the failed call
+     * @param {string} details.message - Error message
+     * @param {string} details.server - Name of the registered MCP server
+     * @param {string} details.tool - Name of the tool that was called
+     * @param {Array<Object>} [details.content] - Content blocks the server returned with the error
+     * @param {boolean} [details.isTransportError] - Whether the call failed to reach the server
+     * @param {number} [details.code] - JSON-RPC error code, if the server rejected the call
+     */
+    constructor({ message, server, tool, content = [], isTransportError = false, code }) {
+        super(message);
+        this.name = "MCPToolError";
+        this.server = server;
+        this.tool = tool;
+        this.content = content;
+        this.isTransportError = isTransportError;
+        this.code = code;
+    }
+}
+
 /**
  * Call an MCP tool
  * @template T
@@ -83,9 +110,37 @@ export function registerMCP(config) {
  * @param {string} call.tool - Name of the tool to call
  * @param {Object} [call.arguments] - Arguments to pass to the tool
  * @returns {Promise<T>} The tool's response
+ * @throws {MCPToolError} If the server reports an error or cannot be reached
  */
 export async function callMCPTool(call) {
-    return await ops.op_call_mcp_tool(call);
+    const { value, error } = await ops.op_call_mcp_tool(call);
+    if (error) {
+        throw new MCPToolError(error);
+    }
+    return value;
+}
+
+/**
+ * Read an MCP resource
+ * @param {Object} request - Resource to read
+ * @param {string} request.name - Name of the registered MCP server
+ * @param {string} request.uri - URI of the resource
+ * @returns {Promise<Array<{uri: string, mimeType?: string, text?: string, blob?: string}>>} The resource's contents
+ */
+export async function readMCPResource(request) {
+    return await ops.op_read_mcp_resource(request);
+}
+
+/**
+ * Get an MCP prompt
+ * @param {Object} request - Prompt to get
+ * @param {string} request.name - Name of the registered MCP server
+ * @param {string} request.prompt - Name of the prompt
+ * @param {Object<string, string>} [request.arguments] - Arguments filled into the prompt
+ * @returns {Promise<{description?: string, messages: Array<{role: string, content: Object}>}>} The rendered prompt
+ */
+export async function getMCPPrompt(request) {
+    return await ops.op_get_mcp_prompt(request);
 }
 
 /**
@@ -114,6 +169,7 @@ export const REGISTRY = {
      * Delete an MCP server configuration
      * @param {string} name - Name of the MCP server
      * @returns {boolean} True if deleted, false if not found
+     * @throws {Error} If the server is configured by pctx rather than registered by the code
      */
     delete(name) {
         return ops.op_mcp_delete(name);
@@ -140,5 +196,8 @@ async function fetch(url, options) {
 // Make APIs available globally for convenience (matching original behavior)
 globalThis.registerMCP = registerMCP;
 globalThis.callMCPTool = callMCPTool;
+globalThis.MCPToolError = MCPToolError;
+globalThis.readMCPResource = readMCPResource;
+globalThis.getMCPPrompt = getMCPPrompt;
 globalThis.REGISTRY = REGISTRY;
 globalThis.fetch = fetch;
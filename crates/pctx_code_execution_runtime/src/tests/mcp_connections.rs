@@ -0,0 +1,86 @@
+//! Unit tests for the shared MCP connection manager
+
+use pctx_config::server::ServerConfig;
+use rmcp::model::CallToolRequestParam;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::connections::{
+    FORWARDED_IDLE_SECS, MAX_FORWARDED_CONNECTIONS, MCPConnectionManager, SlotEntry,
+    prune_forwarded,
+};
+use crate::error::McpError;
+
+fn call_params() -> CallToolRequestParam {
+    CallToolRequestParam {
+        name: "echo".into(),
+        arguments: None,
+    }
+}
+
+#[tokio::test]
+async fn test_connection_failure_is_reported() {
+    let connections = MCPConnectionManager::new();
+    let config = ServerConfig::new_stdio(
+        "missing".into(),
+        "pctx-test-command-that-does-not-exist".into(),
+        vec![],
+    );
+
+    let err = connections
+        .call_tool(&config, call_params())
+        .await
+        .expect_err("Call should fail when the server cannot be started");
+    assert!(
+        matches!(err, McpError::Connection(_)),
+        "Expected connection error, got: {err:?}"
+    );
+
+    // a failed connection must not be pooled, the next call tries again
+    let err = connections
+        .call_tool(&config, call_params())
+        .await
+        .expect_err("Call should fail again");
+    assert!(matches!(err, McpError::Connection(_)));
+}
+
+#[tokio::test]
+async fn test_close_all_empty() {
+    let connections = MCPConnectionManager::new();
+    connections.close_all().await;
+}
+
+#[test]
+fn test_prune_forwarded() {
+    let idle = Instant::now();
+    let now = idle + Duration::from_secs(FORWARDED_IDLE_SECS + 1);
+    let entry = |last_used| SlotEntry {
+        slot: Arc::default(),
+        last_used,
+    };
+
+    let mut slots = HashMap::new();
+    slots.insert(("shared".to_string(), None), entry(idle));
+    slots.insert(
+        ("github".to_string(), Some("idle".to_string())),
+        entry(idle),
+    );
+    for (i, secs) in (0..=MAX_FORWARDED_CONNECTIONS).zip(2..) {
+        let last_used = idle + Duration::from_secs(secs);
+        slots.insert(
+            ("github".to_string(), Some(format!("user-{i}"))),
+            entry(last_used),
+        );
+    }
+
+    prune_forwarded(&mut slots, now);
+
+    // connections without a forwarded credential are kept however long they're idle
+    assert!(slots.contains_key(&("shared".to_string(), None)));
+    assert!(!slots.contains_key(&("github".to_string(), Some("idle".to_string()))));
+    // the least recently used credential is dropped to stay within the cap
+    assert!(!slots.contains_key(&("github".to_string(), Some("user-0".to_string()))));
+    assert!(slots.contains_key(&("github".to_string(), Some("user-1".to_string()))));
+    assert_eq!(slots.len(), MAX_FORWARDED_CONNECTIONS + 1);
+}
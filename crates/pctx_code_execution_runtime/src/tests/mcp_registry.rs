@@ -1,8 +1,15 @@
 //! Unit tests for MCP Registry operations
 
 use pctx_config::server::ServerConfig;
+use rmcp::model::{Content, ResourceContents};
+use serde_json::json;
 
-use crate::registry::MCPRegistry;
+use crate::connections::MCPConnectionManager;
+use crate::error::McpError;
+use crate::registry::{
+    CallMCPToolArgs, GetMCPPromptArgs, MCPRegistry, ReadMCPResourceArgs, call_mcp_tool,
+    content_value, get_mcp_prompt, read_mcp_resource,
+};
 
 #[test]
 fn test_registry_new() {
@@ -61,7 +68,10 @@ fn test_registry_get() {
 
     let retrieved = registry.get("my-server").expect("Should retrieve server");
     assert_eq!(retrieved.name, "my-server");
-    assert_eq!(retrieved.url, "http://localhost:4000".parse().unwrap());
+    assert_eq!(
+        retrieved.url(),
+        Some(&"http://localhost:4000".parse().unwrap())
+    );
 }
 
 #[test]
@@ -151,8 +161,8 @@ fn test_registry_multiple_servers() {
         assert!(registry.has(name), "Server {name} should exist");
         let config = registry.get(name).expect("Should get server");
         assert_eq!(
-            config.url,
-            url.parse().unwrap(),
+            config.url(),
+            Some(&url.parse().unwrap()),
             "URL should match for {name}"
         );
     }
@@ -190,3 +200,129 @@ fn test_registry_clone() {
         "Original registry should see new server from clone"
     );
 }
+
+#[tokio::test]
+async fn test_call_filtered_tool_is_rejected() {
+    let registry = MCPRegistry::new();
+    let connections = MCPConnectionManager::new();
+
+    let mut config = ServerConfig::new_stdio(
+        "filtered".into(),
+        "pctx-test-command-that-does-not-exist".into(),
+        vec![],
+    );
+    config.exclude_tools = vec!["delete_*".parse().unwrap()];
+    registry.add(config).expect("Should add server");
+
+    // rejected before connecting, a connection error would mean the filter was bypassed
+    let err = call_mcp_tool(
+        &registry,
+        &connections,
+        CallMCPToolArgs {
+            name: "filtered".into(),
+            tool: "delete_repo".into(),
+            arguments: None,
+        },
+    )
+    .await
+    .expect_err("Filtered tool call should fail");
+    assert!(
+        matches!(err, McpError::ToolCall(ref msg) if msg.contains("not allowed")),
+        "Expected tool filter error, got: {err:?}"
+    );
+
+    let err = call_mcp_tool(
+        &registry,
+        &connections,
+        CallMCPToolArgs {
+            name: "filtered".into(),
+            tool: "get_repo".into(),
+            arguments: None,
+        },
+    )
+    .await
+    .expect_err("Server cannot be started");
+    assert!(
+        matches!(err, McpError::Tool(ref e) if e.is_transport_error && e.tool == "get_repo"),
+        "Expected transport tool error, got: {err:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_resources_and_prompts_of_unknown_server() {
+    let registry = MCPRegistry::new();
+    let connections = MCPConnectionManager::new();
+
+    let err = read_mcp_resource(
+        &registry,
+        &connections,
+        ReadMCPResourceArgs {
+            name: "unknown".into(),
+            uri: "file:///README.md".into(),
+        },
+    )
+    .await
+    .expect_err("Reading from an unregistered server should fail");
+    assert!(
+        matches!(err, McpError::ToolCall(ref msg) if msg.contains("does not exist")),
+        "Expected unknown server error, got: {err:?}"
+    );
+
+    let err = get_mcp_prompt(
+        &registry,
+        &connections,
+        GetMCPPromptArgs {
+            name: "unknown".into(),
+            prompt: "summarize".into(),
+            arguments: None,
+        },
+    )
+    .await
+    .expect_err("Getting a prompt from an unregistered server should fail");
+    assert!(
+        matches!(err, McpError::ToolCall(ref msg) if msg.contains("does not exist")),
+        "Expected unknown server error, got: {err:?}"
+    );
+}
+
+#[test]
+fn test_content_value_single_text() {
+    assert_eq!(
+        content_value(&[Content::text(r#"{"count": 2}"#)]),
+        json!({"count": 2}),
+        "A single JSON text block should be parsed"
+    );
+    assert_eq!(
+        content_value(&[Content::text("plain text")]),
+        json!("plain text"),
+        "A single non-JSON text block should stay a string"
+    );
+}
+
+#[test]
+fn test_content_value_keeps_every_block() {
+    let content = vec![
+        Content::text("A chart"),
+        Content::image("aGVsbG8=", "image/png"),
+        Content::resource(ResourceContents::BlobResourceContents {
+            uri: "file:///report.pdf".into(),
+            mime_type: Some("application/pdf".into()),
+            blob: "JVBERi0=".into(),
+            meta: None,
+        }),
+    ];
+
+    assert_eq!(
+        content_value(&content),
+        json!([
+            {"type": "text", "text": "A chart"},
+            {"type": "image", "data": "aGVsbG8=", "mimeType": "image/png"},
+            {
+                "type": "resource",
+                "uri": "file:///report.pdf",
+                "mimeType": "application/pdf",
+                "blob": "JVBERi0="
+            }
+        ])
+    );
+}
@@ -3,5 +3,6 @@
 //! This module contains both unit tests for the Rust MCP client implementation
 //! and integration tests that spin up a JavaScript runtime to test the full stack.
 
+mod mcp_connections;
 mod mcp_registry;
 mod runtime_integration;
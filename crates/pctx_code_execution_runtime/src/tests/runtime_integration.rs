@@ -16,6 +16,7 @@ fn op_test_set_result(#[serde] value: serde_json::Value) -> serde_json::Value {
 /// Helper function to create a JavaScript runtime with `pctx_runtime` extension and test ops
 fn create_test_runtime() -> JsRuntime {
     let registry = MCPRegistry::new();
+    let connections = crate::MCPConnectionManager::new();
     let allowed_hosts = crate::AllowedHosts::default();
 
     // Create a simple extension for test helpers
@@ -24,7 +25,12 @@ fn create_test_runtime() -> JsRuntime {
     JsRuntime::new(RuntimeOptions {
         startup_snapshot: Some(crate::RUNTIME_SNAPSHOT),
         extensions: vec![
-            crate::pctx_runtime_snapshot::init(registry, allowed_hosts),
+            crate::pctx_runtime_snapshot::init(
+                registry,
+                connections,
+                allowed_hosts,
+                crate::NetworkConfig::default(),
+            ),
             test_helpers::init(),
         ],
         ..Default::default()
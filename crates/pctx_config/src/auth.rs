@@ -15,16 +15,36 @@ pub enum AuthConfig {
     Custom {
         headers: IndexMap<String, SecretString>,
     },
-    // TODO: support OAuth client credentials flow?
-    // /// OAuth 2.1 Client Credentials Flow (machine-to-machine)
-    // #[serde(rename = "oauth_client_credentials")]
-    // OAuthClientCredentials {
-    //     client_id: SecretString,
-    //     client_secret: SecretString,
-    //     token_url: url::Url,
-    //     #[serde(skip_serializing_if = "Option::is_none")]
-    //     scope: Option<String>,
-    // },
+    /// OAuth 2.1 authorization code flow, credentials are obtained
+    /// with `pctx auth login` and stored in the keychain
+    #[serde(rename = "oauth")]
+    OAuth {
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        scopes: Vec<String>,
+    },
+    /// OAuth 2.1 Client Credentials Flow (machine-to-machine)
+    #[serde(rename = "oauth_client_credentials")]
+    OAuthClientCredentials {
+        client_id: SecretString,
+        client_secret: SecretString,
+        token_url: url::Url,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scope: Option<String>,
+    },
+    /// Credential of the client calling pctx, forwarded from a header of its
+    /// request so upstream calls are made on its behalf
+    Forward {
+        /// Request header holding the credential, defaults to `X-Upstream-Token-<server name>`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        header: Option<String>,
+        /// Header the credential is sent upstream in, defaults to `Authorization`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        upstream_header: Option<String>,
+        /// Value sent when no client credential is available, e.g. while
+        /// discovering the server's tools on startup
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fallback: Option<SecretString>,
+    },
 }
 
 /// A string that may contain 0 or more embedded secrets
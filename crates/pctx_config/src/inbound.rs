@@ -0,0 +1,122 @@
+//! Authentication of the clients connecting to `pctx start`
+
+use anyhow::Result;
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, time::Duration};
+
+use crate::auth::SecretString;
+
+/// Request headers clients send their `inbound_auth` credentials in
+pub const INBOUND_CREDENTIAL_HEADERS: [&str; 2] = ["Authorization", "X-API-Key"];
+
+/// Default clock skew tolerated when checking the `exp` and `nbf` claims
+const DEFAULT_JWT_LEEWAY_SECS: u64 = 60;
+
+/// Credentials clients must present to the HTTP MCP endpoint, either one of
+/// the `api_keys` or a JWT accepted by `jwt`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InboundAuthConfig {
+    /// Static API keys, sent as `Authorization: Bearer <key>` or `X-API-Key: <key>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<ApiKeyConfig>,
+    /// Validation of JWT bearer tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwt: Option<JwtConfig>,
+}
+
+/// Static API key and the client it identifies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyConfig {
+    /// Name of the client using the key, logged as its identity
+    pub name: String,
+    pub key: SecretString,
+}
+
+/// Validation of JWT bearer tokens signed by a key of a JSON Web Key Set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtConfig {
+    /// URL the JWKS is fetched from, refetched when a token is signed with an unknown key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwks_url: Option<url::Url>,
+    /// Local JWKS file, instead of `jwks_url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwks_file: Option<Utf8PathBuf>,
+    /// Required `iss` claim
+    pub issuer: String,
+    /// Required `aud` claim
+    pub audience: String,
+    /// Seconds of clock skew tolerated when checking `exp` and `nbf`, defaults to 60
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leeway: Option<u64>,
+}
+
+impl JwtConfig {
+    pub fn leeway(&self) -> Duration {
+        Duration::from_secs(self.leeway.unwrap_or(DEFAULT_JWT_LEEWAY_SECS))
+    }
+}
+
+impl InboundAuthConfig {
+    /// Checks the settings can authenticate anyone
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if neither API keys nor JWT validation
+    /// are configured, API key names are not unique, or the JWKS source is not
+    /// exactly one of `jwks_url` and `jwks_file`
+    pub fn validate(&self) -> Result<()> {
+        if self.api_keys.is_empty() && self.jwt.is_none() {
+            anyhow::bail!("`inbound_auth` needs `api_keys` or `jwt`");
+        }
+
+        let mut names = HashSet::new();
+        for key in &self.api_keys {
+            if !names.insert(key.name.as_str()) {
+                anyhow::bail!("API key name '{}' is used more than once", key.name);
+            }
+        }
+
+        if let Some(jwt) = &self.jwt
+            && jwt.jwks_url.is_some() == jwt.jwks_file.is_some()
+        {
+            anyhow::bail!("`inbound_auth.jwt` needs exactly one of `jwks_url` and `jwks_file`");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_inbound_auth_validate() {
+        let empty = InboundAuthConfig::default();
+        assert!(empty.validate().is_err());
+
+        let keys: InboundAuthConfig = serde_json::from_value(json!({
+            "api_keys": [
+                { "name": "ci", "key": "${env:PCTX_CI_KEY}" },
+                { "name": "ci", "key": "plain" }
+            ]
+        }))
+        .unwrap();
+        assert!(keys.validate().is_err());
+
+        let mut jwt: InboundAuthConfig = serde_json::from_value(json!({
+            "jwt": {
+                "jwks_url": "https://auth.example.com/.well-known/jwks.json",
+                "issuer": "https://auth.example.com/",
+                "audience": "pctx"
+            }
+        }))
+        .unwrap();
+        assert!(jwt.validate().is_ok());
+        assert_eq!(jwt.jwt.as_ref().unwrap().leeway().as_secs(), 60);
+
+        jwt.jwt.as_mut().unwrap().jwks_file = Some("jwks.json".into());
+        assert!(jwt.validate().is_err());
+    }
+}
@@ -1,13 +1,20 @@
 use anyhow::{Context, Result};
 use camino::Utf8PathBuf;
+use codegen::case::is_valid_identifier;
 use log::debug;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::fs;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
 
-use crate::server::ServerConfig;
+use crate::{inbound::InboundAuthConfig, network::NetworkConfig, server::ServerConfig};
 
 pub mod auth;
+pub mod inbound;
+pub mod network;
+pub mod oauth;
 pub mod server;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -26,6 +33,21 @@ pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
+    /// Proxy and TLS settings of all upstream servers and sandbox network requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkConfig>,
+
+    /// Credentials clients must present to the HTTP MCP endpoint, anyone
+    /// reaching it is served when unset. Only read when `pctx start` starts,
+    /// config reloads log that changes need a restart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inbound_auth: Option<InboundAuthConfig>,
+
+    /// Also expose the tools of every upstream server as plain MCP tools
+    /// (`<namespace>__<tool>`) next to the code mode tools
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub passthrough: bool,
+
     /// Upstream MCP server configurations
     #[serde(default)]
     pub servers: Vec<ServerConfig>,
@@ -127,4 +149,205 @@ impl Config {
     pub fn get_server_mut(&mut self, name: &str) -> Option<&mut ServerConfig> {
         self.servers.iter_mut().find(|s| s.name == name)
     }
+
+    /// `server` as it should be connected to, with the global `network` and
+    /// `passthrough` settings applied to anything it doesn't set itself
+    pub fn resolve_server(&self, server: &ServerConfig) -> ServerConfig {
+        let mut resolved = server.clone();
+        resolved.passthrough = Some(server.passthrough.unwrap_or(self.passthrough));
+        if let Some(global) = &self.network {
+            resolved.network = Some(
+                server
+                    .network
+                    .as_ref()
+                    .map_or_else(|| global.clone(), |n| n.or(global)),
+            );
+        }
+        resolved
+    }
+
+    /// Every server resolved with [`Config::resolve_server`]
+    pub fn resolved_servers(&self) -> Vec<ServerConfig> {
+        self.servers
+            .iter()
+            .map(|s| self.resolve_server(s))
+            .collect()
+    }
+
+    /// Checks for problems in the config that parsing alone does not catch
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if several servers share a name or
+    /// TypeScript namespace, a namespace is not a valid identifier, or
+    /// `inbound_auth` is invalid
+    pub fn validate(&self) -> Result<()> {
+        if let Some(inbound_auth) = &self.inbound_auth {
+            inbound_auth.validate()?;
+        }
+
+        let mut names = HashSet::new();
+        let mut namespaces: HashMap<String, &str> = HashMap::new();
+        for server in &self.servers {
+            if !names.insert(server.name.as_str()) {
+                anyhow::bail!("Server name '{}' is used more than once", server.name);
+            }
+
+            let namespace = server.namespace();
+            if !is_valid_identifier(&namespace) {
+                anyhow::bail!(
+                    "Namespace '{namespace}' of server '{}' is not a valid TypeScript identifier, \
+                    set a `namespace` made of letters, digits, `_` or `$` that doesn't start with a digit",
+                    server.name
+                );
+            }
+            if let Some(other) = namespaces.insert(namespace.clone(), &server.name) {
+                anyhow::bail!(
+                    "Servers '{other}' and '{}' both use the TypeScript namespace '{namespace}', \
+                    set a distinct `namespace` for one of them",
+                    server.name
+                );
+            }
+
+            // the client's credential for pctx itself must never reach an upstream server
+            if self.inbound_auth.is_some()
+                && let Some(header) = server.forwarded_header()
+                && inbound::INBOUND_CREDENTIAL_HEADERS
+                    .iter()
+                    .any(|h| h.eq_ignore_ascii_case(&header))
+            {
+                anyhow::bail!(
+                    "Server '{}' forwards the `{header}` header, which clients authenticate to pctx with \
+                    when `inbound_auth` is set, set another `header` for its `forward` auth",
+                    server.name
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_duplicate_server_names() {
+        let mut cfg = Config::default();
+        cfg.servers.push(ServerConfig::new(
+            "a".into(),
+            "http://localhost:3000".parse().unwrap(),
+        ));
+        cfg.servers.push(ServerConfig::new(
+            "b".into(),
+            "http://localhost:3001".parse().unwrap(),
+        ));
+        assert!(cfg.validate().is_ok());
+
+        cfg.servers
+            .push(ServerConfig::new_stdio("a".into(), "npx".into(), vec![]));
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_forwarded_inbound_credential() {
+        let mut cfg: Config = serde_json::from_value(json!({
+            "name": "pctx",
+            "servers": [{
+                "name": "github",
+                "url": "https://api.githubcopilot.com/mcp/",
+                "auth": { "type": "forward", "header": "authorization" }
+            }]
+        }))
+        .unwrap();
+        assert!(cfg.validate().is_ok());
+
+        cfg.inbound_auth = Some(
+            serde_json::from_value(json!({ "api_keys": [{ "name": "ci", "key": "plain" }] }))
+                .unwrap(),
+        );
+        assert!(cfg.validate().is_err());
+
+        cfg.servers[0] = serde_json::from_value(json!({
+            "name": "github",
+            "url": "https://api.githubcopilot.com/mcp/",
+            "auth": { "type": "forward" }
+        }))
+        .unwrap();
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn test_resolve_server_network() {
+        let mut cfg: Config = serde_json::from_value(json!({
+            "name": "pctx",
+            "network": { "proxy": "http://proxy.internal:3128" },
+            "servers": [
+                { "name": "a", "url": "http://localhost:3000" },
+                {
+                    "name": "b",
+                    "url": "http://localhost:3001",
+                    "network": { "ca_cert": "ca.pem" }
+                }
+            ]
+        }))
+        .unwrap();
+
+        let resolved = cfg.resolved_servers();
+        let a = resolved[0].network.as_ref().unwrap();
+        assert!(a.proxy.is_some());
+        let b = resolved[1].network.as_ref().unwrap();
+        assert!(b.proxy.is_some());
+        assert_eq!(b.ca_cert, Some("ca.pem".into()));
+        // the global settings are not copied into the saved servers
+        assert!(cfg.servers[0].network.is_none());
+
+        cfg.network = None;
+        assert!(cfg.resolve_server(&cfg.servers[0]).network.is_none());
+    }
+
+    #[test]
+    fn test_resolve_server_passthrough() {
+        let mut cfg: Config = serde_json::from_value(json!({
+            "name": "pctx",
+            "passthrough": true,
+            "servers": [
+                { "name": "a", "url": "http://localhost:3000" },
+                { "name": "b", "url": "http://localhost:3001", "passthrough": false }
+            ]
+        }))
+        .unwrap();
+
+        let resolved = cfg.resolved_servers();
+        assert!(resolved[0].passthrough());
+        assert!(!resolved[1].passthrough());
+
+        cfg.passthrough = false;
+        assert!(!cfg.resolve_server(&cfg.servers[0]).passthrough());
+    }
+
+    #[test]
+    fn test_validate_namespaces() {
+        let mut cfg = Config::default();
+        cfg.servers.push(ServerConfig::new(
+            "my-api".into(),
+            "http://localhost:3000".parse().unwrap(),
+        ));
+        cfg.servers.push(ServerConfig::new(
+            "my_api".into(),
+            "http://localhost:3001".parse().unwrap(),
+        ));
+        let err = cfg.validate().unwrap_err().to_string();
+        assert!(err.contains("'MyApi'"), "unexpected error: {err}");
+
+        cfg.servers[1].namespace = Some("MyOtherApi".into());
+        assert!(cfg.validate().is_ok());
+
+        cfg.servers[1].namespace = Some("delete".into());
+        assert!(cfg.validate().is_err());
+
+        cfg.servers[1].namespace = Some("2fa".into());
+        assert!(cfg.validate().is_err());
+    }
 }
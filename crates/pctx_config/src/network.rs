@@ -0,0 +1,155 @@
+//! Proxy and TLS settings of outgoing HTTP connections
+
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::SecretString;
+
+/// Proxy and TLS settings, configured globally and/or per server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Proxy requests are sent through (e.g. `http://proxy.internal:3128`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<url::Url>,
+    /// Hosts reached without going through `proxy`, in `NO_PROXY` syntax
+    /// (e.g. `localhost`, `.internal`, `10.0.0.0/8`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+    /// PEM bundle of additional CA certificates to trust
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<Utf8PathBuf>,
+    /// PEM client certificate (chain) presented for mutual TLS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<Utf8PathBuf>,
+    /// PEM private key of `client_cert`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<SecretString>,
+}
+
+impl NetworkConfig {
+    /// Settings of `self`, falling back on `defaults` for anything not set
+    #[must_use]
+    pub fn or(&self, defaults: &NetworkConfig) -> NetworkConfig {
+        NetworkConfig {
+            proxy: self.proxy.clone().or_else(|| defaults.proxy.clone()),
+            no_proxy: if self.no_proxy.is_empty() {
+                defaults.no_proxy.clone()
+            } else {
+                self.no_proxy.clone()
+            },
+            ca_cert: self.ca_cert.clone().or_else(|| defaults.ca_cert.clone()),
+            client_cert: self
+                .client_cert
+                .clone()
+                .or_else(|| defaults.client_cert.clone()),
+            client_key: self
+                .client_key
+                .clone()
+                .or_else(|| defaults.client_key.clone()),
+        }
+    }
+
+    /// Creates an HTTP client builder with the proxy and TLS settings applied
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the CA bundle or client certificate
+    /// cannot be read or parsed, or the client key cannot be resolved
+    pub async fn client_builder(&self) -> Result<reqwest::ClientBuilder> {
+        let mut builder = reqwest::Client::builder();
+
+        if let Some(proxy_url) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy_url.as_str())
+                .context(format!("Invalid proxy: {proxy_url}"))?
+                .no_proxy(reqwest::NoProxy::from_string(&self.no_proxy.join(",")));
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(path) = &self.ca_cert {
+            let pem = tokio::fs::read(path)
+                .await
+                .context(format!("Failed reading CA bundle: {path}"))?;
+            let certs = reqwest::Certificate::from_pem_bundle(&pem)
+                .context(format!("Invalid CA bundle: {path}"))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        match (&self.client_cert, &self.client_key) {
+            (Some(path), Some(key)) => {
+                let mut pem = tokio::fs::read(path)
+                    .await
+                    .context(format!("Failed reading client certificate: {path}"))?;
+                let key = key.resolve().await.context("Failed resolving client key")?;
+                pem.push(b'\n');
+                pem.extend_from_slice(key.as_bytes());
+
+                let identity = reqwest::Identity::from_pem(&pem)
+                    .context(format!("Invalid client certificate or key: {path}"))?;
+                // PEM identities are only supported by the rustls backend
+                builder = builder.use_rustls_tls().identity(identity);
+            }
+            (None, None) => {}
+            _ => anyhow::bail!("`client_cert` and `client_key` must be set together"),
+        }
+
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_network_config_or() {
+        let global: NetworkConfig = serde_json::from_value(json!({
+            "proxy": "http://proxy.internal:3128",
+            "no_proxy": ["localhost"],
+            "ca_cert": "/etc/ssl/corp.pem"
+        }))
+        .unwrap();
+        let server: NetworkConfig = serde_json::from_value(json!({
+            "no_proxy": [".internal"],
+            "client_cert": "client.pem",
+            "client_key": "${env:CLIENT_KEY}"
+        }))
+        .unwrap();
+
+        let merged = server.or(&global);
+        assert_eq!(
+            merged.proxy.as_ref().map(url::Url::as_str),
+            Some("http://proxy.internal:3128/")
+        );
+        assert_eq!(merged.no_proxy, vec![".internal".to_string()]);
+        assert_eq!(merged.ca_cert, Some("/etc/ssl/corp.pem".into()));
+        assert_eq!(merged.client_cert, Some("client.pem".into()));
+        assert!(merged.client_key.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_client_builder_errors() {
+        let missing_ca = NetworkConfig {
+            ca_cert: Some("/pctx/does/not/exist.pem".into()),
+            ..Default::default()
+        };
+        let err = missing_ca.client_builder().await.unwrap_err();
+        assert!(err.to_string().contains("CA bundle"));
+
+        let cert_without_key = NetworkConfig {
+            client_cert: Some("client.pem".into()),
+            ..Default::default()
+        };
+        assert!(cert_without_key.client_builder().await.is_err());
+
+        let proxied = NetworkConfig {
+            proxy: Some("http://proxy.internal:3128".parse().unwrap()),
+            no_proxy: vec!["localhost".into()],
+            ..Default::default()
+        };
+        assert!(proxied.client_builder().await.unwrap().build().is_ok());
+    }
+}
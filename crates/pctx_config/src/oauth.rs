@@ -0,0 +1,857 @@
+//! OAuth 2.1 support for upstream MCP servers
+//!
+//! Credentials obtained through the authorization code flow are stored in the
+//! system keychain (one entry per server) and refreshed automatically whenever
+//! a connection is made with an expired access token. Tokens obtained through
+//! the client credentials flow are only cached in memory.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use oauth2::{CsrfToken, PkceCodeChallenge, PkceCodeVerifier};
+use rmcp::transport::auth::{AuthorizationManager, AuthorizationMetadata};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+use url::Url;
+
+use crate::{
+    auth::{SecretString, remove_from_keychain, write_to_keychain},
+    network::NetworkConfig,
+};
+
+/// Access tokens expiring within this many seconds are refreshed before use
+const EXPIRY_MARGIN_SECS: u64 = 60;
+
+/// Seconds `pctx` waits for the user to complete the login in their browser
+const LOGIN_TIMEOUT_SECS: u64 = 300;
+
+/// Timeout of the requests made to authorization servers
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// OAuth credentials of an upstream MCP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthCredentials {
+    /// URL of the MCP server the credentials were issued for, they are not
+    /// sent to any other URL the server may be configured with later
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<Url>,
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    pub token_url: Url,
+    pub access_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// Unix timestamp (in seconds) the access token expires at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl OAuthCredentials {
+    /// Keychain entry the credentials of `server_name` are stored under
+    pub fn keychain_key(server_name: &str) -> String {
+        format!("{server_name}_oauth")
+    }
+
+    /// Loads the stored credentials of a server, `None` if the server has
+    /// not been logged into
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the keychain cannot be read or
+    /// the stored credentials are invalid
+    pub fn load(server_name: &str) -> Result<Option<Self>> {
+        let key = Self::keychain_key(server_name);
+        let entry = keyring::Entry::new("pctx", &key).context("Failed to create keychain entry")?;
+
+        match entry.get_password() {
+            Ok(stored) => Ok(Some(serde_json::from_str(&stored).context(format!(
+                "Invalid OAuth credentials stored in keychain (service: 'pctx', user: '{key}')"
+            ))?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e).context(format!(
+                "Failed to retrieve OAuth credentials from keychain (service: 'pctx', user: '{key}')"
+            )),
+        }
+    }
+
+    /// Stores the credentials of a server in the keychain
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the keychain cannot be written
+    pub fn store(&self, server_name: &str) -> Result<()> {
+        write_to_keychain(
+            &Self::keychain_key(server_name),
+            &serde_json::to_string(self)?,
+        )
+    }
+
+    /// Removes the stored credentials of a server from the keychain
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the keychain cannot be accessed
+    pub fn remove(server_name: &str) -> Result<()> {
+        remove_from_keychain(&Self::keychain_key(server_name))
+    }
+
+    /// Whether the credentials were issued for the MCP server at `url`,
+    /// credentials stored before the server's URL was recorded never are
+    pub fn is_for(&self, url: &Url) -> bool {
+        self.resource.as_ref() == Some(url)
+    }
+
+    /// Whether the access token has expired (or is about to)
+    pub fn is_expired(&self) -> bool {
+        is_expired(self.expires_at)
+    }
+
+    /// Exchanges the refresh token for a new access token, connecting to the
+    /// token endpoint with the `network` settings of the server
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no refresh token or
+    /// the token endpoint rejects it
+    pub async fn refresh(&mut self, network: &NetworkConfig) -> Result<()> {
+        let refresh_token = self
+            .refresh_token
+            .clone()
+            .context("No refresh token available, log in again")?;
+
+        let mut form = vec![
+            ("grant_type", "refresh_token".to_string()),
+            ("refresh_token", refresh_token),
+            ("client_id", self.client_id.clone()),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.clone()));
+        }
+
+        let res = request_token(&http_client(network).await?, &self.token_url, &form).await?;
+        self.access_token = res.access_token;
+        self.expires_at = res.expires_in.map(|s| unix_now() + s);
+        if res.refresh_token.is_some() {
+            // refresh tokens may be rotated
+            self.refresh_token = res.refresh_token;
+        }
+
+        Ok(())
+    }
+
+    /// Returns a valid access token for the server at `url`, refreshing (and
+    /// re-storing) the credentials first if the access token has expired
+    /// or `refresh` is set. `None` if the server has not been logged into,
+    /// or was logged into at another URL.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the credentials cannot be
+    /// loaded, refreshed or stored
+    pub async fn access_token(
+        server_name: &str,
+        url: &Url,
+        refresh: bool,
+        network: &NetworkConfig,
+    ) -> Result<Option<String>> {
+        let Some(mut creds) = Self::load(server_name)? else {
+            return Ok(None);
+        };
+        if !creds.is_for(url) {
+            warn!(
+                "Stored OAuth credentials of {server_name} were not issued for {url}, log in again with `pctx auth login {server_name}`"
+            );
+            return Ok(None);
+        }
+
+        if refresh || creds.is_expired() {
+            debug!("Refreshing OAuth access token of {server_name}");
+            creds.refresh(network).await?;
+            creds.store(server_name)?;
+        }
+
+        Ok(Some(creds.access_token))
+    }
+}
+
+/// Token endpoint response, shared by all grant types
+#[derive(Debug, Deserialize)]
+pub(crate) struct TokenResponse {
+    pub access_token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+}
+
+/// Client of the requests to an authorization server, connecting with the
+/// `network` settings of the MCP server it authorizes
+async fn http_client(network: &NetworkConfig) -> Result<reqwest::Client> {
+    network
+        .client_builder()
+        .await?
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .build()
+        .context("Failed creating OAuth HTTP client")
+}
+
+/// Sends a form encoded token request to an OAuth token endpoint
+pub(crate) async fn request_token(
+    client: &reqwest::Client,
+    token_url: &Url,
+    form: &[(&str, String)],
+) -> Result<TokenResponse> {
+    let res = client
+        .post(token_url.clone())
+        .form(form)
+        .send()
+        .await
+        .context(format!("Failed sending token request to {token_url}"))?;
+
+    let status = res.status();
+    if !status.is_success() {
+        let body = res.text().await.unwrap_or_default();
+        anyhow::bail!("Token request to {token_url} failed ({status}): {body}");
+    }
+
+    res.json()
+        .await
+        .context(format!("Invalid token response from {token_url}"))
+}
+
+/// Access token obtained with the client credentials flow
+struct CachedToken {
+    access_token: String,
+    expires_at: Option<u64>,
+}
+
+/// Client credentials tokens of this process, keyed by token URL, client & scope
+static CLIENT_CREDENTIALS_TOKENS: LazyLock<Mutex<HashMap<String, CachedToken>>> =
+    LazyLock::new(Mutex::default);
+
+/// Returns an access token obtained with the OAuth client credentials flow.
+///
+/// Tokens are cached in memory until just before they expire, `refresh`
+/// skips the cache (e.g. when the server rejected the cached token). The
+/// token endpoint is reached with the `network` settings of the server.
+///
+/// # Errors
+///
+/// This function will return an error if the client credentials cannot be
+/// resolved or the token endpoint rejects them
+///
+/// # Panics
+///
+/// Panics if the token cache lock is poisoned
+pub async fn client_credentials_token(
+    client_id: &SecretString,
+    client_secret: &SecretString,
+    token_url: &Url,
+    scope: Option<&str>,
+    refresh: bool,
+    network: &NetworkConfig,
+) -> Result<String> {
+    let client_id = client_id.resolve().await?;
+    let cache_key = format!("{token_url} {client_id} {}", scope.unwrap_or_default());
+
+    if !refresh {
+        let cache = CLIENT_CREDENTIALS_TOKENS.lock().unwrap();
+        if let Some(cached) = cache.get(&cache_key)
+            && !is_expired(cached.expires_at)
+        {
+            return Ok(cached.access_token.clone());
+        }
+    }
+
+    debug!("Requesting client credentials token from {token_url}");
+    let mut form = vec![
+        ("grant_type", "client_credentials".to_string()),
+        ("client_id", client_id),
+        ("client_secret", client_secret.resolve().await?),
+    ];
+    if let Some(scope) = scope {
+        form.push(("scope", scope.to_string()));
+    }
+    let res = request_token(&http_client(network).await?, token_url, &form).await?;
+
+    CLIENT_CREDENTIALS_TOKENS.lock().unwrap().insert(
+        cache_key,
+        CachedToken {
+            access_token: res.access_token.clone(),
+            expires_at: res.expires_in.map(|s| unix_now() + s),
+        },
+    );
+
+    Ok(res.access_token)
+}
+
+/// Checks whether the MCP server at `url` advertises an OAuth authorization server
+pub async fn supports_oauth(url: &Url, network: &NetworkConfig) -> bool {
+    match authorization_metadata(url, network).await {
+        Ok(_) => true,
+        Err(e) => {
+            debug!("No OAuth support detected for {url}: {e:#}");
+            false
+        }
+    }
+}
+
+/// Discovers the metadata of the authorization server of the MCP server at
+/// `url`, connecting with its `network` settings
+async fn authorization_metadata(
+    url: &Url,
+    network: &NetworkConfig,
+) -> Result<AuthorizationMetadata> {
+    let mut manager = AuthorizationManager::new(url.as_str()).await?;
+    manager.with_client(http_client(network).await?)?;
+    Ok(manager.discover_metadata().await?)
+}
+
+/// Dynamic client registration request (RFC 7591) of `pctx` as a public client
+#[derive(Serialize)]
+struct ClientRegistrationRequest<'a> {
+    client_name: &'a str,
+    redirect_uris: [&'a str; 1],
+    grant_types: [&'a str; 2],
+    response_types: [&'a str; 1],
+    token_endpoint_auth_method: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ClientRegistrationResponse {
+    client_id: String,
+    #[serde(default)]
+    client_secret: Option<String>,
+}
+
+/// An in-progress OAuth 2.1 authorization code login (with PKCE and dynamic
+/// client registration) against an MCP server.
+///
+/// The user completes the login by visiting [`Self::authorization_url`], after
+/// which the authorization server redirects to a listener on localhost. Every
+/// request to the authorization server is made with the MCP server's
+/// `network` settings.
+pub struct AuthorizationCodeLogin {
+    resource: Url,
+    listener: TcpListener,
+    client: reqwest::Client,
+    client_id: String,
+    client_secret: Option<String>,
+    token_url: Url,
+    redirect_uri: String,
+    pkce_verifier: PkceCodeVerifier,
+    csrf_token: CsrfToken,
+    authorization_url: String,
+}
+
+impl AuthorizationCodeLogin {
+    /// Starts a login: binds the local redirect listener (`port` 0 picks a
+    /// free port), discovers the authorization server and registers `pctx`
+    /// as a client.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the listener cannot be bound,
+    /// the server does not support OAuth or `pctx` cannot register as a client
+    pub async fn start(
+        url: &Url,
+        scopes: &[String],
+        port: u16,
+        network: &NetworkConfig,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .await
+            .context("Failed binding OAuth redirect listener")?;
+        let redirect_uri = format!(
+            "http://127.0.0.1:{}/callback",
+            listener.local_addr()?.port()
+        );
+
+        let client = http_client(network).await?;
+        let metadata = authorization_metadata(url, network).await.context(format!(
+            "Failed discovering the OAuth authorization server of {url}"
+        ))?;
+        let registration_url = metadata.registration_endpoint.as_deref().context(format!(
+            "The authorization server of {url} does not support dynamic client registration"
+        ))?;
+
+        let res = client
+            .post(registration_url)
+            .json(&ClientRegistrationRequest {
+                client_name: "pctx",
+                redirect_uris: [&redirect_uri],
+                grant_types: ["authorization_code", "refresh_token"],
+                response_types: ["code"],
+                token_endpoint_auth_method: "none",
+            })
+            .send()
+            .await
+            .context(format!("Failed registering with {registration_url}"))?;
+        let status = res.status();
+        if !status.is_success() {
+            let body = res.text().await.unwrap_or_default();
+            anyhow::bail!("Client registration with {registration_url} failed ({status}): {body}");
+        }
+        let registration: ClientRegistrationResponse = res.json().await.context(format!(
+            "Invalid client registration response from {registration_url}"
+        ))?;
+
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let csrf_token = CsrfToken::new_random();
+        let mut authorization_url: Url = metadata
+            .authorization_endpoint
+            .parse()
+            .context("Invalid authorization endpoint")?;
+        {
+            let mut query = authorization_url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", &registration.client_id)
+                .append_pair("redirect_uri", &redirect_uri)
+                .append_pair("code_challenge", pkce_challenge.as_str())
+                .append_pair("code_challenge_method", pkce_challenge.method().as_str())
+                .append_pair("state", csrf_token.secret());
+            if !scopes.is_empty() {
+                query.append_pair("scope", &scopes.join(" "));
+            }
+        }
+
+        Ok(Self {
+            resource: url.clone(),
+            listener,
+            client,
+            client_id: registration.client_id,
+            // some servers return an empty secret for public clients
+            client_secret: registration.client_secret.filter(|s| !s.is_empty()),
+            token_url: metadata
+                .token_endpoint
+                .parse()
+                .context("Invalid token endpoint")?,
+            redirect_uri,
+            pkce_verifier,
+            csrf_token,
+            authorization_url: authorization_url.to_string(),
+        })
+    }
+
+    /// URL the user needs to visit to authorize `pctx`
+    pub fn authorization_url(&self) -> &str {
+        &self.authorization_url
+    }
+
+    /// Waits for the authorization server to redirect back to the local
+    /// listener and exchanges the authorization code for tokens
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the user denies access, the
+    /// login times out or the code exchange fails
+    pub async fn finish(self) -> Result<OAuthCredentials> {
+        let (code, csrf) = tokio::time::timeout(
+            Duration::from_secs(LOGIN_TIMEOUT_SECS),
+            wait_for_callback(&self.listener),
+        )
+        .await
+        .context("Timed out waiting for OAuth login to complete")??;
+        if &csrf != self.csrf_token.secret() {
+            anyhow::bail!("OAuth login failed: the redirect's state does not match the login's");
+        }
+
+        let mut form = vec![
+            ("grant_type", "authorization_code".to_string()),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri),
+            ("client_id", self.client_id.clone()),
+            ("code_verifier", self.pkce_verifier.secret().clone()),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.clone()));
+        }
+        let token = request_token(&self.client, &self.token_url, &form)
+            .await
+            .context("Failed exchanging OAuth authorization code")?;
+
+        Ok(OAuthCredentials {
+            resource: Some(self.resource),
+            client_id: self.client_id,
+            client_secret: self.client_secret,
+            token_url: self.token_url,
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_at: token.expires_in.map(|s| unix_now() + s),
+        })
+    }
+}
+
+/// Accepts connections until the OAuth redirect arrives, returning its
+/// authorization code and state
+///
+/// Anything else reaching the listener (e.g. a browser asking for its
+/// favicon, or a malformed request) is answered and ignored.
+async fn wait_for_callback(listener: &TcpListener) -> Result<(String, String)> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut request_line = String::new();
+        if let Err(e) = BufReader::new(&mut stream)
+            .read_line(&mut request_line)
+            .await
+        {
+            debug!("Ignoring unreadable request to the OAuth redirect listener: {e}");
+            continue;
+        }
+
+        // e.g. "GET /callback?code=...&state=... HTTP/1.1"
+        let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+        let callback = match Url::parse(&format!("http://127.0.0.1{target}")) {
+            Ok(callback) => callback,
+            Err(e) => {
+                debug!("Ignoring malformed request to the OAuth redirect listener: {e}");
+                let _ = stream
+                    .write_all(b"HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\n\r\n")
+                    .await;
+                continue;
+            }
+        };
+        if callback.path() != "/callback" {
+            let _ = stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n")
+                .await;
+            continue;
+        }
+
+        let param = |name: &str| {
+            callback
+                .query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.to_string())
+        };
+        let (message, result) = match (param("code"), param("state"), param("error")) {
+            (Some(code), Some(state), None) => (
+                "Login successful, you can close this window and return to pctx.",
+                Ok((code, state)),
+            ),
+            (_, _, error) => {
+                let reason = param("error_description")
+                    .or(error)
+                    .unwrap_or_else(|| "missing authorization code".into());
+                (
+                    "Login failed, return to pctx for details.",
+                    Err(anyhow::anyhow!("OAuth authorization failed: {reason}")),
+                )
+            }
+        };
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=utf-8\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{message}",
+            message.len()
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+
+        return result;
+    }
+}
+
+/// Whether a token expiring at `expires_at` has expired (or is about to)
+fn is_expired(expires_at: Option<u64>) -> bool {
+    expires_at.is_some_and(|at| unix_now() + EXPIRY_MARGIN_SECS >= at)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use axum::{
+        Form, Json, Router,
+        extract::{Query, State},
+        http::StatusCode,
+        response::{IntoResponse, Redirect, Response},
+        routing::{get, post},
+    };
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::auth::AuthConfig;
+
+    /// Starts a stand-in OAuth authorization server, returning its base URL
+    async fn spawn_auth_server() -> Url {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let base: Url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+
+        let app = Router::new()
+            .route("/.well-known/oauth-authorization-server", get(metadata))
+            .route("/register", post(register))
+            .route("/authorize", get(authorize))
+            .route("/token", post(token))
+            .with_state(base.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        base
+    }
+
+    async fn metadata(State(base): State<Url>) -> Json<Value> {
+        Json(json!({
+            "issuer": base,
+            "authorization_endpoint": base.join("/authorize").unwrap(),
+            "token_endpoint": base.join("/token").unwrap(),
+            "registration_endpoint": base.join("/register").unwrap(),
+        }))
+    }
+
+    async fn register(Json(req): Json<Value>) -> Json<Value> {
+        Json(json!({
+            "client_id": "pctx-test-client",
+            "redirect_uris": req["redirect_uris"],
+        }))
+    }
+
+    async fn authorize(Query(params): Query<HashMap<String, String>>) -> Response {
+        if params.get("code_challenge_method").map(String::as_str) != Some("S256") {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+        Redirect::to(&format!(
+            "{}?code=test-code&state={}",
+            params["redirect_uri"], params["state"]
+        ))
+        .into_response()
+    }
+
+    /// Number of client credentials tokens issued by the stand-in server
+    static ISSUED_M2M_TOKENS: AtomicUsize = AtomicUsize::new(0);
+
+    async fn token(Form(params): Form<HashMap<String, String>>) -> Response {
+        let get = |k: &str| params.get(k).map(String::as_str);
+        if get("grant_type") == Some("client_credentials") {
+            if get("client_id") != Some("m2m") || get("client_secret") != Some("secret") {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+            let issued = ISSUED_M2M_TOKENS.fetch_add(1, Ordering::SeqCst) + 1;
+            return Json(json!({
+                "access_token": format!("m2m-{issued}-{}", get("scope").unwrap_or_default()),
+                "token_type": "Bearer",
+                "expires_in": 3600,
+            }))
+            .into_response();
+        }
+
+        let (access, refresh) = match get("grant_type") {
+            Some("authorization_code")
+                if get("code") == Some("test-code") && get("code_verifier").is_some() =>
+            {
+                ("access-1", "refresh-1")
+            }
+            Some("refresh_token") if get("refresh_token") == Some("refresh-1") => {
+                ("access-2", "refresh-2")
+            }
+            _ => return StatusCode::BAD_REQUEST.into_response(),
+        };
+
+        Json(json!({
+            "access_token": access,
+            "token_type": "Bearer",
+            "expires_in": 3600,
+            "refresh_token": refresh,
+        }))
+        .into_response()
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code_login_and_refresh() {
+        let mcp_url = spawn_auth_server().await.join("/mcp").unwrap();
+        let network = NetworkConfig::default();
+        assert!(supports_oauth(&mcp_url, &network).await);
+
+        let login = AuthorizationCodeLogin::start(&mcp_url, &["read".into()], 0, &network)
+            .await
+            .unwrap();
+        assert!(
+            login
+                .authorization_url()
+                .contains("client_id=pctx-test-client")
+        );
+
+        // stand in for the user's browser, the authorize endpoint redirects
+        // straight back to the local listener
+        let browser = tokio::spawn(reqwest::get(login.authorization_url().to_string()));
+        let mut creds = login.finish().await.unwrap();
+        assert!(browser.await.unwrap().unwrap().status().is_success());
+
+        assert!(creds.is_for(&mcp_url));
+        assert!(!creds.is_for(&"https://other.example.com/mcp".parse().unwrap()));
+        assert_eq!(creds.client_id, "pctx-test-client");
+        assert_eq!(creds.token_url.path(), "/token");
+        assert_eq!(creds.access_token, "access-1");
+        assert_eq!(creds.refresh_token.as_deref(), Some("refresh-1"));
+        assert!(!creds.is_expired());
+
+        creds.refresh(&network).await.unwrap();
+        assert_eq!(creds.access_token, "access-2");
+        assert_eq!(creds.refresh_token.as_deref(), Some("refresh-2"));
+
+        // the rotated refresh token is not the one the server expects anymore
+        creds.refresh_token = Some("revoked".into());
+        assert!(creds.refresh(&network).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials_token_cache() {
+        let token_url = spawn_auth_server().await.join("/token").unwrap();
+        let client_id = SecretString::new_plain("m2m");
+        let client_secret = SecretString::new_plain("secret");
+        let network = NetworkConfig::default();
+
+        let first = client_credentials_token(
+            &client_id,
+            &client_secret,
+            &token_url,
+            Some("read"),
+            false,
+            &network,
+        )
+        .await
+        .unwrap();
+        assert!(first.starts_with("m2m-") && first.ends_with("-read"));
+
+        let cached = client_credentials_token(
+            &client_id,
+            &client_secret,
+            &token_url,
+            Some("read"),
+            false,
+            &network,
+        )
+        .await
+        .unwrap();
+        assert_eq!(cached, first, "token should be served from the cache");
+
+        let refreshed = client_credentials_token(
+            &client_id,
+            &client_secret,
+            &token_url,
+            Some("read"),
+            true,
+            &network,
+        )
+        .await
+        .unwrap();
+        assert_ne!(refreshed, first, "refresh should request a new token");
+
+        let wrong_secret = SecretString::new_plain("wrong");
+        let res = client_credentials_token(
+            &client_id,
+            &wrong_secret,
+            &token_url,
+            Some("read"),
+            true,
+            &network,
+        )
+        .await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_supports_oauth_without_metadata() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let url: Url = format!("http://{}/mcp", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        drop(listener);
+
+        assert!(!supports_oauth(&url, &NetworkConfig::default()).await);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_callback_ignores_malformed_requests() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let browser = tokio::spawn(async move {
+            for request in [
+                "GET :99999/callback HTTP/1.1\r\n\r\n",
+                "GET /favicon.ico HTTP/1.1\r\n\r\n",
+                "GET /callback?code=test-code&state=test-state HTTP/1.1\r\n\r\n",
+            ] {
+                let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+                stream.write_all(request.as_bytes()).await.unwrap();
+                let mut response = String::new();
+                let _ = tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response).await;
+            }
+        });
+
+        let (code, state) = wait_for_callback(&listener).await.unwrap();
+        assert_eq!(code, "test-code");
+        assert_eq!(state, "test-state");
+        browser.await.unwrap();
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let mut creds = OAuthCredentials {
+            resource: None,
+            client_id: "client".into(),
+            client_secret: None,
+            token_url: "https://auth.example.com/token".parse().unwrap(),
+            access_token: "access".into(),
+            refresh_token: None,
+            expires_at: None,
+        };
+        assert!(!creds.is_expired(), "tokens without expiry never expire");
+
+        creds.expires_at = Some(unix_now() + 30);
+        assert!(creds.is_expired(), "tokens about to expire are refreshed");
+
+        creds.expires_at = Some(unix_now() + 3600);
+        assert!(!creds.is_expired());
+    }
+
+    #[test]
+    fn test_oauth_auth_config() {
+        let auth: AuthConfig = serde_json::from_value(json!({ "type": "oauth" })).unwrap();
+        assert!(matches!(auth, AuthConfig::OAuth { ref scopes } if scopes.is_empty()));
+        assert_eq!(
+            serde_json::to_value(&auth).unwrap(),
+            json!({ "type": "oauth" })
+        );
+
+        let auth: AuthConfig =
+            serde_json::from_value(json!({ "type": "oauth", "scopes": ["read"] })).unwrap();
+        assert!(matches!(auth, AuthConfig::OAuth { ref scopes } if scopes == &["read"]));
+
+        let auth: AuthConfig = serde_json::from_value(json!({
+            "type": "oauth_client_credentials",
+            "client_id": "${env:CLIENT_ID}",
+            "client_secret": "${keychain:client_secret}",
+            "token_url": "https://auth.example.com/token",
+        }))
+        .unwrap();
+        let AuthConfig::OAuthClientCredentials {
+            client_id, scope, ..
+        } = &auth
+        else {
+            panic!("expected client credentials auth, got {auth:?}");
+        };
+        assert!(client_id.has_secrets());
+        assert!(scope.is_none());
+        assert!(serde_json::to_value(&auth).unwrap().get("scope").is_none());
+    }
+}
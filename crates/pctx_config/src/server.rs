@@ -1,37 +1,335 @@
+use camino::Utf8PathBuf;
+use codegen::case::Case;
 use http::{HeaderMap, HeaderName, HeaderValue};
+use indexmap::IndexMap;
+use log::debug;
 use rmcp::{
-    RoleClient, ServiceExt,
+    ClientHandler, RoleClient, ServiceExt,
     model::{
         ClientCapabilities, ClientInfo, Implementation, InitializeRequestParam, ProtocolVersion,
     },
     service::{ClientInitializeError, RunningService},
     transport::{
-        StreamableHttpClientTransport,
+        IntoTransport, SseClientTransport, StreamableHttpClientTransport, TokioChildProcess,
+        sse_client::{SseClientConfig, SseTransportError},
         streamable_http_client::{StreamableHttpClientTransportConfig, StreamableHttpError},
     },
 };
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::{fmt::Display, process::Stdio, str::FromStr, time::Duration};
+use tokio::io::{AsyncBufReadExt, BufReader};
 
-use super::auth::AuthConfig;
+use super::auth::{AuthConfig, SecretString};
+use super::network::NetworkConfig;
+use super::oauth::{OAuthCredentials, client_credentials_token, supports_oauth};
+
+/// Seconds `pctx start` waits for a server's tools when no `connect_timeout` is set
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 30;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub name: String,
+    /// TypeScript namespace of the server's functions, derived from `name` if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(flatten)]
+    pub connection: ServerConnection,
+    /// Seconds to wait for the server to connect and list its tools on
+    /// startup, defaults to [`DEFAULT_CONNECT_TIMEOUT_SECS`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    /// Number of times a failed startup connection is retried, defaults to 0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Seconds between re-listing the server's tools, for servers that never
+    /// send `notifications/tools/list_changed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_interval: Option<u64>,
+    /// Glob patterns of the tools to expose, every tool is exposed if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_tools: Vec<ToolPattern>,
+    /// Glob patterns of tools to hide, applied after `include_tools`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_tools: Vec<ToolPattern>,
+    /// Proxy and TLS settings, overriding the global `network` settings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkConfig>,
+    /// Overrides of how tools are presented to agents, keyed by upstream tool name
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tools: IndexMap<String, ToolOverride>,
+    /// Also expose the server's tools as plain MCP tools (`<namespace>__<tool>`),
+    /// overriding the global `passthrough` setting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passthrough: Option<bool>,
+}
+
+/// Changes to the generated function of an upstream tool, the upstream tool
+/// name is still used when calling it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolOverride {
+    /// Function name, must be a valid TypeScript identifier
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Replaces the description reported by the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Extra notes appended to the function's docstring
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Usage examples added to the function's docstring
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<String>,
+}
+
+/// Glob pattern matched against upstream tool names (e.g. `delete_*`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolPattern(glob::Pattern);
+
+impl ToolPattern {
+    pub fn matches(&self, tool: &str) -> bool {
+        self.0.matches(tool)
+    }
+}
+
+impl FromStr for ToolPattern {
+    type Err = glob::PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        glob::Pattern::new(s).map(Self)
+    }
+}
+
+impl Display for ToolPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for ToolPattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolPattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        pattern
+            .parse()
+            .map_err(|e| serde::de::Error::custom(format!("invalid tool pattern '{pattern}': {e}")))
+    }
+}
+
+/// How pctx reaches an upstream MCP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    untagged,
+    expecting = "server must define either a `url` (HTTP) or a `command` (stdio)"
+)]
+pub enum ServerConnection {
+    /// Remote server reached over HTTP
+    Http(HttpConnection),
+    /// Local server spawned as a child process, speaking MCP over stdin/stdout
+    Stdio(StdioConnection),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpConnection {
     pub url: url::Url,
+    /// HTTP transport spoken by the server, defaults to streamable HTTP
+    #[serde(default)]
+    pub transport: HttpTransport,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auth: Option<AuthConfig>,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HttpTransport {
+    /// Streamable HTTP transport (MCP 2025-03-26 and later)
+    #[default]
+    StreamableHttp,
+    /// Legacy HTTP+SSE transport (MCP 2024-11-05)
+    Sse,
+}
+
+impl Display for HttpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpTransport::StreamableHttp => write!(f, "streamable-http"),
+            HttpTransport::Sse => write!(f, "sse"),
+        }
+    }
+}
+
+impl FromStr for HttpTransport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "streamable-http" => Ok(HttpTransport::StreamableHttp),
+            "sse" => Ok(HttpTransport::Sse),
+            _ => anyhow::bail!("Unknown transport '{s}', expected `streamable-http` or `sse`"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StdioConnection {
+    /// Executable to spawn (e.g. `npx`, `uvx`)
+    pub command: String,
+    /// Arguments passed to the command
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Extra environment variables for the child process
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, SecretString>,
+    /// Working directory of the child process, defaults to the current directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<Utf8PathBuf>,
+}
+
+impl Display for ServerConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerConnection::Http(http) => write!(f, "{}", http.url),
+            ServerConnection::Stdio(stdio) => {
+                write!(f, "{}", stdio.command)?;
+                for arg in &stdio.args {
+                    write!(f, " {arg}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 impl ServerConfig {
     pub fn new(name: String, url: url::Url) -> Self {
         Self {
             name,
-            url,
-            auth: None,
+            namespace: None,
+            connection: ServerConnection::Http(HttpConnection {
+                url,
+                transport: HttpTransport::default(),
+                auth: None,
+            }),
+            connect_timeout: None,
+            retries: None,
+            refresh_interval: None,
+            include_tools: vec![],
+            exclude_tools: vec![],
+            network: None,
+            tools: IndexMap::new(),
+            passthrough: None,
         }
     }
 
+    pub fn new_stdio(name: String, command: String, args: Vec<String>) -> Self {
+        Self {
+            name,
+            namespace: None,
+            connection: ServerConnection::Stdio(StdioConnection {
+                command,
+                args,
+                env: IndexMap::new(),
+                cwd: None,
+            }),
+            connect_timeout: None,
+            retries: None,
+            refresh_interval: None,
+            include_tools: vec![],
+            exclude_tools: vec![],
+            network: None,
+            tools: IndexMap::new(),
+            passthrough: None,
+        }
+    }
+
+    /// TypeScript namespace of the server's functions, the `PascalCase` name
+    /// unless explicitly set
+    pub fn namespace(&self) -> String {
+        self.namespace
+            .clone()
+            .unwrap_or_else(|| Case::Pascal.sanitize(&self.name))
+    }
+
+    /// URL of the server, `None` for stdio servers
+    pub fn url(&self) -> Option<&url::Url> {
+        match &self.connection {
+            ServerConnection::Http(http) => Some(&http.url),
+            ServerConnection::Stdio(_) => None,
+        }
+    }
+
+    /// HTTP transport of the server, `None` for stdio servers
+    pub fn transport(&self) -> Option<HttpTransport> {
+        match &self.connection {
+            ServerConnection::Http(http) => Some(http.transport),
+            ServerConnection::Stdio(_) => None,
+        }
+    }
+
+    /// Authentication of the server, always `None` for stdio servers
+    pub fn auth(&self) -> Option<&AuthConfig> {
+        match &self.connection {
+            ServerConnection::Http(http) => http.auth.as_ref(),
+            ServerConnection::Stdio(_) => None,
+        }
+    }
+
+    /// Request header the credential of a server with `forward` auth is read
+    /// from, `None` for other servers
+    pub fn forwarded_header(&self) -> Option<String> {
+        match self.auth()? {
+            AuthConfig::Forward { header, .. } => Some(
+                header
+                    .clone()
+                    .unwrap_or_else(|| format!("X-Upstream-Token-{}", self.name)),
+            ),
+            _ => None,
+        }
+    }
+
+    /// `self` sending `credential`, forwarded from the calling client, instead
+    /// of the `fallback` of its `forward` auth
+    #[must_use]
+    pub fn with_forwarded_credential(&self, credential: &str) -> Self {
+        let mut cfg = self.clone();
+        if let ServerConnection::Http(http) = &mut cfg.connection
+            && let Some(AuthConfig::Forward { fallback, .. }) = &mut http.auth
+        {
+            *fallback = Some(SecretString::new_plain(credential));
+        }
+        cfg
+    }
+
+    /// Time allowed for connecting to the server and listing its tools on startup
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS))
+    }
+
+    /// Interval of the periodic tool list refresh, `None` if the server's
+    /// tools are only refreshed when it notifies about changes
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_interval.map(Duration::from_secs)
+    }
+
+    /// Whether the server's tools are also exposed as plain MCP tools
+    pub fn passthrough(&self) -> bool {
+        self.passthrough.unwrap_or_default()
+    }
+
+    /// Whether the tool `name` passes the server's `include_tools` and
+    /// `exclude_tools` filters
+    pub fn allows_tool(&self, name: &str) -> bool {
+        let included =
+            self.include_tools.is_empty() || self.include_tools.iter().any(|p| p.matches(name));
+        included && !self.exclude_tools.iter().any(|p| p.matches(name))
+    }
+
     /// Connects to the MCP server as specified in the `ServerConfig`
     ///
     /// # Errors
@@ -41,10 +339,78 @@ impl ServerConfig {
     pub async fn connect(
         &self,
     ) -> Result<RunningService<RoleClient, InitializeRequestParam>, McpConnectionError> {
+        self.connect_with(client_info()).await
+    }
+
+    /// Connects to the MCP server like [`ServerConfig::connect`], with `handler`
+    /// receiving the server's notifications and requests.
+    ///
+    /// The handler should identify itself with [`client_info`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if unable to connect and send the
+    /// initialization request
+    pub async fn connect_with<H: ClientHandler + Clone>(
+        &self,
+        handler: H,
+    ) -> Result<RunningService<RoleClient, H>, McpConnectionError> {
+        match &self.connection {
+            ServerConnection::Http(http) => self.connect_http(http, handler).await,
+            ServerConnection::Stdio(stdio) => self.connect_stdio(stdio, handler).await,
+        }
+    }
+
+    async fn connect_http<H: ClientHandler + Clone>(
+        &self,
+        http: &HttpConnection,
+        handler: H,
+    ) -> Result<RunningService<RoleClient, H>, McpConnectionError> {
+        let res = self
+            .connect_http_with_headers(http, self.auth_headers(http, false).await?, handler.clone())
+            .await;
+
+        match (res, &http.auth) {
+            (
+                Err(McpConnectionError::RequiresAuth),
+                Some(auth @ (AuthConfig::OAuth { .. } | AuthConfig::OAuthClientCredentials { .. })),
+            ) => {
+                // the access token may have been revoked before its expiry,
+                // retry once with a new token
+                debug!("OAuth access token of {} rejected, refreshing", self.name);
+                let retried = match self.auth_headers(http, true).await {
+                    Ok(headers) => self.connect_http_with_headers(http, headers, handler).await,
+                    Err(e) => Err(e),
+                };
+                match (retried, auth) {
+                    (
+                        Err(McpConnectionError::RequiresAuth | McpConnectionError::Failed(_)),
+                        AuthConfig::OAuth { .. },
+                    ) => Err(McpConnectionError::RequiresOAuth),
+                    (res, _) => res,
+                }
+            }
+            (Err(McpConnectionError::RequiresAuth), None)
+                if supports_oauth(&http.url, &self.network.clone().unwrap_or_default()).await =>
+            {
+                Err(McpConnectionError::RequiresOAuth)
+            }
+            (res, _) => res,
+        }
+    }
+
+    /// Resolves the headers authenticating requests to an HTTP server,
+    /// `refresh` forces OAuth access tokens to be renewed
+    async fn auth_headers(
+        &self,
+        http: &HttpConnection,
+        refresh: bool,
+    ) -> Result<HeaderMap, McpConnectionError> {
         let mut default_headers = HeaderMap::new();
+        let network = self.network.clone().unwrap_or_default();
 
         // Add auth to http client
-        if let Some(a) = &self.auth {
+        if let Some(a) = &http.auth {
             match a {
                 AuthConfig::Bearer { token } => {
                     let resolved = token
@@ -71,46 +437,267 @@ impl ServerConfig {
                         );
                     }
                 }
+                AuthConfig::OAuth { .. } => {
+                    let token =
+                        OAuthCredentials::access_token(&self.name, &http.url, refresh, &network)
+                            .await
+                            .map_err(|e| McpConnectionError::Failed(e.to_string()))?
+                            .ok_or(McpConnectionError::RequiresOAuth)?;
+                    default_headers.append(
+                        http::header::AUTHORIZATION,
+                        HeaderValue::from_str(&format!("Bearer {token}"))
+                            .map_err(|e| McpConnectionError::Failed(e.to_string()))?,
+                    );
+                }
+                AuthConfig::OAuthClientCredentials {
+                    client_id,
+                    client_secret,
+                    token_url,
+                    scope,
+                } => {
+                    let token = client_credentials_token(
+                        client_id,
+                        client_secret,
+                        token_url,
+                        scope.as_deref(),
+                        refresh,
+                        &network,
+                    )
+                    .await
+                    .map_err(|e| McpConnectionError::Failed(e.to_string()))?;
+                    default_headers.append(
+                        http::header::AUTHORIZATION,
+                        HeaderValue::from_str(&format!("Bearer {token}"))
+                            .map_err(|e| McpConnectionError::Failed(e.to_string()))?,
+                    );
+                }
+                AuthConfig::Forward {
+                    upstream_header,
+                    fallback,
+                    ..
+                } => {
+                    let Some(credential) = fallback else {
+                        return Err(McpConnectionError::Failed(format!(
+                            "No credential was forwarded in the `{}` header",
+                            self.forwarded_header().unwrap_or_default()
+                        )));
+                    };
+                    let resolved = credential
+                        .resolve()
+                        .await
+                        .map_err(|e| McpConnectionError::Failed(e.to_string()))?;
+                    let name = upstream_header.as_deref().unwrap_or("Authorization");
+                    let mut value = HeaderValue::from_str(&resolved)
+                        .map_err(|e| McpConnectionError::Failed(e.to_string()))?;
+                    value.set_sensitive(true);
+                    default_headers.append(
+                        HeaderName::from_str(name)
+                            .map_err(|e| McpConnectionError::Failed(e.to_string()))?,
+                        value,
+                    );
+                }
             }
         }
 
-        let reqwest_client = reqwest::Client::builder()
+        Ok(default_headers)
+    }
+
+    async fn connect_http_with_headers<H: ClientHandler>(
+        &self,
+        http: &HttpConnection,
+        default_headers: HeaderMap,
+        handler: H,
+    ) -> Result<RunningService<RoleClient, H>, McpConnectionError> {
+        let builder = match &self.network {
+            Some(network) => network
+                .client_builder()
+                .await
+                .map_err(|e| McpConnectionError::Failed(format!("{e:#}")))?,
+            None => reqwest::Client::builder(),
+        };
+        let reqwest_client = builder
             .default_headers(default_headers)
             .build()
             .map_err(|e| McpConnectionError::Failed(e.to_string()))?;
 
-        let transport = StreamableHttpClientTransport::with_client(
-            reqwest_client,
-            StreamableHttpClientTransportConfig {
-                uri: self.url.as_str().into(),
-                ..Default::default()
-            },
-        );
-        let init_request = ClientInfo {
-            protocol_version: ProtocolVersion::default(),
-            capabilities: ClientCapabilities::default(),
-            client_info: Implementation {
-                name: "pctx-client".to_string(),
-                version: option_env!("CARGO_PKG_VERSION")
-                    .unwrap_or("0.1.0")
-                    .to_string(),
-                ..Default::default()
-            },
+        match http.transport {
+            HttpTransport::StreamableHttp => {
+                let transport = StreamableHttpClientTransport::with_client(
+                    reqwest_client,
+                    StreamableHttpClientTransportConfig {
+                        uri: http.url.as_str().into(),
+                        ..Default::default()
+                    },
+                );
+                initialize(handler, transport).await
+            }
+            HttpTransport::Sse => {
+                // the SSE transport opens its event stream before the initialize request
+                let transport = SseClientTransport::start_with_client(
+                    reqwest_client,
+                    SseClientConfig {
+                        sse_endpoint: http.url.as_str().into(),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .map_err(|e| match e {
+                    SseTransportError::Client(ref r_err)
+                        if r_err.status() == Some(reqwest::StatusCode::UNAUTHORIZED) =>
+                    {
+                        McpConnectionError::RequiresAuth
+                    }
+                    e => McpConnectionError::Failed(e.to_string()),
+                })?;
+                initialize(handler, transport).await
+            }
+        }
+    }
+
+    /// Connects to the MCP server, detecting the HTTP transport it speaks.
+    ///
+    /// Streamable HTTP is tried first, falling back on the legacy HTTP+SSE
+    /// transport. The detected transport is stored in the `ServerConfig`.
+    /// Stdio servers connect as usual.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if unable to connect with any transport
+    pub async fn connect_detect_transport(
+        &mut self,
+    ) -> Result<RunningService<RoleClient, InitializeRequestParam>, McpConnectionError> {
+        let ServerConnection::Http(http) = &mut self.connection else {
+            return self.connect().await;
+        };
+
+        http.transport = HttpTransport::StreamableHttp;
+        let streamable_err = match self.connect().await {
+            Ok(client) => return Ok(client),
+            // server answered with an auth challenge, so the transport is right
+            Err(e @ (McpConnectionError::RequiresAuth | McpConnectionError::RequiresOAuth)) => {
+                return Err(e);
+            }
+            Err(McpConnectionError::Failed(msg)) => msg,
         };
-        match init_request.serve(transport).await {
-            Ok(c) => Ok(c),
-            Err(ClientInitializeError::TransportError { error, .. }) => {
-                if let Some(s_err) = error
-                    .error
-                    .downcast_ref::<StreamableHttpError<reqwest::Error>>()
-                    && let StreamableHttpError::AuthRequired(_) = s_err
-                {
-                    return Err(McpConnectionError::RequiresAuth);
+
+        debug!(
+            "Streamable HTTP connection to '{}' failed, trying SSE transport: {streamable_err}",
+            self.name
+        );
+        self.set_transport(HttpTransport::Sse);
+        match self.connect().await {
+            Ok(client) => Ok(client),
+            Err(e @ (McpConnectionError::RequiresAuth | McpConnectionError::RequiresOAuth)) => {
+                Err(e)
+            }
+            Err(McpConnectionError::Failed(sse_err)) => {
+                self.set_transport(HttpTransport::StreamableHttp);
+                debug!("SSE connection to '{}' failed: {sse_err}", self.name);
+                Err(McpConnectionError::Failed(streamable_err))
+            }
+        }
+    }
+
+    fn set_transport(&mut self, transport: HttpTransport) {
+        if let ServerConnection::Http(http) = &mut self.connection {
+            http.transport = transport;
+        }
+    }
+
+    async fn connect_stdio<H: ClientHandler>(
+        &self,
+        stdio: &StdioConnection,
+        handler: H,
+    ) -> Result<RunningService<RoleClient, H>, McpConnectionError> {
+        let mut cmd = tokio::process::Command::new(&stdio.command);
+        cmd.args(&stdio.args);
+        for (key, val) in &stdio.env {
+            let resolved = val
+                .resolve()
+                .await
+                .map_err(|e| McpConnectionError::Failed(e.to_string()))?;
+            cmd.env(key, resolved);
+        }
+        if let Some(cwd) = &stdio.cwd {
+            cmd.current_dir(cwd);
+        }
+
+        let (transport, stderr) = TokioChildProcess::builder(cmd)
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                McpConnectionError::Failed(format!("Failed to spawn `{}`: {e}", &self.connection))
+            })?;
+
+        // forward the child's stderr into our logs so it doesn't corrupt the terminal,
+        // under a target of its own so it can be filtered per server (e.g. with `RUST_LOG`)
+        if let Some(stderr) = stderr {
+            let name = self.name.clone();
+            tokio::spawn(async move {
+                let target = format!("{STDERR_TARGET}::{name}");
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    log::log!(target: &target, stderr_level(&line), "[{name}] {line}");
                 }
-                Err(McpConnectionError::Failed(error.error.to_string()))
+            });
+        }
+
+        initialize(handler, transport).await
+    }
+}
+
+/// Log target of the stderr of stdio servers, followed by `::<server name>`
+const STDERR_TARGET: &str = "pctx::upstream";
+
+/// Level a line the child process of a stdio server wrote to stderr is logged at,
+/// lines reporting errors or warnings are logged as warnings
+fn stderr_level(line: &str) -> log::Level {
+    let line = line.to_lowercase();
+    if line.contains("error") || line.contains("warn") || line.contains("panic") {
+        log::Level::Warn
+    } else {
+        log::Level::Info
+    }
+}
+
+/// Client info pctx identifies itself with to upstream MCP servers
+pub fn client_info() -> ClientInfo {
+    ClientInfo {
+        protocol_version: ProtocolVersion::default(),
+        capabilities: ClientCapabilities::default(),
+        client_info: Implementation {
+            name: "pctx-client".to_string(),
+            version: option_env!("CARGO_PKG_VERSION")
+                .unwrap_or("0.1.0")
+                .to_string(),
+            ..Default::default()
+        },
+    }
+}
+
+/// Sends the MCP initialize request over the provided transport
+async fn initialize<H, T, E, A>(
+    handler: H,
+    transport: T,
+) -> Result<RunningService<RoleClient, H>, McpConnectionError>
+where
+    H: ClientHandler,
+    T: IntoTransport<RoleClient, E, A>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    match handler.serve(transport).await {
+        Ok(c) => Ok(c),
+        Err(ClientInitializeError::TransportError { error, .. }) => {
+            if let Some(s_err) = error
+                .error
+                .downcast_ref::<StreamableHttpError<reqwest::Error>>()
+                && let StreamableHttpError::AuthRequired(_) = s_err
+            {
+                return Err(McpConnectionError::RequiresAuth);
             }
-            Err(e) => Err(McpConnectionError::Failed(format!("{e}"))),
+            Err(McpConnectionError::Failed(error.error.to_string()))
         }
+        Err(e) => Err(McpConnectionError::Failed(format!("{e}"))),
     }
 }
 
@@ -120,7 +707,235 @@ pub enum McpConnectionError {
     /// Server requires authentication
     #[error("Server requires authentication")]
     RequiresAuth,
+    /// Server requires an OAuth login (`pctx auth login`)
+    #[error("Server requires OAuth login")]
+    RequiresOAuth,
     /// Connection failed (network error, invalid URL, etc.)
     #[error("Failed to connect: {0}")]
     Failed(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_deserialize_http_server() {
+        let cfg: ServerConfig = serde_json::from_value(json!({
+            "name": "remote",
+            "url": "https://mcp.example.com/mcp",
+            "auth": { "type": "bearer", "token": "${env:TOKEN}" }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            cfg.url().map(url::Url::as_str),
+            Some("https://mcp.example.com/mcp")
+        );
+        assert!(matches!(cfg.auth(), Some(AuthConfig::Bearer { .. })));
+        assert_eq!(cfg.transport(), Some(HttpTransport::StreamableHttp));
+    }
+
+    #[test]
+    fn test_deserialize_connect_options() {
+        let cfg: ServerConfig = serde_json::from_value(json!({
+            "name": "slow",
+            "command": "npx",
+            "connect_timeout": 5,
+            "retries": 2,
+            "refresh_interval": 90
+        }))
+        .unwrap();
+
+        assert_eq!(cfg.connect_timeout(), Duration::from_secs(5));
+        assert_eq!(cfg.retries, Some(2));
+        assert_eq!(cfg.refresh_interval(), Some(Duration::from_secs(90)));
+        assert!(matches!(cfg.connection, ServerConnection::Stdio(_)));
+
+        let defaults =
+            ServerConfig::new("remote".into(), "https://mcp.example.com".parse().unwrap());
+        assert_eq!(
+            defaults.connect_timeout(),
+            Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS)
+        );
+        assert_eq!(defaults.refresh_interval(), None);
+        assert!(
+            serde_json::to_value(&defaults)
+                .unwrap()
+                .get("retries")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_tool_filters() {
+        let cfg: ServerConfig = serde_json::from_value(json!({
+            "name": "github",
+            "url": "https://mcp.example.com/mcp",
+            "include_tools": ["get_*", "list_*", "delete_branch"],
+            "exclude_tools": ["*_secret", "delete_*"]
+        }))
+        .unwrap();
+
+        assert!(cfg.allows_tool("get_issue"));
+        assert!(cfg.allows_tool("list_repos"));
+        assert!(!cfg.allows_tool("create_issue"));
+        assert!(!cfg.allows_tool("get_secret"));
+        assert!(!cfg.allows_tool("delete_branch"));
+        assert_eq!(
+            serde_json::to_value(&cfg).unwrap()["exclude_tools"],
+            json!(["*_secret", "delete_*"])
+        );
+
+        let unfiltered = ServerConfig::new_stdio("local".into(), "npx".into(), vec![]);
+        assert!(unfiltered.allows_tool("delete_everything"));
+
+        let invalid = serde_json::from_value::<ServerConfig>(json!({
+            "name": "github",
+            "url": "https://mcp.example.com/mcp",
+            "include_tools": ["get_[*"]
+        }));
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_deserialize_tool_overrides() {
+        let cfg: ServerConfig = serde_json::from_value(json!({
+            "name": "github",
+            "url": "https://mcp.example.com/mcp",
+            "tools": {
+                "search_issues_v2": {
+                    "name": "searchIssues",
+                    "description": "Search issues across repositories",
+                    "examples": ["await Github.searchIssues({ query: \"is:open\" })"]
+                }
+            }
+        }))
+        .unwrap();
+
+        let o = &cfg.tools["search_issues_v2"];
+        assert_eq!(o.name.as_deref(), Some("searchIssues"));
+        assert!(o.title.is_none());
+        assert_eq!(o.examples.len(), 1);
+        assert!(
+            serde_json::to_value(&cfg).unwrap()["tools"]["search_issues_v2"]
+                .get("notes")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forward_auth() {
+        let cfg: ServerConfig = serde_json::from_value(json!({
+            "name": "github",
+            "url": "https://mcp.example.com/mcp",
+            "auth": { "type": "forward" }
+        }))
+        .unwrap();
+        let ServerConnection::Http(http) = &cfg.connection else {
+            panic!("expected an HTTP server");
+        };
+        assert_eq!(
+            cfg.forwarded_header().as_deref(),
+            Some("X-Upstream-Token-github")
+        );
+        assert!(cfg.auth_headers(http, false).await.is_err());
+
+        let forwarded = cfg.with_forwarded_credential("Bearer user-token");
+        let ServerConnection::Http(http) = &forwarded.connection else {
+            panic!("expected an HTTP server");
+        };
+        let headers = forwarded.auth_headers(http, false).await.unwrap();
+        assert_eq!(headers[http::header::AUTHORIZATION], "Bearer user-token");
+        assert!(headers[http::header::AUTHORIZATION].is_sensitive());
+
+        let stdio = ServerConfig::new_stdio("local".into(), "npx".into(), vec![]);
+        assert!(stdio.forwarded_header().is_none());
+    }
+
+    #[test]
+    fn test_deserialize_sse_server() {
+        let cfg: ServerConfig = serde_json::from_value(json!({
+            "name": "legacy",
+            "url": "https://mcp.example.com/sse",
+            "transport": "sse"
+        }))
+        .unwrap();
+
+        assert_eq!(cfg.transport(), Some(HttpTransport::Sse));
+        assert_eq!(serde_json::to_value(&cfg).unwrap()["transport"], "sse");
+    }
+
+    #[test]
+    fn test_parse_http_transport() {
+        assert_eq!(
+            "streamable-http".parse::<HttpTransport>().unwrap(),
+            HttpTransport::StreamableHttp
+        );
+        assert_eq!("sse".parse::<HttpTransport>().unwrap(), HttpTransport::Sse);
+        assert!("websocket".parse::<HttpTransport>().is_err());
+    }
+
+    #[test]
+    fn test_deserialize_stdio_server() {
+        let cfg: ServerConfig = serde_json::from_value(json!({
+            "name": "fs",
+            "command": "npx",
+            "args": ["-y", "@modelcontextprotocol/server-filesystem", "."],
+            "env": { "API_KEY": "${keychain:fs_key}" },
+            "cwd": "/tmp"
+        }))
+        .unwrap();
+
+        let ServerConnection::Stdio(stdio) = &cfg.connection else {
+            panic!("expected stdio connection");
+        };
+        assert_eq!(stdio.command, "npx");
+        assert_eq!(stdio.args.len(), 3);
+        assert_eq!(stdio.env["API_KEY"].to_string(), "${keychain:fs_key}");
+        assert_eq!(stdio.cwd, Some(Utf8PathBuf::from("/tmp")));
+        assert!(cfg.url().is_none());
+        assert_eq!(
+            cfg.connection.to_string(),
+            "npx -y @modelcontextprotocol/server-filesystem ."
+        );
+    }
+
+    #[test]
+    fn test_serialize_stdio_server_roundtrip() {
+        let cfg = ServerConfig::new_stdio("local".into(), "uvx".into(), vec!["mcp-time".into()]);
+        let val = serde_json::to_value(&cfg).unwrap();
+        assert_eq!(
+            val,
+            json!({ "name": "local", "command": "uvx", "args": ["mcp-time"] })
+        );
+
+        let parsed: ServerConfig = serde_json::from_value(val).unwrap();
+        assert!(matches!(parsed.connection, ServerConnection::Stdio(_)));
+    }
+
+    #[test]
+    fn test_deserialize_server_missing_connection() {
+        let res = serde_json::from_value::<ServerConfig>(json!({ "name": "broken" }));
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connect_stdio_spawn_failure() {
+        let cfg = ServerConfig::new_stdio(
+            "missing".into(),
+            "pctx-command-that-does-not-exist".into(),
+            vec![],
+        );
+        let err = cfg.connect().await.unwrap_err();
+        assert!(matches!(err, McpConnectionError::Failed(msg) if msg.contains("Failed to spawn")));
+    }
+
+    #[test]
+    fn test_stderr_level() {
+        assert_eq!(stderr_level("Server listening on stdio"), log::Level::Info);
+        assert_eq!(stderr_level("ERROR: token expired"), log::Level::Warn);
+        assert_eq!(stderr_level("Warning: deprecated flag"), log::Level::Warn);
+    }
+}
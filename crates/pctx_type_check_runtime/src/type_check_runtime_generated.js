@@ -29,6 +29,7 @@ declare namespace Deno {
 interface MCPServerConfig {
   name: string;
   url: string;
+  transport?: "streamable-http" | "sse";
   auth?: any;
 }
 
@@ -41,6 +42,52 @@ interface MCPToolCall {
 declare function registerMCP(config: MCPServerConfig): void;
 declare function callMCPTool<T = any>(call: MCPToolCall): Promise<T>;
 
+/** Error thrown when an upstream MCP tool call fails */
+declare class MCPToolError extends Error {
+  /** Name of the registered server */
+  server: string;
+  /** Name of the tool that was called */
+  tool: string;
+  /** Content blocks the server returned along with the error */
+  content: MCPContent[];
+  /** Whether the call failed to reach the server rather than being rejected by it */
+  isTransportError: boolean;
+  /** JSON-RPC error code, if the server rejected the call */
+  code?: number;
+}
+
+interface MCPResourceContents {
+  uri: string;
+  mimeType?: string;
+  text?: string;
+  blob?: string;
+}
+
+/** Content block of a tool result that is not a single text or JSON value */
+type MCPContent =
+  | { type: "text"; text: string }
+  | { type: "image"; data: string; mimeType: string }
+  | { type: "audio"; data: string; mimeType: string }
+  | ({ type: "resource" } & MCPResourceContents)
+  | { type: "resource_link"; uri: string; name: string; mimeType?: string };
+
+interface MCPPromptMessage {
+  role: "user" | "assistant";
+  content: any;
+}
+
+interface MCPPrompt {
+  description?: string;
+  messages: MCPPromptMessage[];
+}
+
+declare function readMCPResource(request: { name: string; uri: string }): Promise<MCPResourceContents[]>;
+declare function getMCPPrompt(request: {
+  name: string;
+  prompt: string;
+  arguments?: Record<string, string>;
+}): Promise<MCPPrompt>;
+
 declare const REGISTRY: {
   has(name: string): boolean;
   get(name: string): MCPServerConfig | undefined;